@@ -0,0 +1,324 @@
+use config::ProtectionPolicy;
+use github::{
+    BranchProtection, BranchProtectionUpdate, GithubRepo, RequiredReviewsUpdate, RequiredStatusChecks, RestrictionsUpdate,
+};
+use host::ReleaseHost;
+
+#[derive(Serialize, Debug)]
+pub struct ProtectionAudit {
+    pub repo: String,
+    pub violations: Vec<String>,
+    pub fixed: bool,
+    // There's no release branch to protect, so nothing was checked or fixed.
+    pub missing_branch: bool,
+}
+
+/// Everything about a release branch's protection that falls short of the policy.
+pub fn protection_violations(
+    policy: &ProtectionPolicy,
+    protection: Option<&BranchProtection>,
+) -> Vec<String> {
+    let protection = match protection {
+        Some(protection) => protection,
+        None => return vec!["release branch isn't protected".to_owned()],
+    };
+    let mut violations = Vec::new();
+
+    if let Some(wanted_reviews) = policy.required_approving_reviews {
+        match protection.required_pull_request_reviews {
+            None if wanted_reviews > 0 => violations.push("pull request reviews aren't required".to_owned()),
+            Some(ref reviews) if reviews.required_approving_review_count < wanted_reviews => {
+                violations.push(format!(
+                    "requires {} approving reviews, policy wants {}",
+                    reviews.required_approving_review_count, wanted_reviews
+                ))
+            }
+            _ => (),
+        }
+    }
+
+    if let Some(ref wanted_checks) = policy.required_status_checks {
+        let contexts = match protection.required_status_checks {
+            Some(ref checks) => checks.contexts.clone(),
+            None => Vec::new(),
+        };
+        for check in wanted_checks {
+            if !contexts.contains(check) {
+                violations.push(format!("status check {} isn't required", check));
+            }
+        }
+    }
+
+    if policy.strict_status_checks {
+        let strict = match protection.required_status_checks {
+            Some(ref checks) => checks.strict,
+            None => false,
+        };
+        if !strict {
+            violations.push("branches don't have to be up to date before merging".to_owned());
+        }
+    }
+
+    if policy.enforce_admins {
+        let enforced = match protection.enforce_admins {
+            Some(ref admins) => admins.enabled,
+            None => false,
+        };
+        if !enforced {
+            violations.push("admins aren't held to the protection rules".to_owned());
+        }
+    }
+
+    violations
+}
+
+// Setting protection replaces all of it, so keep whatever the branch already has that goes beyond the policy.
+pub fn protection_for_policy(
+    policy: &ProtectionPolicy,
+    existing: Option<&BranchProtection>,
+) -> BranchProtectionUpdate {
+    let empty = BranchProtection::default();
+    let existing = existing.unwrap_or(&empty);
+
+    let mut contexts = match existing.required_status_checks {
+        Some(ref checks) => checks.contexts.clone(),
+        None => Vec::new(),
+    };
+    if let Some(ref wanted_checks) = policy.required_status_checks {
+        for check in wanted_checks {
+            if !contexts.contains(check) {
+                contexts.push(check.clone());
+            }
+        }
+    }
+    let existing_strict = match existing.required_status_checks {
+        Some(ref checks) => checks.strict,
+        None => false,
+    };
+    let required_status_checks = if contexts.is_empty() && !policy.strict_status_checks && !existing_strict {
+        None
+    } else {
+        Some(RequiredStatusChecks {
+            strict: existing_strict || policy.strict_status_checks,
+            contexts,
+        })
+    };
+
+    let existing_reviews = existing.required_pull_request_reviews.as_ref();
+    let required_pull_request_reviews = match (existing_reviews, policy.required_approving_reviews) {
+        (None, None) => None,
+        (existing_reviews, wanted_reviews) => Some(RequiredReviewsUpdate {
+            required_approving_review_count: existing_reviews
+                .map_or(0, |reviews| reviews.required_approving_review_count)
+                .max(wanted_reviews.unwrap_or(0)),
+            dismiss_stale_reviews: existing_reviews
+                .map(|reviews| reviews.dismiss_stale_reviews)
+                .unwrap_or(false),
+            require_code_owner_reviews: existing_reviews
+                .map(|reviews| reviews.require_code_owner_reviews)
+                .unwrap_or(false),
+            dismissal_restrictions: existing_reviews
+                .and_then(|reviews| reviews.dismissal_restrictions.as_ref())
+                .map(RestrictionsUpdate::from),
+        }),
+    };
+
+    let existing_admins = match existing.enforce_admins {
+        Some(ref admins) => admins.enabled,
+        None => false,
+    };
+
+    BranchProtectionUpdate {
+        required_status_checks,
+        enforce_admins: existing_admins || policy.enforce_admins,
+        required_pull_request_reviews,
+        restrictions: existing.restrictions.as_ref().map(RestrictionsUpdate::from),
+    }
+}
+
+pub fn audit_release_protection(
+    repos: &[GithubRepo],
+    policy: &ProtectionPolicy,
+    fix: bool,
+    dryrun: bool,
+    host: &dyn ReleaseHost,
+) -> Vec<ProtectionAudit> {
+    let mut audits = Vec::new();
+    for repo in repos {
+        let protection = match host.branch_protection(repo, "release") {
+            Ok(protection) => protection,
+            Err(e) => {
+                // Without a release branch there's nothing to protect, and setting protection would fail.
+                if host.branch_exists(repo, "release") == Ok(false) {
+                    audits.push(ProtectionAudit {
+                        repo: repo.name.clone(),
+                        violations: Vec::new(),
+                        fixed: false,
+                        missing_branch: true,
+                    });
+                } else {
+                    eprintln!("Couldn't check release branch protection for {}: {}", repo.name, e);
+                }
+                continue;
+            }
+        };
+        let violations = protection_violations(policy, protection.as_ref());
+        if violations.is_empty() {
+            continue;
+        }
+        let mut fixed = false;
+        if fix && !dryrun {
+            let update = protection_for_policy(policy, protection.as_ref());
+            match host.set_branch_protection(repo, "release", &update) {
+                Ok(_) => fixed = true,
+                Err(e) => eprintln!("Couldn't fix release branch protection for {}: {}", repo.name, e),
+            }
+        }
+        audits.push(ProtectionAudit {
+            repo: repo.name.clone(),
+            violations,
+            fixed,
+            missing_branch: false,
+        });
+    }
+    audits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use github::{EnforceAdmins, RequiredReviews, RestrictedSlug, RestrictedUser, Restrictions};
+
+    fn policy() -> ProtectionPolicy {
+        ProtectionPolicy {
+            required_approving_reviews: Some(2),
+            required_status_checks: Some(vec!["ci".to_owned()]),
+            strict_status_checks: false,
+            enforce_admins: true,
+        }
+    }
+
+    #[test]
+    fn unprotected_branch_is_one_violation() {
+        assert_eq!(
+            vec!["release branch isn't protected".to_owned()],
+            protection_violations(&policy(), None)
+        );
+    }
+
+    #[test]
+    fn finds_each_shortfall() {
+        let protection = BranchProtection {
+            required_status_checks: Some(RequiredStatusChecks {
+                strict: false,
+                contexts: vec!["lint".to_owned()],
+            }),
+            enforce_admins: Some(EnforceAdmins { enabled: false }),
+            required_pull_request_reviews: Some(RequiredReviews {
+                required_approving_review_count: 1,
+                ..RequiredReviews::default()
+            }),
+            restrictions: None,
+        };
+        assert_eq!(
+            vec![
+                "requires 1 approving reviews, policy wants 2".to_owned(),
+                "status check ci isn't required".to_owned(),
+                "admins aren't held to the protection rules".to_owned(),
+            ],
+            protection_violations(&policy(), Some(&protection))
+        );
+    }
+
+    #[test]
+    fn compliant_branch_has_no_violations() {
+        let protection = BranchProtection {
+            required_status_checks: Some(RequiredStatusChecks {
+                strict: true,
+                contexts: vec!["ci".to_owned()],
+            }),
+            enforce_admins: Some(EnforceAdmins { enabled: true }),
+            required_pull_request_reviews: Some(RequiredReviews {
+                required_approving_review_count: 3,
+                ..RequiredReviews::default()
+            }),
+            restrictions: None,
+        };
+        assert!(protection_violations(&policy(), Some(&protection)).is_empty());
+    }
+
+    #[test]
+    fn fixing_keeps_stricter_existing_settings() {
+        let protection = BranchProtection {
+            required_status_checks: Some(RequiredStatusChecks {
+                strict: true,
+                contexts: vec!["lint".to_owned()],
+            }),
+            enforce_admins: None,
+            required_pull_request_reviews: Some(RequiredReviews {
+                required_approving_review_count: 3,
+                ..RequiredReviews::default()
+            }),
+            restrictions: None,
+        };
+        let update = protection_for_policy(&policy(), Some(&protection));
+        let checks = update.required_status_checks.unwrap();
+        assert!(checks.strict);
+        assert_eq!(vec!["lint".to_owned(), "ci".to_owned()], checks.contexts);
+        assert_eq!(
+            3,
+            update
+                .required_pull_request_reviews
+                .unwrap()
+                .required_approving_review_count
+        );
+        assert!(update.enforce_admins);
+    }
+
+    #[test]
+    fn fixing_keeps_push_restrictions_and_review_settings() {
+        let protection = BranchProtection {
+            required_pull_request_reviews: Some(RequiredReviews {
+                required_approving_review_count: 1,
+                dismiss_stale_reviews: true,
+                require_code_owner_reviews: true,
+                dismissal_restrictions: Some(Restrictions {
+                    users: vec![RestrictedUser {
+                        login: "release-manager".to_owned(),
+                    }],
+                    ..Restrictions::default()
+                }),
+            }),
+            restrictions: Some(Restrictions {
+                users: vec![RestrictedUser {
+                    login: "release-bot".to_owned(),
+                }],
+                teams: vec![RestrictedSlug {
+                    slug: "maintainers".to_owned(),
+                }],
+                apps: vec![RestrictedSlug {
+                    slug: "deployer".to_owned(),
+                }],
+            }),
+            ..BranchProtection::default()
+        };
+        let update = protection_for_policy(&policy(), Some(&protection));
+
+        assert_eq!(
+            Some(RestrictionsUpdate {
+                users: vec!["release-bot".to_owned()],
+                teams: vec!["maintainers".to_owned()],
+                apps: vec!["deployer".to_owned()],
+            }),
+            update.restrictions
+        );
+        let reviews = update.required_pull_request_reviews.unwrap();
+        assert_eq!(2, reviews.required_approving_review_count);
+        assert!(reviews.dismiss_stale_reviews);
+        assert!(reviews.require_code_owner_reviews);
+        assert_eq!(
+            vec!["release-manager".to_owned()],
+            reviews.dismissal_restrictions.unwrap().users
+        );
+    }
+}
@@ -0,0 +1,480 @@
+extern crate reqwest;
+extern crate serde_json;
+
+use self::reqwest::header::{HeaderMap, AUTHORIZATION, USER_AGENT};
+use self::reqwest::Url;
+use serde::de::DeserializeOwned;
+
+
+use forge::{branch_protection_unsupported, pr_comments_unsupported, pr_labels_unsupported, BITBUCKET};
+use github::{
+    ActualCommitInPR, BranchProtection, BranchProtectionUpdate, ChangedFile, Commit, CommitInPR,
+    CompareCommitsResponse, GithubRepo, GithubTag,
+};
+use host::ReleaseHost;
+use http::HttpSettings;
+
+static USERAGENT: &str = "release-party-br";
+
+// How many items to ask for per page. Bitbucket's default cap is 1000.
+const PAGE_LIMIT: &str = "100";
+
+// One page of a Bitbucket list: keep asking from `nextPageStart` until `isLastPage`.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Page<T> {
+    values: Vec<T>,
+    #[serde(default = "last_page")]
+    is_last_page: bool,
+    #[serde(default)]
+    next_page_start: Option<u64>,
+}
+
+fn last_page() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug)]
+struct BitbucketRepo {
+    slug: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct BitbucketCommit {
+    id: String,
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Change {
+    path: ChangePath,
+    #[serde(rename = "type")]
+    change_type: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ChangePath {
+    to_string: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PullRequest {
+    id: u64,
+    version: u64,
+    title: String,
+    #[serde(default)]
+    description: Option<String>,
+    from_ref: Ref,
+    to_ref: Ref,
+    links: Links,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct Ref {
+    id: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Links {
+    #[serde(rename = "self")]
+    self_links: Vec<Link>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Link {
+    href: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Tag {
+    display_id: String,
+    latest_commit: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Branch {
+    display_id: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct NewPullRequest<'a> {
+    title: &'a str,
+    from_ref: Ref,
+    to_ref: Ref,
+}
+
+#[derive(Serialize, Debug)]
+struct PullRequestUpdate<'a> {
+    version: u64,
+    title: &'a str,
+    description: &'a str,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct NewBranch<'a> {
+    name: &'a str,
+    start_point: &'a str,
+}
+
+/// Talks to the Bitbucket Server and Data Center REST API (`/rest/api/1.0`): a project's repos stand in for an org's.
+pub struct BitbucketClient {
+    client: reqwest::Client,
+}
+
+/// A client that sends a Bitbucket HTTP access token and our user agent with every request.
+pub fn client_for_token(token: &str, http: &HttpSettings) -> reqwest::Client {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        USER_AGENT,
+        USERAGENT.parse().expect("useragent should be a string"),
+    );
+    headers.insert(
+        AUTHORIZATION,
+        format!("Bearer {}", token).parse().expect("token should be a string"),
+    );
+    match http.builder().default_headers(headers).build() {
+        Ok(new_client) => new_client,
+        Err(e) => panic!("Couldn't create new reqwest client: {}", e),
+    }
+}
+
+/// The repos URL for a project, by its key.
+pub fn project_repos_url(api_url: &str, project: &str) -> String {
+    format!("{}/projects/{}/repos", api_url, project)
+}
+
+fn branch_ref(branch: &str) -> String {
+    format!("refs/heads/{}", branch)
+}
+
+fn commit_in_pr(commit: BitbucketCommit) -> CommitInPR {
+    CommitInPR {
+        sha: commit.id,
+        actual_commit: ActualCommitInPR {
+            message: commit.message,
+        },
+    }
+}
+
+fn changed_file(change: Change) -> ChangedFile {
+    let status = match change.change_type.as_str() {
+        "ADD" | "COPY" => "added",
+        "DELETE" => "removed",
+        "MOVE" => "renamed",
+        _ => "modified",
+    };
+    ChangedFile {
+        filename: change.path.to_string,
+        status: status.to_owned(),
+        additions: 0,
+        deletions: 0,
+    }
+}
+
+fn compare_response(ahead: Vec<BitbucketCommit>, behind_by: usize, changes: Vec<Change>) -> CompareCommitsResponse {
+    let ahead_by = ahead.len() as i32;
+    let behind_by = behind_by as i32;
+    let status = match (ahead_by, behind_by) {
+        (0, 0) => "identical",
+        (_, 0) => "ahead",
+        (0, _) => "behind",
+        _ => "diverged",
+    };
+    CompareCommitsResponse {
+        status: status.to_owned(),
+        ahead_by,
+        behind_by,
+        total_commits: ahead_by,
+        commits: ahead.into_iter().map(commit_in_pr).collect(),
+        files: changes.into_iter().map(changed_file).collect(),
+    }
+}
+
+fn pr_link(pull: &PullRequest) -> Option<String> {
+    pull.links.self_links.first().map(|link| link.href.clone())
+}
+
+impl BitbucketClient {
+    pub fn new(client: reqwest::Client) -> BitbucketClient {
+        BitbucketClient { client }
+    }
+
+    // Every page of a list, following `nextPageStart` until `isLastPage`.
+    fn get_all<T: DeserializeOwned>(&self, url: &str, params: &[(&str, &str)], what: &str) -> Result<Vec<T>, String> {
+        let mut items = Vec::new();
+        let mut start = "0".to_owned();
+        loop {
+            let mut page_params = params.to_vec();
+            page_params.push(("limit", PAGE_LIMIT));
+            page_params.push(("start", &start));
+            let page_url = match Url::parse_with_params(url, &page_params) {
+                Ok(page_url) => page_url,
+                Err(e) => return Err(format!("Couldn't parse uri {:?} : {:?}", url, e)),
+            };
+            let mut page: Page<T> = BITBUCKET.send_for_json(self.client.get(page_url), what)?;
+            items.append(&mut page.values);
+            match page.next_page_start {
+                Some(next) if !page.is_last_page => start = next.to_string(),
+                _ => return Ok(items),
+            }
+        }
+    }
+
+    // The commits reachable from `from` but not from `to`, newest first.
+    fn commits_between(&self, repo: &GithubRepo, from: &str, to: &str) -> Result<Vec<BitbucketCommit>, String> {
+        self.get_all(
+            &format!("{}/compare/commits", repo.url),
+            &[("from", from), ("to", to)],
+            &format!("commits on {} that {} doesn't have in {}", from, to, repo.name),
+        )
+    }
+
+    fn pull_request(&self, repo: &GithubRepo, pr_number: &str) -> Result<PullRequest, String> {
+        let url = format!("{}/pull-requests/{}", repo.url, pr_number);
+        BITBUCKET.send_for_json(self.client.get(&url), &format!("pull request {} of {}", pr_number, repo.name))
+    }
+}
+
+impl ReleaseHost for BitbucketClient {
+    fn list_repos(&self, org_url: &str) -> Result<Vec<GithubRepo>, String> {
+        let bitbucket_repos: Vec<BitbucketRepo> = self.get_all(org_url, &[], "the project's repos")?;
+        let repos = bitbucket_repos
+            .into_iter()
+            .map(|r| GithubRepo::new(&r.slug, &format!("{}/{}", org_url.trim_end_matches('/'), r.slug)))
+            .collect::<Vec<GithubRepo>>();
+        eprintln!("Number of repos to check: {:?}", repos.len());
+        Ok(repos)
+    }
+
+    fn compare(&self, repo: &GithubRepo, base: &str, head: &str) -> Result<CompareCommitsResponse, String> {
+        let ahead = self.commits_between(repo, head, base)?;
+        let behind = self.commits_between(repo, base, head)?;
+        let changes = self.get_all(
+            &format!("{}/compare/changes", repo.url),
+            &[("from", head), ("to", base)],
+            &format!("files changed on {} since {} in {}", head, base, repo.name),
+        )?;
+        Ok(compare_response(ahead, behind.len(), changes))
+    }
+
+    fn find_pr(&self, repo: &GithubRepo, head: &str, base: &str) -> Option<String> {
+        let head_ref = branch_ref(head);
+        let base_ref = branch_ref(base);
+        let pulls: Vec<PullRequest> = match self.get_all(
+            &format!("{}/pull-requests", repo.url),
+            &[("state", "OPEN"), ("direction", "OUTGOING"), ("at", &head_ref)],
+            &format!("pull requests of {}", repo.name),
+        ) {
+            Ok(pulls) => pulls,
+            Err(e) => {
+                eprintln!("{}", e);
+                return None;
+            }
+        };
+        pulls
+            .iter()
+            .find(|p| p.from_ref.id == head_ref && p.to_ref.id == base_ref)
+            .and_then(pr_link)
+    }
+
+    fn create_pr(&self, repo: &GithubRepo, title: &str, head: &str, base: &str) -> Result<String, String> {
+        let new_pull = NewPullRequest {
+            title,
+            from_ref: Ref { id: branch_ref(head) },
+            to_ref: Ref { id: branch_ref(base) },
+        };
+        // Bitbucket answers 409 Conflict when there's one open already.
+        let url = format!("{}/pull-requests", repo.url);
+        let pull: PullRequest =
+            BITBUCKET.send_for_json(self.client.post(&url).json(&new_pull), &format!("a new pull request for {}", repo.name))?;
+        match pr_link(&pull) {
+            Some(link) => Ok(link),
+            None => Err(format!("Bitbucket didn't link to pull request {} of {}", pull.id, repo.name)),
+        }
+    }
+
+    fn pr_commits(&self, repo: &GithubRepo, pr_number: &str) -> Result<Vec<CommitInPR>, String> {
+        let commits: Vec<BitbucketCommit> = self.get_all(
+            &format!("{}/pull-requests/{}/commits", repo.url, pr_number),
+            &[],
+            &format!("commits of pull request {}", pr_number),
+        )?;
+        Ok(commits.into_iter().map(commit_in_pr).collect())
+    }
+
+    fn pr_body(&self, repo: &GithubRepo, pr_number: &str) -> Result<Option<String>, String> {
+        Ok(self.pull_request(repo, pr_number)?.description)
+    }
+
+    // Bitbucket wants the version we last saw, so fetch the pull request first.
+    fn update_pr_body(&self, repo: &GithubRepo, pr_number: &str, body: &str) -> Result<(), String> {
+        let pull = self.pull_request(repo, pr_number)?;
+        let update = PullRequestUpdate {
+            version: pull.version,
+            title: &pull.title,
+            description: body,
+        };
+        let url = format!("{}/pull-requests/{}", repo.url, pr_number);
+        BITBUCKET.send(
+            self.client.put(&url).json(&update),
+            &format!("updating the description of pull request {}", pr_number),
+        )
+        .map(|_| ())
+    }
+
+    fn pr_comments(&self, _repo: &GithubRepo, _pr_number: &str) -> Result<Vec<String>, String> {
+        Err(pr_comments_unsupported())
+    }
+
+    fn comment_on_pr(&self, _repo: &GithubRepo, _pr_number: &str, _body: &str) -> Result<(), String> {
+        Err(pr_comments_unsupported())
+    }
+
+    fn pr_labels(&self, _repo: &GithubRepo, _pr_number: &str) -> Result<Vec<String>, String> {
+        Err(pr_labels_unsupported())
+    }
+
+    fn add_pr_label(&self, _repo: &GithubRepo, _pr_number: &str, _label: &str) -> Result<(), String> {
+        Err(pr_labels_unsupported())
+    }
+
+    fn tags(&self, repo: &GithubRepo) -> Vec<GithubTag> {
+        match self.get_all::<Tag>(&format!("{}/tags", repo.url), &[], &format!("tags of {}", repo.name)) {
+            Ok(tags) => tags
+                .into_iter()
+                .map(|tag| GithubTag {
+                    name: tag.display_id,
+                    commit: Commit { sha: tag.latest_commit },
+                })
+                .collect(),
+            Err(e) => {
+                eprintln!("{}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    fn branch_exists(&self, repo: &GithubRepo, branch: &str) -> Result<bool, String> {
+        let branches: Vec<Branch> = self.get_all(
+            &format!("{}/branches", repo.url),
+            &[("filterText", branch)],
+            &format!("branch {}", branch),
+        )?;
+        Ok(branches.iter().any(|b| b.display_id == branch))
+    }
+
+    fn commit_sha(&self, repo: &GithubRepo, reference: &str) -> Result<String, String> {
+        let url = match Url::parse_with_params(
+            &format!("{}/commits", repo.url),
+            &[("until", reference), ("limit", "1")],
+        ) {
+            Ok(url) => url,
+            Err(e) => return Err(format!("Couldn't create url for commits: {}", e)),
+        };
+        let page: Page<BitbucketCommit> = BITBUCKET.send_for_json(self.client.get(url), reference)?;
+        match page.values.into_iter().next() {
+            Some(commit) => Ok(commit.id),
+            None => Err(format!("No commits on {}", reference)),
+        }
+    }
+
+    // There's no total to jump to the last page with, so walk the whole history.
+    fn first_commit(&self, repo: &GithubRepo, branch: &str) -> Result<String, String> {
+        let mut commits: Vec<BitbucketCommit> = self.get_all(
+            &format!("{}/commits", repo.url),
+            &[("until", branch)],
+            &format!("commits on {}", branch),
+        )?;
+        match commits.pop() {
+            Some(commit) => Ok(commit.id),
+            None => Err(format!("No commits on {}", branch)),
+        }
+    }
+
+    fn create_branch(&self, repo: &GithubRepo, branch: &str, sha: &str) -> Result<(), String> {
+        let new_branch = NewBranch {
+            name: branch,
+            start_point: sha,
+        };
+        let url = format!("{}/branches", repo.url);
+        BITBUCKET.send(
+            self.client.post(&url).json(&new_branch),
+            &format!("creating branch {}", branch),
+        )
+        .map(|_| ())
+    }
+
+    fn branch_protection(&self, _repo: &GithubRepo, _branch: &str) -> Result<Option<BranchProtection>, String> {
+        Err(branch_protection_unsupported())
+    }
+
+    fn set_branch_protection(
+        &self,
+        _repo: &GithubRepo,
+        _branch: &str,
+        _protection: &BranchProtectionUpdate,
+    ) -> Result<(), String> {
+        Err(branch_protection_unsupported())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_follow_next_page_start() {
+        let page: Page<BitbucketRepo> = serde_json::from_str(
+            r#"{"size": 1, "limit": 1, "isLastPage": false, "start": 0, "nextPageStart": 1, "values": [{"slug": "moe"}]}"#,
+        )
+        .unwrap();
+        assert_eq!((false, Some(1)), (page.is_last_page, page.next_page_start));
+
+        let last: Page<BitbucketRepo> =
+            serde_json::from_str(r#"{"size": 0, "limit": 1, "isLastPage": true, "start": 1, "values": []}"#).unwrap();
+        assert_eq!((true, None), (last.is_last_page, last.next_page_start));
+    }
+
+    #[test]
+    fn pull_requests_link_to_themselves() {
+        let pull: PullRequest = serde_json::from_str(
+            r#"{
+                "id": 7, "version": 3, "title": "automated release partay", "description": null,
+                "fromRef": {"id": "refs/heads/master"}, "toRef": {"id": "refs/heads/release"},
+                "links": {"self": [{"href": "https://bitbucket.example.com/projects/PARTY/repos/moe/pull-requests/7"}]}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Some("https://bitbucket.example.com/projects/PARTY/repos/moe/pull-requests/7".to_owned()),
+            pr_link(&pull)
+        );
+    }
+
+    #[test]
+    fn compares_become_github_shaped() {
+        let ahead = vec![BitbucketCommit {
+            id: "abc".to_owned(),
+            message: "feat: a page".to_owned(),
+        }];
+        let changes = vec![Change {
+            path: ChangePath {
+                to_string: "src/page.rs".to_owned(),
+            },
+            change_type: "ADD".to_owned(),
+        }];
+
+        let compare = compare_response(ahead, 2, changes);
+
+        assert_eq!("diverged", compare.status);
+        assert_eq!((1, 2), (compare.ahead_by, compare.behind_by));
+        assert_eq!("added", compare.files[0].status);
+    }
+}
@@ -0,0 +1,292 @@
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Settings from `ignoredrepos.toml`, looked for in the current directory and then as
+/// `~/.ignoredrepos.toml`.
+#[derive(Deserialize, Debug, Default)]
+pub struct Config {
+    pub ignore: Option<Vec<String>>,
+    pub protection: Option<ProtectionPolicy>,
+    pub webhook: Option<WebhookConfig>,
+    pub email: Option<EmailConfig>,
+    pub jira: Option<JiraConfig>,
+    pub http: Option<HttpConfig>,
+}
+
+/// What protection every repo's release branch should have, checked by the `audit` subcommand.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ProtectionPolicy {
+    pub required_approving_reviews: Option<u32>,
+    pub required_status_checks: Option<Vec<String>>,
+    #[serde(default)]
+    pub strict_status_checks: bool,
+    #[serde(default)]
+    pub enforce_admins: bool,
+}
+
+/// Where to post the release PRs after a party run, see `notify`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    // With `{count}`, `{prs}` and `{mentions}` filled in. `notify::DEFAULT_TEMPLATE` if not set.
+    pub template: Option<String>,
+    #[serde(default)]
+    pub mentions: Vec<String>,
+}
+
+/// The shape of the JSON a webhook expects.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    Slack,
+    Teams,
+    Json,
+}
+
+// Not derived: `#[default]` on an enum variant needs Rust 1.62.
+#[allow(clippy::derivable_impls)]
+impl Default for WebhookFormat {
+    fn default() -> WebhookFormat {
+        WebhookFormat::Slack
+    }
+}
+
+/// Who to email the release PRs to after a party run, see `notify`. The SMTP username and password
+/// come from `RP_SMTPUSERNAME` and `RP_SMTPPASSWORD`, not the file.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct EmailConfig {
+    pub host: String,
+    #[serde(default = "default_smtp_port")]
+    pub port: u16,
+    // Upgrade the connection with STARTTLS, refusing servers that don't offer it.
+    #[serde(default = "default_starttls")]
+    pub starttls: bool,
+    pub from: String,
+    pub to: Vec<String>,
+    // With `{count}` filled in. `notify::DEFAULT_SUBJECT` if not set.
+    pub subject: Option<String>,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_starttls() -> bool {
+    true
+}
+
+/// Jira issue keys to pick out of release PR commits, and what to do with the issues, see `jira`.
+/// The token comes from `RP_JIRATOKEN`, with `RP_JIRAUSERNAME` for basic auth.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct JiraConfig {
+    // `jira::DEFAULT_KEY_PATTERN` if not set.
+    pub key_pattern: Option<String>,
+    // Where Jira is, like https://example.atlassian.net. Without it keys are only listed.
+    pub url: Option<String>,
+    #[serde(default)]
+    pub comment: bool,
+    // A transition name or id to move the issues through.
+    pub transition: Option<String>,
+}
+
+/// How to reach the forge, Jira and webhooks, see `http`. The CLI options of the same names win over these.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct HttpConfig {
+    // Seconds to wait for a connection.
+    pub connect_timeout: Option<u64>,
+    // Seconds to wait on each read or write, reqwest's 30 if not set.
+    pub timeout: Option<u64>,
+    // Every request goes through this proxy, like http://proxy.example.com:3128.
+    pub proxy: Option<String>,
+    // A PEM file of root certificates to trust as well as the system's, for GitHub Enterprise behind a private CA.
+    pub ca_bundle: Option<String>,
+}
+
+impl Config {
+    pub fn ignored_repos(&self) -> Vec<String> {
+        match self.ignore {
+            Some(ref repos_to_ignore) => repos_to_ignore.clone(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// The config, or the default when there's no ignoredrepos.toml. A file that's there but can't be read or
+/// parsed is an error: carrying on without it would release the repos it says to ignore.
+pub fn load_config() -> Result<Config, String> {
+    let hfi = match dirs::home_dir() {
+        Some(path) => {
+            if Path::new(&path).join(".ignoredrepos.toml").exists() {
+                Some(Path::new(&path).join(".ignoredrepos.toml"))
+            } else {
+                None
+            }
+        },
+        None => None,
+    };
+
+    let lfi = match Path::new("ignoredrepos.toml").exists() {
+        true  => Some(Path::new("ignoredrepos.toml").to_path_buf()),
+        false => None,
+    };
+
+    let fi = match (lfi, hfi) {
+        (Some(a), _) => a,
+        (None, Some(b)) => b,
+        (_, _) => {eprintln!("The ignoredrepos.toml file not found"); return Ok(Config::default())},
+    };
+
+    read_config(&fi)
+}
+
+pub fn read_config(fi: &Path) -> Result<Config, String> {
+    let mut f = match File::open(fi) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(format!("Couldn't open {}: {}", fi.display(), e)),
+    };
+
+    eprintln!(
+        "Found ignoredrepos.toml file at {:#?}",
+        fi
+    );
+
+    let mut buffer = String::new();
+    if let Err(e) = f.read_to_string(&mut buffer) {
+        return Err(format!("Couldn't read {}: {}", fi.display(), e));
+    }
+
+    toml::from_str(&buffer).map_err(|e| format!("Couldn't parse {}: {}", fi.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn get_ignored_repos_happy_path() {
+        let config_path = env::temp_dir().join("release-party-ignoredrepos-happy-path.toml");
+        fs::write(&config_path, "ignore = [\n    \"calagator\",\n    \"moe\"\n]\n").unwrap();
+
+        let ignored_repositories = vec!["calagator".to_owned(), "moe".to_owned()];
+        assert_eq!(ignored_repositories, read_config(&config_path).unwrap().ignored_repos());
+        fs::remove_file(&config_path).unwrap();
+    }
+
+    #[test]
+    fn missing_config_ignores_nothing() {
+        let config_path = env::temp_dir().join("release-party-ignoredrepos-missing.toml");
+        assert!(read_config(&config_path).unwrap().ignored_repos().is_empty());
+    }
+
+    #[test]
+    fn config_that_doesnt_parse_is_an_error() {
+        let config_path = env::temp_dir().join("release-party-ignoredrepos-typo.toml");
+        fs::write(&config_path, "ignore = [\"calagator\"]\n[webhook]\nurl = https://hooks.example.com\n").unwrap();
+
+        let error = read_config(&config_path).unwrap_err();
+        fs::remove_file(&config_path).unwrap();
+        assert!(error.starts_with("Couldn't parse "), "{}", error);
+    }
+
+    #[test]
+    fn parses_protection_policy() {
+        let config: Config = toml::from_str(
+            r#"
+            ignore = ["moe"]
+
+            [protection]
+            required_approving_reviews = 2
+            required_status_checks = ["continuous-integration/travis-ci"]
+            enforce_admins = true
+            "#,
+        )
+        .unwrap();
+        let policy = config.protection.unwrap();
+        assert_eq!(Some(2), policy.required_approving_reviews);
+        assert_eq!(
+            Some(vec!["continuous-integration/travis-ci".to_owned()]),
+            policy.required_status_checks
+        );
+        assert!(!policy.strict_status_checks);
+        assert!(policy.enforce_admins);
+    }
+
+    #[test]
+    fn parses_webhook() {
+        let config: Config = toml::from_str(
+            r#"
+            [webhook]
+            url = "https://outlook.office.com/webhook/abc"
+            format = "teams"
+            mentions = ["@release-team"]
+            "#,
+        )
+        .unwrap();
+        let webhook = config.webhook.unwrap();
+        assert_eq!(WebhookFormat::Teams, webhook.format);
+        assert_eq!(None, webhook.template);
+        assert_eq!(vec!["@release-team".to_owned()], webhook.mentions);
+
+        let slack: Config = toml::from_str("[webhook]\nurl = \"https://hooks.slack.com/services/T/B/x\"\n").unwrap();
+        assert_eq!(WebhookFormat::Slack, slack.webhook.unwrap().format);
+    }
+
+    #[test]
+    fn parses_email() {
+        let config: Config = toml::from_str(
+            r#"
+            [email]
+            host = "smtp.example.com"
+            from = "release-party@example.com"
+            to = ["cab@example.com"]
+            "#,
+        )
+        .unwrap();
+        let email = config.email.unwrap();
+        assert_eq!(587, email.port);
+        assert!(email.starttls);
+        assert_eq!(vec!["cab@example.com".to_owned()], email.to);
+    }
+
+    #[test]
+    fn parses_jira() {
+        let config: Config = toml::from_str(
+            r#"
+            [jira]
+            key_pattern = "PAY-[0-9]+"
+            url = "https://example.atlassian.net"
+            transition = "Ready for release"
+            "#,
+        )
+        .unwrap();
+        let jira = config.jira.unwrap();
+        assert_eq!(Some("PAY-[0-9]+".to_owned()), jira.key_pattern);
+        assert!(!jira.comment);
+        assert_eq!(Some("Ready for release".to_owned()), jira.transition);
+    }
+
+    #[test]
+    fn parses_http() {
+        let config: Config = toml::from_str(
+            r#"
+            [http]
+            connect_timeout = 5
+            proxy = "http://proxy.example.com:3128"
+            ca_bundle = "/etc/ssl/corp-ca.pem"
+            "#,
+        )
+        .unwrap();
+        let http = config.http.unwrap();
+        assert_eq!(Some(5), http.connect_timeout);
+        assert_eq!(None, http.timeout);
+        assert_eq!(Some("http://proxy.example.com:3128".to_owned()), http.proxy);
+    }
+}
@@ -0,0 +1,293 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use yaml_rust::YamlLoader;
+
+/// A GitHub token and where we found it.
+#[derive(Debug, PartialEq)]
+pub struct FoundToken {
+    pub token: String,
+    pub source: String,
+}
+
+/// Finds a GitHub token for `host` (e.g. `github.com`), trying in order: `token_file`, the `RP_GITHUBTOKEN`
+/// and `GITHUB_TOKEN` environment variables, the `gh` CLI's `hosts.yml` and `git credential fill`.
+pub fn find_token(token_file: Option<&Path>, host: &str) -> Result<FoundToken, String> {
+    let sources = TokenSources {
+        env_vars: &["RP_GITHUBTOKEN", "GITHUB_TOKEN"],
+        gh_cli: true,
+    };
+    find_token_with(token_file, host, &sources, &|name| env::var(name).ok(), &git_credential_fill)
+}
+
+/// Finds a token for a forge other than GitHub: `token_file`, then `env_vars` in order, then `git credential fill`.
+pub fn find_forge_token(token_file: Option<&Path>, host: &str, env_vars: &[&str]) -> Result<FoundToken, String> {
+    let sources = TokenSources { env_vars, gh_cli: false };
+    find_token_with(token_file, host, &sources, &|name| env::var(name).ok(), &git_credential_fill)
+}
+
+// Where to look besides the token file and git's credential helpers.
+struct TokenSources<'a> {
+    env_vars: &'a [&'a str],
+    gh_cli: bool,
+}
+
+fn find_token_with(
+    token_file: Option<&Path>,
+    host: &str,
+    sources: &TokenSources,
+    env_var: &dyn Fn(&str) -> Option<String>,
+    git_credential: &dyn Fn(&str) -> Option<String>,
+) -> Result<FoundToken, String> {
+    // Asked for explicitly, so a file we can't read is an error rather than a reason to look elsewhere.
+    if let Some(path) = token_file {
+        return match fs::read_to_string(path) {
+            Ok(contents) if !contents.trim().is_empty() => Ok(FoundToken {
+                token: contents.trim().to_owned(),
+                source: format!("token file {}", path.display()),
+            }),
+            Ok(_) => Err(format!("The token file {} is empty", path.display())),
+            Err(e) => Err(format!("Couldn't read the token file {}: {}", path.display(), e)),
+        };
+    }
+
+    for name in sources.env_vars {
+        if let Some(token) = env_var(name) {
+            if !token.trim().is_empty() {
+                return Ok(FoundToken {
+                    token: token.trim().to_owned(),
+                    source: format!("{} environment variable", name),
+                });
+            }
+        }
+    }
+
+    if sources.gh_cli {
+        if let Some(hosts_file) = gh_hosts_file(env_var) {
+            if let Ok(hosts) = fs::read_to_string(&hosts_file) {
+                if let Some(token) = token_from_gh_hosts(&hosts, host) {
+                    return Ok(FoundToken {
+                        token,
+                        source: format!("gh CLI config {}", hosts_file.display()),
+                    });
+                }
+            }
+        }
+    }
+
+    if let Some(token) = git_credential(host) {
+        return Ok(FoundToken {
+            token,
+            source: "git credential helper".to_owned(),
+        });
+    }
+
+    if sources.gh_cli {
+        return Err(format!(
+            "No GitHub token found for {}. Use --token-file, set RP_GITHUBTOKEN or GITHUB_TOKEN, log in with `gh auth login` or store one with a git credential helper.",
+            host
+        ));
+    }
+    Err(format!(
+        "No token found for {}. Use --token-file, set {} or store one with a git credential helper.",
+        host,
+        sources.env_vars.join(" or ")
+    ))
+}
+
+// Where `gh` keeps its logins: $GH_CONFIG_DIR, $XDG_CONFIG_HOME/gh or ~/.config/gh.
+fn gh_hosts_file(env_var: &dyn Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    let config_dir = match (env_var("GH_CONFIG_DIR"), env_var("XDG_CONFIG_HOME")) {
+        (Some(gh_dir), _) => PathBuf::from(gh_dir),
+        (None, Some(xdg_dir)) => Path::new(&xdg_dir).join("gh"),
+        (None, None) => dirs::home_dir()?.join(".config").join("gh"),
+    };
+    Some(config_dir.join("hosts.yml"))
+}
+
+fn token_from_gh_hosts(hosts_yml: &str, host: &str) -> Option<String> {
+    let docs = YamlLoader::load_from_str(hosts_yml).ok()?;
+    let token = docs.first()?[host]["oauth_token"].as_str()?;
+    if token.is_empty() {
+        return None;
+    }
+    Some(token.to_owned())
+}
+
+// Asks git's credential helpers, never prompting: a run in CI would hang waiting for input.
+fn git_credential_fill(host: &str) -> Option<String> {
+    let mut git = Command::new("git")
+        .args(["credential", "fill"])
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    {
+        let stdin = git.stdin.as_mut()?;
+        stdin
+            .write_all(format!("protocol=https\nhost={}\n\n", host).as_bytes())
+            .ok()?;
+    }
+    let output = git.wait_with_output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    password_from_credential_output(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn password_from_credential_output(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find(|line| line.starts_with("password="))
+        .map(|line| line["password=".len()..].to_owned())
+        .filter(|password| !password.is_empty())
+}
+
+/// The SMTP username and password from `RP_SMTPUSERNAME` and `RP_SMTPPASSWORD`, when both are set.
+pub fn smtp_credentials() -> Option<(String, String)> {
+    match (env::var("RP_SMTPUSERNAME"), env::var("RP_SMTPPASSWORD")) {
+        (Ok(username), Ok(password)) => Some((username, password)),
+        _ => None,
+    }
+}
+
+/// The Jira token from `RP_JIRATOKEN`, with the username from `RP_JIRAUSERNAME` if set.
+pub fn jira_credentials() -> Option<(Option<String>, String)> {
+    match env::var("RP_JIRATOKEN") {
+        Ok(token) => Some((env::var("RP_JIRAUSERNAME").ok(), token)),
+        Err(_) => None,
+    }
+}
+
+/// The web host credentials are stored under for an API URL: `github.com` for `https://api.github.com`,
+/// the Enterprise host for `https://github.example.com/api/v3`.
+pub fn credential_host(api_url: &str) -> String {
+    let without_scheme = match api_url.find("://") {
+        Some(index) => &api_url[index + 3..],
+        None => api_url,
+    };
+    let host = without_scheme.split('/').next().unwrap_or(without_scheme);
+    if host == "api.github.com" {
+        return "github.com".to_owned();
+    }
+    host.to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static GITHUB: TokenSources = TokenSources {
+        env_vars: &["RP_GITHUBTOKEN", "GITHUB_TOKEN"],
+        gh_cli: true,
+    };
+
+    fn no_git(_host: &str) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn token_file_comes_first() {
+        let path = env::temp_dir().join("release-party-token-file");
+        fs::write(&path, "file-token\n").unwrap();
+        let env_var = |_: &str| Some("env-token".to_owned());
+
+        let found = find_token_with(Some(&path), "github.com", &GITHUB, &env_var, &no_git).unwrap();
+
+        assert_eq!("file-token", found.token);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rp_githubtoken_beats_github_token() {
+        let env_var = |name: &str| match name {
+            "RP_GITHUBTOKEN" => Some("rp-token".to_owned()),
+            "GITHUB_TOKEN" => Some("github-token".to_owned()),
+            _ => None,
+        };
+        let found = find_token_with(None, "github.com", &GITHUB, &env_var, &no_git).unwrap();
+        assert_eq!(
+            FoundToken {
+                token: "rp-token".to_owned(),
+                source: "RP_GITHUBTOKEN environment variable".to_owned(),
+            },
+            found
+        );
+    }
+
+    #[test]
+    fn falls_back_to_gh_then_git() {
+        let gh_dir = env::temp_dir().join("release-party-gh-config");
+        fs::create_dir_all(&gh_dir).unwrap();
+        fs::write(
+            gh_dir.join("hosts.yml"),
+            "github.com:\n    user: partier\n    oauth_token: gho_from_gh\n    git_protocol: https\n",
+        )
+        .unwrap();
+        let gh_dir_string = gh_dir.to_string_lossy().into_owned();
+        let env_var = |name: &str| match name {
+            "GH_CONFIG_DIR" => Some(gh_dir_string.clone()),
+            _ => None,
+        };
+        let git = |_: &str| Some("from-git".to_owned());
+
+        assert_eq!(
+            "gho_from_gh",
+            find_token_with(None, "github.com", &GITHUB, &env_var, &git).unwrap().token
+        );
+        assert_eq!(
+            "from-git",
+            find_token_with(None, "github.example.com", &GITHUB, &env_var, &git).unwrap().token
+        );
+        assert!(find_token_with(None, "github.example.com", &GITHUB, &env_var, &no_git).is_err());
+    }
+
+    #[test]
+    fn other_forges_skip_the_gh_cli() {
+        let gh_dir = env::temp_dir().join("release-party-gh-config-other-forge");
+        fs::create_dir_all(&gh_dir).unwrap();
+        fs::write(gh_dir.join("hosts.yml"), "gitlab.example.com:\n    oauth_token: gho_from_gh\n").unwrap();
+        let gh_dir_string = gh_dir.to_string_lossy().into_owned();
+        let env_var = |name: &str| match name {
+            "GH_CONFIG_DIR" => Some(gh_dir_string.clone()),
+            "GITHUB_TOKEN" => Some("github-token".to_owned()),
+            "GITLAB_TOKEN" => Some("gitlab-token".to_owned()),
+            _ => None,
+        };
+        let gitlab = TokenSources {
+            env_vars: &["RP_GITLABTOKEN", "GITLAB_TOKEN"],
+            gh_cli: false,
+        };
+
+        let found = find_token_with(None, "gitlab.example.com", &gitlab, &env_var, &no_git).unwrap();
+        assert_eq!("gitlab-token", found.token);
+
+        let only_gh = |name: &str| match name {
+            "GH_CONFIG_DIR" => Some(gh_dir_string.clone()),
+            _ => None,
+        };
+        let err = find_token_with(None, "gitlab.example.com", &gitlab, &only_gh, &no_git).unwrap_err();
+        assert!(err.contains("set RP_GITLABTOKEN or GITLAB_TOKEN"));
+    }
+
+    #[test]
+    fn reads_password_from_git_credential_output() {
+        assert_eq!(
+            Some("s3cret".to_owned()),
+            password_from_credential_output("protocol=https\nhost=github.com\nusername=me\npassword=s3cret\n")
+        );
+        assert_eq!(None, password_from_credential_output("protocol=https\nhost=github.com\n"));
+    }
+
+    #[test]
+    fn credential_hosts() {
+        assert_eq!("github.com", credential_host("https://api.github.com"));
+        assert_eq!("github.example.com", credential_host("https://github.example.com/api/v3"));
+        assert_eq!("127.0.0.1:8080", credential_host("http://127.0.0.1:8080"));
+    }
+}
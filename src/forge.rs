@@ -0,0 +1,105 @@
+extern crate reqwest;
+extern crate serde_json;
+
+use self::reqwest::header::HeaderMap;
+use self::reqwest::{RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+
+use std::io::Read;
+
+/// One of the forges besides GitHub, for the requests and error messages their clients share.
+#[derive(Debug, Clone, Copy)]
+pub struct Forge {
+    // How it reads at the start of a sentence and in the middle of one: "Gitlab", "gitlab".
+    title: &'static str,
+    name: &'static str,
+}
+
+pub const GITLAB: Forge = Forge {
+    title: "Gitlab",
+    name: "gitlab",
+};
+
+pub const GITEA: Forge = Forge {
+    title: "Gitea",
+    name: "gitea",
+};
+
+pub const BITBUCKET: Forge = Forge {
+    title: "Bitbucket",
+    name: "bitbucket",
+};
+
+impl Forge {
+    /// Sends `request`, giving back the body and headers of a successful response. `what` is what was asked
+    /// for, for the error message.
+    pub fn send(self, request: RequestBuilder, what: &str) -> Result<(String, HeaderMap), String> {
+        let mut res = match request.send() {
+            Ok(response) => response,
+            Err(e) => return Err(format!("Error in request to {} for {}: {}", self.name, what, e)),
+        };
+        if !res.status().is_success() {
+            return Err(format!("{} responded with {} for {}", self.title, res.status(), what));
+        }
+        let mut buffer = String::new();
+        if let Err(e) = res.read_to_string(&mut buffer) {
+            return Err(format!("Couldn't read {}'s response for {}: {}", self.name, what, e));
+        }
+        Ok((buffer, res.headers().clone()))
+    }
+
+    pub fn send_for_json<T: DeserializeOwned>(self, request: RequestBuilder, what: &str) -> Result<T, String> {
+        let (buffer, _) = self.send(request, what)?;
+        serde_json::from_str(&buffer).map_err(|e| format!("Couldn't deserialize {} from {}: {}", what, self.name, e))
+    }
+
+    /// Whether `request` finds something: a success is yes and a 404 is no.
+    pub fn exists(self, request: RequestBuilder, what: &str) -> Result<bool, String> {
+        let res = match request.send() {
+            Ok(response) => response,
+            Err(e) => return Err(format!("Error in request to {} for {}: {}", self.name, what, e)),
+        };
+        if res.status().is_success() {
+            return Ok(true);
+        }
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        Err(format!("{} responded with {} looking for {}", self.title, res.status(), what))
+    }
+}
+
+/// `segment` made safe to put between two slashes of a URL path, for branch names like `feature/a#1`.
+pub fn encode_segment(segment: &str) -> String {
+    segment
+        .replace('%', "%25")
+        .replace('/', "%2F")
+        .replace('#', "%23")
+        .replace('?', "%3F")
+        .replace(' ', "%20")
+}
+
+/// What the GitHub-only parts of `ReleaseHost` answer on other forges.
+pub fn branch_protection_unsupported() -> String {
+    "Branch protection audits are only supported on GitHub".to_owned()
+}
+
+pub fn pr_comments_unsupported() -> String {
+    "Commenting on merged PRs is only supported on GitHub".to_owned()
+}
+
+pub fn pr_labels_unsupported() -> String {
+    "Labelling merged PRs is only supported on GitHub".to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_what_would_end_a_path_segment() {
+        assert_eq!("release", encode_segment("release"));
+        assert_eq!("feature%2Fa%231", encode_segment("feature/a#1"));
+        assert_eq!("100%25%3Fdone", encode_segment("100%?done"));
+    }
+}
@@ -0,0 +1,369 @@
+extern crate reqwest;
+extern crate serde_json;
+
+use self::reqwest::header::HeaderMap;
+use self::reqwest::Url;
+use serde::de::DeserializeOwned;
+
+use std::collections::HashMap;
+
+use forge::{self, branch_protection_unsupported, pr_comments_unsupported, pr_labels_unsupported, GITEA};
+use github::{
+    BranchProtection, BranchProtectionUpdate, ChangedFile, Commit, CommitInPR, CompareCommitsResponse,
+    GithubPullRequest, GithubRepo, GithubTag, RepoPermissions,
+};
+use host::ReleaseHost;
+
+// How many items to ask for per page. Gitea caps it with MAX_RESPONSE_ITEMS, 50 by default.
+const PAGE_LIMIT: usize = 50;
+
+#[derive(Deserialize, Debug)]
+struct GiteaRepo {
+    name: String,
+    full_name: String,
+    #[serde(default)]
+    permissions: Option<RepoPermissions>,
+}
+
+// Gitea's compare only lists the commits `head` has that `base` doesn't.
+#[derive(Deserialize, Debug)]
+struct Compare {
+    #[serde(default)]
+    commits: Vec<CompareCommit>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CompareCommit {
+    #[serde(flatten)]
+    commit: CommitInPR,
+    #[serde(default)]
+    files: Vec<CommitFile>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CommitFile {
+    filename: String,
+    #[serde(default)]
+    status: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct PullBranch {
+    #[serde(rename = "ref")]
+    branch: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Pull {
+    html_url: String,
+    head: PullBranch,
+    base: PullBranch,
+}
+
+/// Talks to the Gitea (and Forgejo) v1 REST API. It's shaped like GitHub's, but pages with `page`/`limit`.
+pub struct GiteaClient {
+    client: reqwest::Client,
+    api_url: String,
+}
+
+impl GiteaClient {
+    /// `client` authenticates with `Authorization: token ...`, like `github::client_for_token` makes.
+    pub fn new(client: reqwest::Client, api_url: &str) -> GiteaClient {
+        GiteaClient {
+            client,
+            api_url: api_url.trim_end_matches('/').to_owned(),
+        }
+    }
+
+    // Every page of a list, following `page` until a short or empty page or X-Total-Count says we're done.
+    fn get_all<T: DeserializeOwned>(&self, url: &str, params: &[(&str, &str)], what: &str) -> Result<Vec<T>, String> {
+        let mut items = Vec::new();
+        let limit = PAGE_LIMIT.to_string();
+        let mut page = 1;
+        loop {
+            let page_str = page.to_string();
+            let mut page_params = params.to_vec();
+            page_params.push(("limit", &limit));
+            page_params.push(("page", &page_str));
+            let page_url = match Url::parse_with_params(url, &page_params) {
+                Ok(page_url) => page_url,
+                Err(e) => return Err(format!("Couldn't parse uri {:?} : {:?}", url, e)),
+            };
+            let (buffer, headers) = GITEA.send(self.client.get(page_url), what)?;
+            let mut page_items: Vec<T> = match serde_json::from_str(&buffer) {
+                Ok(page_items) => page_items,
+                Err(e) => return Err(format!("Couldn't deserialize {} from gitea: {}", what, e)),
+            };
+            let page_len = page_items.len();
+            items.append(&mut page_items);
+            if is_last_page(&headers, page_len, items.len()) {
+                return Ok(items);
+            }
+            page += 1;
+        }
+    }
+
+    fn compare_commits(&self, repo: &GithubRepo, base: &str, head: &str) -> Result<Vec<CompareCommit>, String> {
+        let url = format!("{}/compare/{}...{}", repo.url, base, head);
+        let compare: Compare = GITEA.send_for_json(self.client.get(&url), &format!("compare {}...{} of {}", base, head, repo.name))?;
+        Ok(compare.commits)
+    }
+
+    // Gitea resolves any branch, tag or SHA through the commit list.
+    fn commits_on(&self, repo: &GithubRepo, reference: &str, page: &str) -> Result<(Vec<Commit>, HeaderMap), String> {
+        let url = match Url::parse_with_params(
+            &format!("{}/commits", repo.url),
+            &[("sha", reference), ("limit", "1"), ("page", page), ("stat", "false")],
+        ) {
+            Ok(url) => url,
+            Err(e) => return Err(format!("Couldn't create url for commits: {}", e)),
+        };
+        let (buffer, headers) = GITEA.send(self.client.get(url), reference)?;
+        match serde_json::from_str(&buffer) {
+            Ok(commits) => Ok((commits, headers)),
+            Err(e) => Err(format!("Couldn't deserialize commits on {}: {}", reference, e)),
+        }
+    }
+}
+
+fn is_last_page(headers: &HeaderMap, page_len: usize, fetched: usize) -> bool {
+    let total = headers
+        .get("X-Total-Count")
+        .and_then(|total| total.to_str().ok())
+        .and_then(|total| total.parse::<usize>().ok());
+    match total {
+        Some(total) => fetched >= total || page_len == 0,
+        None => page_len < PAGE_LIMIT,
+    }
+}
+
+// Two one-sided compares make GitHub's ahead/behind compare. Gitea only names the files each commit touched.
+fn compare_response(ahead: Vec<CompareCommit>, behind: Vec<CompareCommit>) -> CompareCommitsResponse {
+    let ahead_by = ahead.len() as i32;
+    let behind_by = behind.len() as i32;
+    let status = match (ahead_by, behind_by) {
+        (0, 0) => "identical",
+        (_, 0) => "ahead",
+        (0, _) => "behind",
+        _ => "diverged",
+    };
+    let mut files: Vec<ChangedFile> = Vec::new();
+    for file in ahead.iter().flat_map(|c| c.files.iter()) {
+        if !files.iter().any(|f| f.filename == file.filename) {
+            files.push(ChangedFile {
+                filename: file.filename.clone(),
+                status: file.status.clone(),
+                additions: 0,
+                deletions: 0,
+            });
+        }
+    }
+    CompareCommitsResponse {
+        status: status.to_owned(),
+        ahead_by,
+        behind_by,
+        total_commits: ahead_by,
+        commits: ahead.into_iter().map(|c| c.commit).collect(),
+        files,
+    }
+}
+
+impl ReleaseHost for GiteaClient {
+    fn list_repos(&self, org_url: &str) -> Result<Vec<GithubRepo>, String> {
+        let gitea_repos: Vec<GiteaRepo> = self.get_all(org_url, &[], "the org's repos")?;
+        let repos = gitea_repos
+            .into_iter()
+            .map(|r| {
+                let mut repo = GithubRepo::new(&r.name, &format!("{}/repos/{}", self.api_url, r.full_name));
+                repo.permissions = r.permissions;
+                repo
+            })
+            .collect::<Vec<GithubRepo>>();
+        eprintln!("Number of repos to check: {:?}", repos.len());
+        Ok(repos)
+    }
+
+    fn compare(&self, repo: &GithubRepo, base: &str, head: &str) -> Result<CompareCommitsResponse, String> {
+        let ahead = self.compare_commits(repo, base, head)?;
+        let behind = self.compare_commits(repo, head, base)?;
+        Ok(compare_response(ahead, behind))
+    }
+
+    fn find_pr(&self, repo: &GithubRepo, head: &str, base: &str) -> Option<String> {
+        let url = format!("{}/pulls", repo.url);
+        match self.get_all::<Pull>(&url, &[("state", "open")], &format!("pull requests of {}", repo.name)) {
+            Ok(pulls) => pulls
+                .into_iter()
+                .find(|p| p.head.branch == head && p.base.branch == base)
+                .map(|p| p.html_url),
+            Err(e) => {
+                eprintln!("{}", e);
+                None
+            }
+        }
+    }
+
+    fn create_pr(&self, repo: &GithubRepo, title: &str, head: &str, base: &str) -> Result<String, String> {
+        let mut pr_body = HashMap::new();
+        pr_body.insert("title", title);
+        pr_body.insert("head", head);
+        pr_body.insert("base", base);
+
+        // Gitea answers 409 Conflict when there's one open already.
+        let url = format!("{}/pulls", repo.url);
+        let pull: Pull = GITEA.send_for_json(self.client.post(&url).json(&pr_body), &format!("a new PR for {}", repo.name))?;
+        Ok(pull.html_url)
+    }
+
+    fn pr_commits(&self, repo: &GithubRepo, pr_number: &str) -> Result<Vec<CommitInPR>, String> {
+        let url = format!("{}/pulls/{}/commits", repo.url, pr_number);
+        self.get_all(&url, &[], &format!("commits of PR {}", pr_number))
+    }
+
+    fn pr_body(&self, repo: &GithubRepo, pr_number: &str) -> Result<Option<String>, String> {
+        let url = format!("{}/pulls/{}", repo.url, pr_number);
+        let pull: GithubPullRequest = GITEA.send_for_json(self.client.get(&url), &format!("PR {}", pr_number))?;
+        Ok(pull.body)
+    }
+
+    fn update_pr_body(&self, repo: &GithubRepo, pr_number: &str, body: &str) -> Result<(), String> {
+        let mut pr_body = HashMap::new();
+        pr_body.insert("body", body);
+
+        let url = format!("{}/pulls/{}", repo.url, pr_number);
+        GITEA.send(
+            self.client.patch(&url).json(&pr_body),
+            &format!("updating the body of PR {}", pr_number),
+        )
+        .map(|_| ())
+    }
+
+    fn pr_comments(&self, _repo: &GithubRepo, _pr_number: &str) -> Result<Vec<String>, String> {
+        Err(pr_comments_unsupported())
+    }
+
+    fn comment_on_pr(&self, _repo: &GithubRepo, _pr_number: &str, _body: &str) -> Result<(), String> {
+        Err(pr_comments_unsupported())
+    }
+
+    fn pr_labels(&self, _repo: &GithubRepo, _pr_number: &str) -> Result<Vec<String>, String> {
+        Err(pr_labels_unsupported())
+    }
+
+    fn add_pr_label(&self, _repo: &GithubRepo, _pr_number: &str, _label: &str) -> Result<(), String> {
+        Err(pr_labels_unsupported())
+    }
+
+    fn tags(&self, repo: &GithubRepo) -> Vec<GithubTag> {
+        match self.get_all(&format!("{}/tags", repo.url), &[], &format!("tags of {}", repo.name)) {
+            Ok(tags) => tags,
+            Err(e) => {
+                eprintln!("{}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    fn branch_exists(&self, repo: &GithubRepo, branch: &str) -> Result<bool, String> {
+        let url = format!("{}/branches/{}", repo.url, forge::encode_segment(branch));
+        GITEA.exists(self.client.get(&url), &format!("branch {}", branch))
+    }
+
+    fn commit_sha(&self, repo: &GithubRepo, reference: &str) -> Result<String, String> {
+        let (mut commits, _) = self.commits_on(repo, reference, "1")?;
+        match commits.pop() {
+            Some(commit) => Ok(commit.sha),
+            None => Err(format!("No commits on {}", reference)),
+        }
+    }
+
+    // One commit per page, newest first: X-Total-Count is the number of the page with the first commit.
+    fn first_commit(&self, repo: &GithubRepo, branch: &str) -> Result<String, String> {
+        let (mut commits, headers) = self.commits_on(repo, branch, "1")?;
+        if let Some(total) = headers.get("X-Total-Count").and_then(|total| total.to_str().ok()) {
+            if total != "1" {
+                commits = self.commits_on(repo, branch, total)?.0;
+            }
+        }
+        match commits.pop() {
+            Some(commit) => Ok(commit.sha),
+            None => Err(format!("No commits on {}", branch)),
+        }
+    }
+
+    fn create_branch(&self, repo: &GithubRepo, branch: &str, sha: &str) -> Result<(), String> {
+        let mut branch_body = HashMap::new();
+        branch_body.insert("new_branch_name", branch);
+        branch_body.insert("old_ref_name", sha);
+
+        let url = format!("{}/branches", repo.url);
+        GITEA.send(
+            self.client.post(&url).json(&branch_body),
+            &format!("creating branch {}", branch),
+        )
+        .map(|_| ())
+    }
+
+    fn branch_protection(&self, _repo: &GithubRepo, _branch: &str) -> Result<Option<BranchProtection>, String> {
+        Err(branch_protection_unsupported())
+    }
+
+    fn set_branch_protection(
+        &self,
+        _repo: &GithubRepo,
+        _branch: &str,
+        _protection: &BranchProtectionUpdate,
+    ) -> Result<(), String> {
+        Err(branch_protection_unsupported())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use github::ActualCommitInPR;
+
+    fn compare_commit(sha: &str, files: &[&str]) -> CompareCommit {
+        CompareCommit {
+            commit: CommitInPR {
+                sha: sha.to_owned(),
+                actual_commit: ActualCommitInPR {
+                    message: format!("commit {}", sha),
+                },
+            },
+            files: files
+                .iter()
+                .map(|f| CommitFile {
+                    filename: f.to_string(),
+                    status: "modified".to_owned(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn pages_end_at_the_total_count_or_a_short_page() {
+        let mut headers = HeaderMap::new();
+        assert!(!is_last_page(&headers, PAGE_LIMIT, PAGE_LIMIT));
+        assert!(is_last_page(&headers, 3, PAGE_LIMIT + 3));
+
+        headers.insert("X-Total-Count", "100".parse().unwrap());
+        assert!(!is_last_page(&headers, PAGE_LIMIT, PAGE_LIMIT));
+        assert!(is_last_page(&headers, PAGE_LIMIT, 100));
+    }
+
+    #[test]
+    fn compares_become_github_shaped() {
+        let ahead = vec![compare_commit("a", &["src/lib.rs"]), compare_commit("b", &["src/lib.rs", "README.md"])];
+
+        let compare = compare_response(ahead, Vec::new());
+
+        assert_eq!("ahead", compare.status);
+        assert_eq!((2, 0), (compare.ahead_by, compare.behind_by));
+        assert_eq!(
+            vec!["src/lib.rs", "README.md"],
+            compare.files.iter().map(|f| f.filename.as_str()).collect::<Vec<&str>>()
+        );
+        assert_eq!("diverged", compare_response(vec![compare_commit("a", &[])], vec![compare_commit("c", &[])]).status);
+    }
+}
@@ -0,0 +1,259 @@
+extern crate base64;
+extern crate openssl;
+
+use self::openssl::hash::MessageDigest;
+use self::openssl::pkey::{PKey, Private};
+use self::openssl::sign::Signer;
+
+use serde::de::DeserializeOwned;
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use http::HttpSettings;
+
+use super::{client_for, delay_if_running_out_of_requests, Credential};
+
+static MACHINE_MAN_PREVIEW: &str = "application/vnd.github.machine-man-preview+json";
+
+// Refresh installation tokens this long before GitHub says they expire.
+const REFRESH_MARGIN_SECS: u64 = 5 * 60;
+
+/// A GitHub App, which signs JWTs to get installation tokens for the orgs it's installed on.
+pub struct GithubApp {
+    app_id: String,
+    private_key: PKey<Private>,
+    http: HttpSettings,
+}
+
+/// A token for one org's installation of the app. They last an hour.
+#[derive(Deserialize, Debug, Clone)]
+pub struct InstallationToken {
+    pub token: String,
+    pub expires_at: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Installation {
+    id: u64,
+}
+
+#[derive(Serialize, Debug)]
+struct Claims<'a> {
+    iat: u64,
+    exp: u64,
+    iss: &'a str,
+}
+
+impl GithubApp {
+    pub fn new(app_id: &str, private_key_pem: &[u8]) -> Result<GithubApp, String> {
+        match PKey::private_key_from_pem(private_key_pem) {
+            Ok(private_key) => Ok(GithubApp {
+                app_id: app_id.to_owned(),
+                private_key,
+                http: HttpSettings::default(),
+            }),
+            Err(e) => Err(format!("Couldn't read the GitHub App private key: {}", e)),
+        }
+    }
+
+    pub fn from_key_file(app_id: &str, key_path: &Path) -> Result<GithubApp, String> {
+        match fs::read(key_path) {
+            Ok(pem) => GithubApp::new(app_id, &pem),
+            Err(e) => Err(format!("Couldn't read the GitHub App private key from {:?}: {}", key_path, e)),
+        }
+    }
+
+    /// Reaches GitHub with `http`'s timeouts, proxy and certificates, for its installation tokens and
+    /// the clients using them.
+    pub fn with_http(mut self, http: HttpSettings) -> GithubApp {
+        self.http = http;
+        self
+    }
+
+    pub fn http(&self) -> &HttpSettings {
+        &self.http
+    }
+
+    /// A JWT signed with the app's key, good for ten minutes.
+    pub fn jwt(&self, now: u64) -> Result<String, String> {
+        // Back-date it a minute in case our clock is ahead of GitHub's.
+        let claims = Claims {
+            iat: now.saturating_sub(60),
+            exp: now + 9 * 60,
+            iss: &self.app_id,
+        };
+        let header = base64_url(br#"{"alg":"RS256","typ":"JWT"}"#);
+        let claims = match serde_json::to_vec(&claims) {
+            Ok(claims) => base64_url(&claims),
+            Err(e) => return Err(format!("Couldn't serialize JWT claims: {}", e)),
+        };
+        let signing_input = format!("{}.{}", header, claims);
+        let signature = self
+            .sign(signing_input.as_bytes())
+            .map_err(|e| format!("Couldn't sign the GitHub App JWT: {}", e))?;
+        Ok(format!("{}.{}", signing_input, base64_url(&signature)))
+    }
+
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, openssl::error::ErrorStack> {
+        let mut signer = Signer::new(MessageDigest::sha256(), &self.private_key)?;
+        signer.update(data)?;
+        signer.sign_to_vec()
+    }
+
+    /// Looks up the app's installation on `org` and asks for a token for it.
+    pub fn installation_token(&self, api_url: &str, org: &str) -> Result<InstallationToken, String> {
+        let client = client_for(&Credential::AppJwt(self.jwt(unix_now())?), &self.http);
+        let api_url = api_url.trim_end_matches('/');
+
+        let installation_url = format!("{}/orgs/{}/installation", api_url, org);
+        let installation: Installation = send_for_json(
+            client.get(&installation_url).header("Accept", MACHINE_MAN_PREVIEW),
+            &format!("the app's installation on {}", org),
+        )?;
+
+        let tokens_url = format!("{}/app/installations/{}/access_tokens", api_url, installation.id);
+        send_for_json(
+            client.post(&tokens_url).header("Accept", MACHINE_MAN_PREVIEW),
+            &format!("an installation token for {}", org),
+        )
+    }
+}
+
+impl InstallationToken {
+    /// True once the token is within a few minutes of expiring, or if we can't tell when it expires.
+    pub fn needs_refresh(&self, now: u64) -> bool {
+        match parse_timestamp(&self.expires_at) {
+            Some(expires_at) => now + REFRESH_MARGIN_SECS >= expires_at,
+            None => true,
+        }
+    }
+}
+
+fn send_for_json<T: DeserializeOwned>(request: reqwest::RequestBuilder, what: &str) -> Result<T, String> {
+    let mut res = match request.send() {
+        Ok(response) => response,
+        Err(e) => return Err(format!("Error in request to github for {}: {}", what, e)),
+    };
+    delay_if_running_out_of_requests(res.headers());
+    if !res.status().is_success() {
+        return Err(format!("Github responded with {} asking for {}", res.status(), what));
+    }
+    let mut buffer = String::new();
+    if let Err(e) = res.read_to_string(&mut buffer) {
+        return Err(format!("Couldn't read github's response for {}: {}", what, e));
+    }
+    serde_json::from_str(&buffer).map_err(|e| format!("Couldn't deserialize {}: {}", what, e))
+}
+
+pub fn unix_now() -> u64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(since_epoch) => since_epoch.as_secs(),
+        Err(_) => 0,
+    }
+}
+
+fn base64_url(data: &[u8]) -> String {
+    base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+// GitHub's timestamps look like 2016-07-11T22:14:10Z.
+fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let timestamp = timestamp.trim_end_matches('Z');
+    let mut date_time = timestamp.splitn(2, 'T');
+    let date = date_time
+        .next()?
+        .split('-')
+        .map(|part| part.parse::<i64>().ok())
+        .collect::<Option<Vec<i64>>>()?;
+    let time = date_time
+        .next()?
+        .split(':')
+        .map(|part| part.parse::<i64>().ok())
+        .collect::<Option<Vec<i64>>>()?;
+    if date.len() != 3 || time.len() != 3 {
+        return None;
+    }
+    let days = days_from_civil(date[0], date[1], date[2]);
+    let seconds = days * 86_400 + time[0] * 3600 + time[1] * 60 + time[2];
+    if seconds < 0 {
+        return None;
+    }
+    Some(seconds as u64)
+}
+
+// Days since 1970-01-01 for a proleptic Gregorian date, from Howard Hinnant's date algorithms.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::openssl::rsa::Rsa;
+    use super::openssl::sign::Verifier;
+
+    fn test_app() -> GithubApp {
+        let key = Rsa::generate(2048).unwrap();
+        GithubApp::new("1234", &key.private_key_to_pem().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn jwt_is_signed_rs256_with_the_app_id() {
+        let app = test_app();
+        let jwt = app.jwt(1_000_000).unwrap();
+        let parts = jwt.split('.').collect::<Vec<&str>>();
+        assert_eq!(3, parts.len());
+
+        let header = base64::decode_config(parts[0], base64::URL_SAFE_NO_PAD).unwrap();
+        assert_eq!(br#"{"alg":"RS256","typ":"JWT"}"#.to_vec(), header);
+        let claims = base64::decode_config(parts[1], base64::URL_SAFE_NO_PAD).unwrap();
+        assert_eq!(
+            r#"{"iat":999940,"exp":1000540,"iss":"1234"}"#,
+            String::from_utf8(claims).unwrap()
+        );
+
+        let signature = base64::decode_config(parts[2], base64::URL_SAFE_NO_PAD).unwrap();
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &app.private_key).unwrap();
+        verifier
+            .update(format!("{}.{}", parts[0], parts[1]).as_bytes())
+            .unwrap();
+        assert!(verifier.verify(&signature).unwrap());
+    }
+
+    #[test]
+    fn jwt_with_a_clock_near_the_epoch() {
+        assert!(test_app().jwt(30).is_ok());
+    }
+
+    #[test]
+    fn bad_private_key_is_an_error() {
+        assert!(GithubApp::new("1234", b"not a key").is_err());
+    }
+
+    #[test]
+    fn parses_github_timestamps() {
+        assert_eq!(Some(0), parse_timestamp("1970-01-01T00:00:00Z"));
+        assert_eq!(Some(1_468_275_250), parse_timestamp("2016-07-11T22:14:10Z"));
+        assert_eq!(None, parse_timestamp("yesterday"));
+    }
+
+    #[test]
+    fn refreshes_tokens_close_to_expiry() {
+        let token = InstallationToken {
+            token: "v1.abc".to_owned(),
+            expires_at: "2016-07-11T22:14:10Z".to_owned(),
+        };
+        assert!(!token.needs_refresh(1_468_275_250 - 3600));
+        assert!(token.needs_refresh(1_468_275_250 - 60));
+        assert!(token.needs_refresh(1_468_275_250 + 60));
+    }
+}
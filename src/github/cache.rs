@@ -0,0 +1,332 @@
+extern crate openssl;
+extern crate reqwest;
+extern crate serde_json;
+
+use self::openssl::sha::sha256;
+use self::reqwest::header::{HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LINK};
+use self::reqwest::{StatusCode, Url};
+
+use super::retry::{self, RetryPolicy};
+
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{Read, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How long a response stays cached without being fetched or revalidated, see `ResponseCache::prune`.
+pub const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// GitHub GET responses kept on disk by URL with their ETag and Last-Modified, so the next run can
+/// ask whether they changed. GitHub doesn't count a 304 Not Modified against the rate limit.
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct CachedResponse {
+    url: String,
+    #[serde(default)]
+    etag: Option<String>,
+    #[serde(default)]
+    last_modified: Option<String>,
+    // A 304 doesn't repeat the pagination links.
+    #[serde(default)]
+    link: Option<String>,
+    body: String,
+}
+
+/// A response read to the end, either fresh or replayed from the cache.
+pub struct Fetched {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+impl ResponseCache {
+    pub fn new(dir: &Path) -> ResponseCache {
+        ResponseCache { dir: dir.to_owned() }
+    }
+
+    /// Where the cache goes without `--cache-dir`: `release-party-br` in the user's cache directory.
+    /// Without one there's no cache, rather than private responses in a directory everyone shares.
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("release-party-br"))
+    }
+
+    /// Removes the responses GitHub hasn't sent or confirmed as unchanged for longer than `max_age`, so the
+    /// cache doesn't grow forever with repos that are gone. Only the cache's own files are touched.
+    pub fn prune(&self, max_age: Duration) {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        let now = SystemTime::now();
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            if !is_cache_file_name(&entry.file_name().to_string_lossy()) {
+                continue;
+            }
+            let age = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| now.duration_since(modified).ok());
+            if age.map(|age| age > max_age).unwrap_or(false) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    fn path_for(&self, url: &Url) -> PathBuf {
+        let name: String = sha256(url.as_str().as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        self.dir.join(format!("{}.json", name))
+    }
+
+    fn load(&self, url: &Url) -> Option<CachedResponse> {
+        let contents = fs::read_to_string(self.path_for(url)).ok()?;
+        let cached: CachedResponse = serde_json::from_str(&contents).ok()?;
+        // Two URLs hashing the same is unlikely, but costs nothing to rule out.
+        if cached.url == url.as_str() {
+            Some(cached)
+        } else {
+            None
+        }
+    }
+
+    // The cache is only ever a saving: failing to write it is the same as not having it.
+    // Responses can be from private repos, so only the user gets to read them.
+    fn store(&self, url: &Url, cached: &CachedResponse) {
+        if private_dir_builder().create(&self.dir).is_err() {
+            return;
+        }
+        let contents = match serde_json::to_string(cached) {
+            Ok(contents) => contents,
+            Err(_) => return,
+        };
+        if let Ok(mut file) = private_file_options().open(self.path_for(url)) {
+            let _ = file.write_all(contents.as_bytes());
+        }
+    }
+}
+
+// A SHA-256 in hex and .json, see `path_for`.
+fn is_cache_file_name(name: &str) -> bool {
+    match name.strip_suffix(".json") {
+        Some(hash) => hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()),
+        None => false,
+    }
+}
+
+fn private_dir_builder() -> DirBuilder {
+    let mut builder = DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    builder.mode(0o700);
+    builder
+}
+
+fn private_file_options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    options
+}
+
+/// GETs `url`. With a cache that has an earlier response, GitHub is only asked whether it changed,
+/// and a 304 gives back the earlier body as a 200.
+pub fn get(
+    client: &reqwest::Client,
+    retries: &RetryPolicy,
+    url: Url,
+    cache: Option<&ResponseCache>,
+) -> Result<Fetched, String> {
+    let cached = cache.and_then(|cache| cache.load(&url));
+    let mut request = client.get(url.clone());
+    if let Some(ref cached) = cached {
+        if let Some(ref etag) = cached.etag {
+            request = request.header(IF_NONE_MATCH, etag.as_str());
+        }
+        if let Some(ref last_modified) = cached.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified.as_str());
+        }
+    }
+    let mut res = match retry::send(retries, request) {
+        Ok(response) => response,
+        Err(e) => return Err(format!("Error in request to github for {}: {}", url, e)),
+    };
+    let mut headers = res.headers().clone();
+
+    if res.status() == StatusCode::NOT_MODIFIED {
+        if let (Some(cache), Some(cached)) = (cache, cached) {
+            // Writing it again marks it as fresh, so `prune` keeps the responses that are still in use.
+            cache.store(&url, &cached);
+            if !headers.contains_key(LINK) {
+                if let Some(link) = cached.link.and_then(|link| HeaderValue::from_str(&link).ok()) {
+                    headers.insert(LINK, link);
+                }
+            }
+            return Ok(Fetched {
+                status: StatusCode::OK,
+                headers,
+                body: cached.body,
+            });
+        }
+    }
+
+    let mut body = String::new();
+    if let Err(e) = res.read_to_string(&mut body) {
+        return Err(format!("Couldn't read github's response for {}: {}", url, e));
+    }
+    let status = res.status();
+    if let Some(cache) = cache {
+        let etag = header_string(&headers, ETAG);
+        let last_modified = header_string(&headers, LAST_MODIFIED);
+        if status == StatusCode::OK && (etag.is_some() || last_modified.is_some()) {
+            cache.store(
+                &url,
+                &CachedResponse {
+                    url: url.as_str().to_owned(),
+                    etag,
+                    last_modified,
+                    link: header_string(&headers, LINK),
+                    body: body.clone(),
+                },
+            );
+        }
+    }
+    Ok(Fetched { status, headers, body })
+}
+
+fn header_string(headers: &HeaderMap, name: reqwest::header::HeaderName) -> Option<String> {
+    headers.get(name).and_then(|value| value.to_str().ok()).map(|value| value.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use github::app::unix_now;
+    use std::env;
+
+    #[test]
+    fn stores_and_loads_by_url() {
+        let dir = env::temp_dir().join(format!("release-party-cache-test-{}", unix_now()));
+        let cache = ResponseCache::new(&dir);
+        let url = Url::parse("https://api.github.com/orgs/party-org/repos?per_page=50").unwrap();
+        let other = Url::parse("https://api.github.com/orgs/other-org/repos?per_page=50").unwrap();
+        let cached = CachedResponse {
+            url: url.as_str().to_owned(),
+            etag: Some("\"abc\"".to_owned()),
+            last_modified: None,
+            link: None,
+            body: "[]".to_owned(),
+        };
+        cache.store(&url, &cached);
+
+        assert_eq!(Some(cached), cache.load(&url));
+        assert_eq!(None, cache.load(&other));
+        assert_ne!(cache.path_for(&url), cache.path_for(&other));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_the_user_can_read_the_cache() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = env::temp_dir().join(format!("release-party-cache-mode-test-{}", unix_now())).join("nested");
+        let cache = ResponseCache::new(&dir);
+        let url = Url::parse("https://api.github.com/repos/party-org/moe/compare/release...master").unwrap();
+        cache.store(
+            &url,
+            &CachedResponse {
+                url: url.as_str().to_owned(),
+                etag: Some("\"abc\"".to_owned()),
+                last_modified: None,
+                link: None,
+                body: "{}".to_owned(),
+            },
+        );
+
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(0o700, mode(&dir));
+        assert_eq!(0o600, mode(&cache.path_for(&url)));
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn prune_removes_old_responses_and_nothing_else() {
+        let dir = env::temp_dir().join(format!("release-party-cache-prune-test-{}", unix_now()));
+        let cache = ResponseCache::new(&dir);
+        let url = Url::parse("https://api.github.com/orgs/party-org/repos?per_page=50").unwrap();
+        cache.store(
+            &url,
+            &CachedResponse {
+                url: url.as_str().to_owned(),
+                etag: Some("\"abc\"".to_owned()),
+                last_modified: None,
+                link: None,
+                body: "[]".to_owned(),
+            },
+        );
+        fs::write(dir.join("notes.json"), "mine").unwrap();
+
+        cache.prune(MAX_AGE);
+        assert!(cache.load(&url).is_some());
+        cache.prune(Duration::from_secs(0));
+        assert!(cache.load(&url).is_none());
+        assert!(dir.join("notes.json").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn revalidated_responses_survive_pruning() {
+        use std::io::Write;
+        use std::net::TcpListener;
+        use std::thread;
+
+        // GitHub saying nothing changed, once.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 4096];
+            let _ = stream.read(&mut request);
+            stream
+                .write_all(b"HTTP/1.1 304 Not Modified\r\nETag: \"abc\"\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+        });
+
+        let dir = env::temp_dir().join(format!("release-party-cache-revalidate-test-{}", unix_now()));
+        let cache = ResponseCache::new(&dir);
+        let hot = Url::parse(&format!("http://{}/orgs/party-org/repos", address)).unwrap();
+        let cold = Url::parse(&format!("http://{}/repos/party-org/gone/tags", address)).unwrap();
+        for url in &[&hot, &cold] {
+            cache.store(
+                url,
+                &CachedResponse {
+                    url: url.as_str().to_owned(),
+                    etag: Some("\"abc\"".to_owned()),
+                    last_modified: None,
+                    link: None,
+                    body: "[]".to_owned(),
+                },
+            );
+        }
+        thread::sleep(Duration::from_millis(2100));
+
+        let fetched = get(&reqwest::Client::new(), &RetryPolicy::new(0), hot.clone(), Some(&cache)).unwrap();
+        server.join().unwrap();
+        cache.prune(Duration::from_secs(1));
+
+        assert_eq!("[]", fetched.body);
+        assert!(cache.load(&hot).is_some());
+        assert!(cache.load(&cold).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
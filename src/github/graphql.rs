@@ -0,0 +1,275 @@
+extern crate reqwest;
+extern crate serde_json;
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use super::retry::{self, RetryPolicy};
+use super::{delay_if_running_out_of_requests, CompareCommitsResponse, GithubRepo};
+
+/// How many repos go into one GraphQL query.
+pub const BATCH_SIZE: usize = 50;
+
+// Asked of every repo in a batch: how release compares with master, and the open release and back-merge PRs.
+// A fork's `master` is a `master` too, so the PRs come with their head's owner to pick out the repo's own.
+static REPO_FRAGMENT: &str = "fragment ReleaseParty on Repository {
+  release: ref(qualifiedName: \"refs/heads/release\") {
+    compare(headRef: \"master\") { status aheadBy behindBy }
+  }
+  releasePr: pullRequests(baseRefName: \"release\", headRefName: \"master\", states: [OPEN], first: 20) {
+    nodes { url headRepositoryOwner { login } }
+  }
+  backMergePr: pullRequests(baseRefName: \"master\", headRefName: \"release\", states: [OPEN], first: 20) {
+    nodes { url headRepositoryOwner { login } }
+  }
+}";
+
+/// What one batched query says about a repo, standing in for its compare and PR lookups.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepoSnapshot {
+    pub has_release_branch: bool,
+    // "ahead", "behind", "diverged" or "identical", like the REST compare.
+    pub status: String,
+    pub ahead_by: i32,
+    pub behind_by: i32,
+    pub release_pr: Option<String>,
+    pub back_merge_pr: Option<String>,
+}
+
+impl RepoSnapshot {
+    /// The compare, when the snapshot is all of it: with nothing to release there are no commits or files
+    /// to list, which GraphQL can't do anyway.
+    pub fn compare(&self) -> Option<CompareCommitsResponse> {
+        if !self.has_release_branch || self.ahead_by != 0 {
+            return None;
+        }
+        Some(CompareCommitsResponse {
+            status: self.status.clone(),
+            ahead_by: 0,
+            behind_by: self.behind_by,
+            total_commits: 0,
+            commits: Vec::new(),
+            files: Vec::new(),
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct GraphqlResponse {
+    #[serde(default)]
+    data: Option<HashMap<String, Option<RepoNode>>>,
+    #[serde(default)]
+    errors: Vec<GraphqlError>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GraphqlError {
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RepoNode {
+    release: Option<ReleaseRef>,
+    release_pr: PullRequests,
+    back_merge_pr: PullRequests,
+}
+
+#[derive(Deserialize, Debug)]
+struct ReleaseRef {
+    compare: Option<Comparison>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Comparison {
+    status: String,
+    ahead_by: i32,
+    behind_by: i32,
+}
+
+#[derive(Deserialize, Debug)]
+struct PullRequests {
+    nodes: Vec<PullRequestNode>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PullRequestNode {
+    url: String,
+    // Null when the head repo was deleted.
+    head_repository_owner: Option<RepositoryOwner>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RepositoryOwner {
+    login: String,
+}
+
+impl PullRequests {
+    // The first PR from a branch of the repo itself rather than of a fork.
+    fn first_from(self, owner: &str) -> Option<String> {
+        self.nodes
+            .into_iter()
+            .find(|pr| {
+                pr.head_repository_owner
+                    .as_ref()
+                    .map(|head_owner| head_owner.login.eq_ignore_ascii_case(owner))
+                    .unwrap_or(false)
+            })
+            .map(|pr| pr.url)
+    }
+}
+
+/// The GraphQL endpoint next to a REST API URL: `https://api.github.com/graphql`, or
+/// `https://github.example.com/api/graphql` for GitHub Enterprise's `/api/v3`.
+pub fn graphql_url(api_url: &str) -> String {
+    let api_url = api_url.trim_end_matches('/');
+    if api_url.ends_with("/api/v3") {
+        format!("{}/graphql", api_url.trim_end_matches("/v3"))
+    } else {
+        format!("{}/graphql", api_url)
+    }
+}
+
+// The owner from a REST repo URL, `{api}/repos/{owner}/{name}`.
+fn owner_of(repo: &GithubRepo) -> &str {
+    repo.url.rsplit('/').nth(1).unwrap_or("")
+}
+
+/// One query covering every repo in `repos`, the repo names passed as variables `$r0`, `$r1`...
+pub fn batch_query(repos: &[&GithubRepo]) -> serde_json::Value {
+    let mut declarations = Vec::new();
+    let mut fields = Vec::new();
+    let mut variables = serde_json::Map::new();
+    for (i, repo) in repos.iter().enumerate() {
+        declarations.push(format!("$o{}: String!, $r{}: String!", i, i));
+        fields.push(format!(
+            "  r{}: repository(owner: $o{}, name: $r{}) {{ ...ReleaseParty }}",
+            i, i, i
+        ));
+        variables.insert(format!("o{}", i), json!(owner_of(repo)));
+        variables.insert(format!("r{}", i), json!(repo.name));
+    }
+    let query = format!(
+        "query({}) {{\n{}\n}}\n{}",
+        declarations.join(", "),
+        fields.join("\n"),
+        REPO_FRAGMENT
+    );
+    json!({ "query": query, "variables": variables })
+}
+
+/// Looks up every repo in `repos` with one GraphQL query, keyed by repo URL. Repos GitHub couldn't
+/// answer for are left out, to be looked up over REST as usual.
+pub fn fetch_batch(
+    graphql_url: &str,
+    repos: &[&GithubRepo],
+    client: &reqwest::Client,
+    retries: &RetryPolicy,
+) -> Result<HashMap<String, RepoSnapshot>, String> {
+    let mut res = match retry::send(retries, client.post(graphql_url).json(&batch_query(repos))) {
+        Ok(response) => response,
+        Err(e) => return Err(format!("Error in request to github's GraphQL API: {}", e)),
+    };
+    delay_if_running_out_of_requests(res.headers());
+    let mut buffer = String::new();
+    if let Err(e) = res.read_to_string(&mut buffer) {
+        return Err(format!("Couldn't read github's GraphQL response: {}", e));
+    }
+    if !res.status().is_success() {
+        return Err(format!("Github responded with {} to the GraphQL query: {}", res.status(), buffer));
+    }
+    let response: GraphqlResponse = match serde_json::from_str(&buffer) {
+        Ok(response) => response,
+        Err(e) => return Err(format!("Couldn't deserialize github's GraphQL response: {}", e)),
+    };
+    let data = match response.data {
+        Some(data) => data,
+        None => {
+            let messages: Vec<String> = response.errors.into_iter().map(|e| e.message).collect();
+            return Err(format!("Github's GraphQL API said: {}", messages.join(", ")));
+        }
+    };
+    Ok(snapshots(repos, data))
+}
+
+fn snapshots(repos: &[&GithubRepo], mut data: HashMap<String, Option<RepoNode>>) -> HashMap<String, RepoSnapshot> {
+    let mut found = HashMap::new();
+    for (i, repo) in repos.iter().enumerate() {
+        let node = match data.remove(&format!("r{}", i)) {
+            Some(Some(node)) => node,
+            _ => continue,
+        };
+        let comparison = node.release.as_ref().and_then(|release| release.compare.as_ref());
+        // A release branch GitHub can't compare with master is better left to the REST compare's error.
+        if node.release.is_some() && comparison.is_none() {
+            continue;
+        }
+        found.insert(
+            repo.url.clone(),
+            RepoSnapshot {
+                has_release_branch: node.release.is_some(),
+                status: comparison.map_or(String::new(), |c| c.status.to_lowercase()),
+                ahead_by: comparison.map_or(0, |c| c.ahead_by),
+                behind_by: comparison.map_or(0, |c| c.behind_by),
+                release_pr: node.release_pr.first_from(owner_of(repo)),
+                back_merge_pr: node.back_merge_pr.first_from(owner_of(repo)),
+            },
+        );
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn graphql_url_next_to_the_rest_api() {
+        assert_eq!("https://api.github.com/graphql", graphql_url("https://api.github.com"));
+        assert_eq!(
+            "https://github.example.com/api/graphql",
+            graphql_url("https://github.example.com/api/v3/")
+        );
+    }
+
+    #[test]
+    fn one_query_per_batch_with_a_snapshot_per_repo() {
+        let moe = GithubRepo::new("moe", "https://api.github.com/repos/party-org/moe");
+        let calagator = GithubRepo::new("calagator", "https://api.github.com/repos/party-org/calagator");
+        let gone = GithubRepo::new("gone", "https://api.github.com/repos/party-org/gone");
+        let repos = vec![&moe, &calagator, &gone];
+
+        let query = batch_query(&repos);
+        assert!(query["query"].as_str().unwrap().contains("r1: repository(owner: $o1, name: $r1)"));
+        assert_eq!("party-org", query["variables"]["o1"]);
+        assert_eq!("calagator", query["variables"]["r1"]);
+
+        let data = serde_json::from_value(json!({
+            "r0": {
+                "release": {"compare": {"status": "BEHIND", "aheadBy": 0, "behindBy": 2}},
+                "releasePr": {"nodes": [
+                    {"url": "https://github.com/party-org/moe/pull/9", "headRepositoryOwner": {"login": "forker"}},
+                    {"url": "https://github.com/party-org/moe/pull/3", "headRepositoryOwner": null}
+                ]},
+                "backMergePr": {"nodes": [
+                    {"url": "https://github.com/party-org/moe/pull/7", "headRepositoryOwner": {"login": "Party-Org"}}
+                ]}
+            },
+            "r1": {"release": null, "releasePr": {"nodes": []}, "backMergePr": {"nodes": []}},
+            "r2": null
+        }))
+        .unwrap();
+        let found = snapshots(&repos, data);
+
+        assert_eq!(2, found.len());
+        let moe = &found["https://api.github.com/repos/party-org/moe"];
+        assert_eq!("behind", moe.status);
+        // Fork PRs from a `master` aren't release PRs.
+        assert_eq!(None, moe.release_pr);
+        assert_eq!(Some("https://github.com/party-org/moe/pull/7".to_owned()), moe.back_merge_pr);
+        assert_eq!(2, moe.compare().unwrap().behind_by);
+        assert!(!found["https://api.github.com/repos/party-org/calagator"].has_release_branch);
+    }
+}
@@ -1,25 +1,194 @@
 extern crate reqwest;
 extern crate serde_json;
 
-use self::reqwest::header::LINK;
-use self::reqwest::{Error, Response, Url};
+use self::reqwest::header::{ACCEPT, AUTHORIZATION, LINK, USER_AGENT};
+use self::reqwest::{StatusCode, Url};
 use reqwest::hyper_011::{header::Link, header::RelationType, Headers};
 
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::io::Read;
 use std::{thread, time};
 
+use host::{self, ReleaseHost};
+use http::HttpSettings;
+
+pub mod app;
+pub mod cache;
+pub mod graphql;
+pub mod retry;
+
+use self::app::{GithubApp, InstallationToken};
+use self::cache::ResponseCache;
+use self::graphql::RepoSnapshot;
+use self::retry::{RetryPolicy, Sent};
+
+static USERAGENT: &'static str = "release-party-br";
+
 #[derive(Deserialize, Debug)]
 pub struct GithubRepo {
     id: i32,
     pub name: String,
     pub url: String,
+    // What the authenticated user may do to the repo. Only there when the listing was authenticated.
+    #[serde(default)]
+    pub permissions: Option<RepoPermissions>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct RepoPermissions {
+    #[serde(default)]
+    pub admin: bool,
+    #[serde(default)]
+    pub push: bool,
+    #[serde(default)]
+    pub pull: bool,
+}
+
+impl GithubRepo {
+    pub fn new(name: &str, url: &str) -> GithubRepo {
+        GithubRepo {
+            id: 0,
+            name: name.to_owned(),
+            url: url.to_owned(),
+            permissions: None,
+        }
+    }
+
+    /// False only when GitHub told us we can't push: without permissions in the listing, let GitHub decide.
+    pub fn can_push(&self) -> bool {
+        match self.permissions {
+            Some(ref permissions) => permissions.push,
+            None => true,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
 pub struct CompareCommitsResponse {
     pub status: String,
+    pub ahead_by: i32,
     pub behind_by: i32,
+    pub total_commits: i32,
+    #[serde(default)]
+    pub commits: Vec<CommitInPR>,
+    #[serde(default)]
+    pub files: Vec<ChangedFile>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChangedFile {
+    pub filename: String,
+    pub status: String,
+    pub additions: i32,
+    pub deletions: i32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GithubTag {
+    pub name: String,
+    pub commit: Commit,
+}
+
+// The parts of branch protection the audit checks, and the ones a fix has to carry over.
+#[derive(Deserialize, Debug, Default)]
+pub struct BranchProtection {
+    pub required_status_checks: Option<RequiredStatusChecks>,
+    pub enforce_admins: Option<EnforceAdmins>,
+    pub required_pull_request_reviews: Option<RequiredReviews>,
+    #[serde(default)]
+    pub restrictions: Option<Restrictions>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RequiredStatusChecks {
+    pub strict: bool,
+    pub contexts: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EnforceAdmins {
+    pub enabled: bool,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct RequiredReviews {
+    #[serde(default)]
+    pub required_approving_review_count: u32,
+    #[serde(default)]
+    pub dismiss_stale_reviews: bool,
+    #[serde(default)]
+    pub require_code_owner_reviews: bool,
+    #[serde(default)]
+    pub dismissal_restrictions: Option<Restrictions>,
+}
+
+// Who may push to a branch, or dismiss reviews on it.
+#[derive(Deserialize, Debug, Default)]
+pub struct Restrictions {
+    #[serde(default)]
+    pub users: Vec<RestrictedUser>,
+    #[serde(default)]
+    pub teams: Vec<RestrictedSlug>,
+    #[serde(default)]
+    pub apps: Vec<RestrictedSlug>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RestrictedUser {
+    pub login: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RestrictedSlug {
+    pub slug: String,
+}
+
+// The shape GitHub wants when setting protection: every key has to be there, `null` turns it off.
+#[derive(Serialize, Debug)]
+pub struct BranchProtectionUpdate {
+    pub required_status_checks: Option<RequiredStatusChecks>,
+    pub enforce_admins: bool,
+    pub required_pull_request_reviews: Option<RequiredReviewsUpdate>,
+    pub restrictions: Option<RestrictionsUpdate>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RequiredReviewsUpdate {
+    pub required_approving_review_count: u32,
+    pub dismiss_stale_reviews: bool,
+    pub require_code_owner_reviews: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dismissal_restrictions: Option<RestrictionsUpdate>,
+}
+
+// Restrictions are read as users and teams but set as logins and slugs.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct RestrictionsUpdate {
+    pub users: Vec<String>,
+    pub teams: Vec<String>,
+    pub apps: Vec<String>,
+}
+
+impl From<&Restrictions> for RestrictionsUpdate {
+    fn from(restrictions: &Restrictions) -> RestrictionsUpdate {
+        RestrictionsUpdate {
+            users: restrictions.users.iter().map(|user| user.login.clone()).collect(),
+            teams: restrictions.teams.iter().map(|team| team.slug.clone()).collect(),
+            apps: restrictions.apps.iter().map(|app| app.slug.clone()).collect(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IssueComment {
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IssueLabel {
+    pub name: String,
 }
 
 #[derive(Deserialize, Debug)]
@@ -29,6 +198,8 @@ pub struct GithubPullRequest {
     pub html_url: String,
     pub head: Commit,
     pub base: Commit,
+    #[serde(default)]
+    pub body: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -48,45 +219,279 @@ pub struct CommitInPR {
     pub actual_commit: ActualCommitInPR,
 }
 
-pub fn is_release_up_to_date_with_master(repo_url: &str, client: &reqwest::Client) -> bool {
-    let repo_pr_url = format!("{}/{}/{}...{}", repo_url, "compare", "master", "release");
-    let url = match Url::parse(&repo_pr_url) {
+// Compares `base...head`: `ahead_by` and `commits` are what `head` has that `base` doesn't,
+// `behind_by` is how many commits `base` has that `head` doesn't.
+pub fn compare_branches(
+    repo_url: &str,
+    base: &str,
+    head: &str,
+    client: &reqwest::Client,
+    retries: &RetryPolicy,
+    cache: Option<&ResponseCache>,
+) -> Result<CompareCommitsResponse, String> {
+    let repo_compare_url = format!("{}/{}/{}...{}", repo_url, "compare", base, head);
+    let url = match Url::parse(&repo_compare_url) {
+        Ok(new_url) => new_url,
+        Err(e) => return Err(format!("Couldn't create url for compare page: {}", e)),
+    };
+    let res = cache::get(client, retries, url, cache)?;
+    delay_if_running_out_of_requests(&res.headers);
+
+    match serde_json::from_str(&res.body) {
+        Ok(compare_response) => Ok(compare_response),
+        Err(e) => Err(format!(
+            "Couldn't deserialize compare response for {}: {}",
+            repo_url, e
+        )),
+    }
+}
+
+// Every page of tags: repos with a long history can have hundreds, and the highest version isn't
+// necessarily on the first page.
+pub fn get_tags(repo_url: &str, client: &reqwest::Client, retries: &RetryPolicy) -> Vec<GithubTag> {
+    let mut tags_url = match Url::parse_with_params(&format!("{}/tags", repo_url), &[("per_page", "100")]) {
         Ok(new_url) => new_url,
         Err(e) => {
-            println!("Couldn't create url for compare page: {}", e);
-            return true;
+            eprintln!("Couldn't create url for tags: {}", e);
+            return Vec::new();
         }
     };
-    let mut res = match client.get(url.clone()).send() {
-        Ok(response) => response,
-        Err(e) => {
-            println!("Error in request to github for compare page: {}", e);
-            return true;
+    let mut tags = Vec::new();
+    loop {
+        let mut res = match retry::send(retries, client.get(tags_url)) {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("Error in request to github for tags: {}", e);
+                return tags;
+            }
+        };
+        delay_if_running_out_of_requests(res.headers());
+
+        let mut buffer = String::new();
+        match res.read_to_string(&mut buffer) {
+            Ok(_) => (),
+            Err(e) => eprintln!("error getting tags for {}: {}", repo_url, e),
+        }
+        tags.append(&mut serde_json::from_str::<Vec<GithubTag>>(&buffer).unwrap_or_default());
+
+        if !response_has_a_next_link(res.headers()) {
+            return tags;
         }
+        tags_url = match response_next_link(res.headers()) {
+            Ok(next) => next,
+            Err(e) => {
+                eprintln!("Couldn't follow the next page of tags for {}: {}", repo_url, e);
+                return tags;
+            }
+        };
+    }
+}
+
+pub fn branch_exists(
+    repo_url: &str,
+    branch: &str,
+    client: &reqwest::Client,
+    retries: &RetryPolicy,
+) -> Result<bool, String> {
+    let branch_url = format!("{}/branches/{}", repo_url, branch);
+    let res = match retry::send(retries, client.get(&branch_url)) {
+        Ok(response) => response,
+        Err(e) => return Err(format!("Error in request to github for branch {}: {}", branch, e)),
     };
     delay_if_running_out_of_requests(res.headers());
+    if res.status().is_success() {
+        return Ok(true);
+    }
+    if res.status() == StatusCode::NOT_FOUND {
+        return Ok(false);
+    }
+    Err(format!(
+        "Github responded with {} looking for branch {}",
+        res.status(),
+        branch
+    ))
+}
 
+pub fn commit_sha_for(
+    repo_url: &str,
+    reference: &str,
+    client: &reqwest::Client,
+    retries: &RetryPolicy,
+) -> Result<String, String> {
+    let commit_url = format!("{}/commits/{}", repo_url, reference);
+    let mut res = match retry::send(retries, client.get(&commit_url)) {
+        Ok(response) => response,
+        Err(e) => return Err(format!("Error in request to github for {}: {}", reference, e)),
+    };
+    delay_if_running_out_of_requests(res.headers());
+    if !res.status().is_success() {
+        return Err(format!("Github responded with {} looking up {}", res.status(), reference));
+    }
     let mut buffer = String::new();
     match res.read_to_string(&mut buffer) {
         Ok(_) => (),
-        Err(e) => println!("error checking commit diff for {}: {}", repo_url, e),
+        Err(e) => eprintln!("error reading commit {} for {}: {}", reference, repo_url, e),
     }
+    match serde_json::from_str::<Commit>(&buffer) {
+        Ok(commit) => Ok(commit.sha),
+        Err(e) => Err(format!("Couldn't deserialize commit {}: {}", reference, e)),
+    }
+}
 
-    let commits_diff: CompareCommitsResponse = match serde_json::from_str(&buffer) {
-        Ok(compare_response) => compare_response,
-        Err(_) => return true,
+// The commit list is newest first, one per page: the `last` page holds the very first commit.
+pub fn first_commit_on(
+    repo_url: &str,
+    branch: &str,
+    client: &reqwest::Client,
+    retries: &RetryPolicy,
+) -> Result<String, String> {
+    let commits_url = match Url::parse_with_params(
+        &format!("{}/commits", repo_url),
+        &[("sha", branch), ("per_page", "1")],
+    ) {
+        Ok(new_url) => new_url,
+        Err(e) => return Err(format!("Couldn't create url for commits: {}", e)),
+    };
+    let mut res = match retry::send(retries, client.get(commits_url)) {
+        Ok(response) => response,
+        Err(e) => return Err(format!("Error in request to github for commits: {}", e)),
     };
+    delay_if_running_out_of_requests(res.headers());
+    if let Ok(last_page) = response_link_for(res.headers(), &RelationType::Last) {
+        res = match retry::send(retries, client.get(last_page)) {
+            Ok(response) => response,
+            Err(e) => return Err(format!("Error in request to github for commits: {}", e)),
+        };
+        delay_if_running_out_of_requests(res.headers());
+    }
+    let mut buffer = String::new();
+    match res.read_to_string(&mut buffer) {
+        Ok(_) => (),
+        Err(e) => eprintln!("error reading commits for {}: {}", repo_url, e),
+    }
+    match serde_json::from_str::<Vec<Commit>>(&buffer) {
+        Ok(mut commits) => match commits.pop() {
+            Some(commit) => Ok(commit.sha),
+            None => Err(format!("No commits on {}", branch)),
+        },
+        Err(e) => Err(format!("Couldn't deserialize commits: {}", e)),
+    }
+}
 
-    if commits_diff.behind_by > 0 {
-        return false;
+pub fn create_branch(
+    repo_url: &str,
+    branch: &str,
+    sha: &str,
+    client: &reqwest::Client,
+    retries: &RetryPolicy,
+) -> Result<(), String> {
+    let mut ref_body = HashMap::new();
+    let full_ref = format!("refs/heads/{}", branch);
+    ref_body.insert("ref", full_ref.as_str());
+    ref_body.insert("sha", sha);
+
+    let refs_url = format!("{}/git/refs", repo_url);
+    let created = retry::send_once(retries, client.post(&refs_url).json(&ref_body), || {
+        match branch_exists(repo_url, branch, client, retries) {
+            Ok(true) => Some(()),
+            _ => None,
+        }
+    });
+    let res = match created {
+        Ok(Sent::Response(response)) => *response,
+        Ok(Sent::AlreadyDone(())) => return Ok(()),
+        Err(e) => return Err(format!("Error in request to github creating branch {}: {}", branch, e)),
+    };
+    delay_if_running_out_of_requests(res.headers());
+    if res.status().is_success() {
+        return Ok(());
     }
+    Err(format!(
+        "Github responded with {} creating branch {}",
+        res.status(),
+        branch
+    ))
+}
+
+// Required review counts were still in preview when this was written.
+static PROTECTION_PREVIEW: &str = "application/vnd.github.luke-cage-preview+json";
+
+// `Ok(None)` means the branch isn't protected at all.
+pub fn get_branch_protection(
+    repo_url: &str,
+    branch: &str,
+    client: &reqwest::Client,
+    retries: &RetryPolicy,
+) -> Result<Option<BranchProtection>, String> {
+    let protection_url = format!("{}/branches/{}/protection", repo_url, branch);
+    let mut res = match retry::send(retries, client.get(&protection_url).header(ACCEPT, PROTECTION_PREVIEW)) {
+        Ok(response) => response,
+        Err(e) => return Err(format!("Error in request to github for branch protection: {}", e)),
+    };
+    delay_if_running_out_of_requests(res.headers());
+
+    let mut buffer = String::new();
+    match res.read_to_string(&mut buffer) {
+        Ok(_) => (),
+        Err(e) => eprintln!("error reading branch protection for {}: {}", repo_url, e),
+    }
+    // GitHub 404s both a branch without protection and a branch that isn't there, only the message differs.
+    if res.status() == StatusCode::NOT_FOUND && branch_not_protected(&buffer) {
+        return Ok(None);
+    }
+    if !res.status().is_success() {
+        return Err(format!(
+            "Github responded with {} getting protection for {}: {}",
+            res.status(),
+            branch,
+            buffer
+        ));
+    }
+    match serde_json::from_str(&buffer) {
+        Ok(protection) => Ok(Some(protection)),
+        Err(e) => Err(format!("Couldn't deserialize branch protection: {}", e)),
+    }
+}
 
-    true
+fn branch_not_protected(body: &str) -> bool {
+    match serde_json::from_str::<serde_json::Value>(body) {
+        Ok(error) => error["message"] == "Branch not protected",
+        Err(_) => false,
+    }
+}
+
+pub fn set_branch_protection(
+    repo_url: &str,
+    branch: &str,
+    protection: &BranchProtectionUpdate,
+    client: &reqwest::Client,
+    retries: &RetryPolicy,
+) -> Result<(), String> {
+    let protection_url = format!("{}/branches/{}/protection", repo_url, branch);
+    let res = match retry::send(
+        retries,
+        client
+            .put(&protection_url)
+            .header(ACCEPT, PROTECTION_PREVIEW)
+            .json(protection),
+    ) {
+        Ok(response) => response,
+        Err(e) => return Err(format!("Error in request to github setting branch protection: {}", e)),
+    };
+    delay_if_running_out_of_requests(res.headers());
+    if res.status().is_success() {
+        return Ok(());
+    }
+    Err(format!(
+        "Github responded with {} setting protection for {}",
+        res.status(),
+        branch
+    ))
 }
 
 fn delay_if_running_out_of_requests(response_headers: &reqwest::header::HeaderMap) {
     if close_to_running_out_of_requests(response_headers) {
-        println!("Running low on requests, throttling back...");
+        eprintln!("Running low on requests, throttling back...");
         thread::sleep(time::Duration::from_millis(2000));
     }
 }
@@ -95,26 +500,16 @@ fn delay_if_running_out_of_requests(response_headers: &reqwest::header::HeaderMa
 // An improvement could be to use the fraction of requests left over the overall limit.
 // EG: 55 requests left out of a limit of 60 is fine.
 // 10 requests left out of 60 is time to throttle back.
-// TODO: dump the `expects` and handle things more gracefully.
+// Running out altogether is left to the retries, which wait for X-RateLimit-Reset, see `retry`.
 fn close_to_running_out_of_requests(response_headers: &reqwest::header::HeaderMap) -> bool {
     let requests_to_treat_as_running_out = 10;
-    let remaining_requests = match response_headers.get("X-RateLimit-Remaining") {
-        Some(remaining_req_from_github) => {
-            let req_left = remaining_req_from_github
-                .to_str()
-                .expect("Rate limit should be able to be a string")
-                .replace('"', ""); // the formatter puts quotes around the number.  EG: "55"
-            let int_req_left = req_left
-                .parse::<i32>()
-                .expect("Expected number in X-RateLimit-Remaining field");
-            if int_req_left == 0 {
-                panic!("We're out of requests, sad! Exiting poorly.");
-            }
-            int_req_left
-        }
-        // If it's not specified, we'll say we have enough to keep going:
-        None => requests_to_treat_as_running_out + 1,
-    };
+    // A header that's missing or doesn't make sense says we have enough to keep going.
+    let remaining_requests = response_headers
+        .get("X-RateLimit-Remaining")
+        .and_then(|remaining| remaining.to_str().ok())
+        // the formatter puts quotes around the number.  EG: "55"
+        .and_then(|remaining| remaining.replace('"', "").trim().parse::<i32>().ok())
+        .unwrap_or(requests_to_treat_as_running_out + 1);
     remaining_requests < requests_to_treat_as_running_out
 }
 
@@ -139,81 +534,66 @@ fn response_has_a_next_link(response_headers: &reqwest::header::HeaderMap) -> bo
 
 // Expects caller to check to ensure the `next` link is present
 fn response_next_link(response_headers: &reqwest::header::HeaderMap) -> Result<Url, String> {
+    response_link_for(response_headers, &RelationType::Next)
+}
+
+fn response_link_for(
+    response_headers: &reqwest::header::HeaderMap,
+    relation: &RelationType,
+) -> Result<Url, String> {
     let headers = Headers::from(response_headers.clone());
     if let Some(link) = headers.get::<Link>() {
         for l in link.values() {
             if let Some(r) = l.rel() {
                 // r will be a collection of relations
                 for rel in r {
-                    if rel == &RelationType::Next {
-                        let uri = Url::parse(l.link()).expect("Should have a nextlink");
-                        return Ok(uri);
+                    if rel == relation {
+                        return match Url::parse(l.link()) {
+                            Ok(uri) => Ok(uri),
+                            Err(e) => Err(format!("Couldn't parse {} link: {}", relation, e)),
+                        };
                     }
                 }
             }
         }
     }
-    Err("Couldn't find a next link: does it exist?".to_string())
+    Err(format!("Couldn't find a {} link: does it exist?", relation))
 }
 
-pub fn get_repos_at(repos_url: &str, client: &reqwest::Client) -> Result<Vec<GithubRepo>, String> {
+pub fn get_repos_at(
+    repos_url: &str,
+    client: &reqwest::Client,
+    retries: &RetryPolicy,
+    cache: Option<&ResponseCache>,
+) -> Result<Vec<GithubRepo>, String> {
     // We need to pass in the URL from the link headers to github API docs.
     // We'll construct it this first time.
     let url = match Url::parse_with_params(repos_url, &[("per_page", "50")]) {
         Ok(new_url) => new_url,
         Err(e) => return Err(format!("Couldn't parse uri {:?} : {:?}", repos_url, e)),
     };
-    let mut response = get_repos_at_url(url, client).expect("request failed");
-
-    if !response.status().is_success() {
-        let mut buffer = String::new();
-        match response.read_to_string(&mut buffer) {
-            Ok(_) => (),
-            Err(e) => println!(
-                "error reading response from github when getting repo list: {}",
-                e
-            ),
-        }
-        panic!("Error: Github responded with {:?}", buffer);
-    }
-    delay_if_running_out_of_requests(response.headers());
-    let mut buffer = String::new();
-    match response.read_to_string(&mut buffer) {
-        Ok(_) => (),
-        Err(e) => println!(
-            "error reading response from github when getting repo list: {}",
-            e
-        ),
-    }
-    let mut repos = repo_list_from_string(&buffer).expect("expected repos");
-
-    if response_has_a_next_link(response.headers()) {
-        loop {
-            let paging_url = response_next_link(response.headers()).expect("a thing");
-            response = get_repos_at_url(paging_url, client).expect("request failed");
-            delay_if_running_out_of_requests(response.headers());
-            buffer = String::new();
-            match response.read_to_string(&mut buffer) {
-                Ok(_) => (),
-                Err(e) => println!(
-                    "error reading response from github when getting repo list: {}",
-                    e
-                ),
-            }
-            repos.append(&mut repo_list_from_string(&buffer).expect("expected repos"));
-            if !response_has_a_next_link(response.headers()) {
-                break;
-            }
+    let mut repos = Vec::new();
+    let mut next_url = Some(url);
+    while let Some(url) = next_url {
+        let response = cache::get(client, retries, url, cache)?;
+        if !response.status.is_success() {
+            return Err(format!(
+                "Github responded with {} listing repos: {}",
+                response.status, response.body
+            ));
         }
+        delay_if_running_out_of_requests(&response.headers);
+        repos.append(&mut repo_list_from_string(&response.body)?);
+        next_url = if response_has_a_next_link(&response.headers) {
+            Some(response_next_link(&response.headers)?)
+        } else {
+            None
+        };
     }
-    println!("Number of repos to check: {:?}", repos.len());
+    eprintln!("Number of repos to check: {:?}", repos.len());
     Ok(repos)
 }
 
-fn get_repos_at_url(url: reqwest::Url, client: &reqwest::Client) -> Result<Response, Error> {
-    client.get(url).send()
-}
-
 fn repo_list_from_string(json_str: &str) -> Result<Vec<GithubRepo>, String> {
     // This looks a bit weird due to supplying type hints to deserialize:
     let _: Vec<GithubRepo> = match serde_json::from_str(json_str) {
@@ -222,20 +602,25 @@ fn repo_list_from_string(json_str: &str) -> Result<Vec<GithubRepo>, String> {
     };
 }
 
-pub fn existing_release_pr_location(repo: &GithubRepo, client: &reqwest::Client) -> Option<String> {
+pub fn existing_pr_location(
+    repo: &GithubRepo,
+    head: &str,
+    base: &str,
+    client: &reqwest::Client,
+    retries: &RetryPolicy,
+) -> Option<String> {
     let repo_pr_url = format!("{}/{}", repo.url, "pulls");
-    let url = match Url::parse_with_params(&repo_pr_url, &[("head", "master"), ("base", "release")])
-    {
+    let url = match Url::parse_with_params(&repo_pr_url, &[("head", head), ("base", base)]) {
         Ok(new_url) => new_url,
         Err(e) => {
-            println!("Couldn't create url for existing pr location: {}", e);
+            eprintln!("Couldn't create url for existing pr location: {}", e);
             return None;
         }
     };
-    let mut res = match client.get(url).send() {
+    let mut res = match retry::send(retries, client.get(url)) {
         Ok(response) => response,
         Err(e) => {
-            println!("Error in request to github for existing PR location: {}", e);
+            eprintln!("Error in request to github for existing PR location: {}", e);
             return None;
         }
     };
@@ -243,7 +628,7 @@ pub fn existing_release_pr_location(repo: &GithubRepo, client: &reqwest::Client)
     let mut buffer = String::new();
     match res.read_to_string(&mut buffer) {
         Ok(_) => (),
-        Err(e) => println!("error finding existing pr for {}: {}", repo.name, e),
+        Err(e) => eprintln!("error finding existing pr for {}: {}", repo.name, e),
     }
 
     let pull_reqs: Vec<GithubPullRequest> = match serde_json::from_str(&buffer) {
@@ -262,79 +647,211 @@ pub fn get_commits_from_pr(
     repo: &GithubRepo,
     pr_number: &str,
     client: &reqwest::Client,
-    rp_version: &str,
-) -> String {
-    let pr_commits_url = format!("{}/pulls/{}/commits", repo.url, pr_number);
+    retries: &RetryPolicy,
+    cache: Option<&ResponseCache>,
+) -> Result<Vec<CommitInPR>, String> {
+    let pr_commits_url = match Url::parse(&format!("{}/pulls/{}/commits", repo.url, pr_number)) {
+        Ok(new_url) => new_url,
+        Err(e) => return Err(format!("Couldn't create url for PR commits: {}", e)),
+    };
+    let res = cache::get(client, retries, pr_commits_url, cache)?;
+    delay_if_running_out_of_requests(&res.headers);
+
+    match serde_json::from_str(&res.body) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(format!(
+            "Couldn't deserialize PR commits from github: {}. Payload: {:#?}",
+            e, res.body
+        )),
+    }
+}
 
-    let mut res = match client.get(&pr_commits_url).send() {
+pub fn get_pr_body(
+    repo: &GithubRepo,
+    pr_number: &str,
+    client: &reqwest::Client,
+    retries: &RetryPolicy,
+) -> Result<Option<String>, String> {
+    let pr_url = format!("{}/pulls/{}", repo.url, pr_number);
+    let mut res = match retry::send(retries, client.get(&pr_url)) {
         Ok(response) => response,
-        Err(e) => {
-            panic!("Error in request to github for compare page: {}", e);
-        }
+        Err(e) => return Err(format!("Error in request to github for PR {}: {}", pr_number, e)),
     };
     delay_if_running_out_of_requests(res.headers());
+    if !res.status().is_success() {
+        return Err(format!("Github responded with {} looking up PR {}", res.status(), pr_number));
+    }
 
     let mut buffer = String::new();
     match res.read_to_string(&mut buffer) {
         Ok(_) => (),
-        Err(e) => println!("error checking commit diff for {}: {}", pr_commits_url, e),
+        Err(e) => eprintln!("error reading PR {} for {}: {}", pr_number, repo.url, e),
+    }
+    match serde_json::from_str::<GithubPullRequest>(&buffer) {
+        Ok(pr) => Ok(pr.body),
+        Err(e) => Err(format!("Couldn't deserialize PR {} from github: {}", pr_number, e)),
     }
+}
 
-    let prs: Vec<CommitInPR> = match serde_json::from_str(&buffer) {
-        Ok(v) => v,
-        Err(e) => panic!(format!("Couldn't deserialize repos from github: {}. Payload: {:#?}", e, buffer)),
+pub fn set_pr_body(
+    repo: &GithubRepo,
+    pr_number: &str,
+    body: &str,
+    client: &reqwest::Client,
+    retries: &RetryPolicy,
+) -> Result<(), String> {
+    let mut pr_body = HashMap::new();
+    pr_body.insert("body", body);
+
+    let repo_pr_url = format!("{}/pulls/{}", repo.url, pr_number);
+    let res = match retry::send(retries, client.patch(&repo_pr_url).json(&pr_body)) {
+        Ok(response) => response,
+        Err(e) => return Err(format!("Error in request to github updating PR body: {}", e)),
     };
+    delay_if_running_out_of_requests(res.headers());
+    if res.status().is_success() {
+        return Ok(());
+    }
+    Err(format!(
+        "Github responded with {} updating the body of PR {}",
+        res.status(),
+        pr_number
+    ))
+}
 
-    let mut new_body = "automated release partay!\n\nPRs in this release:".to_string();
+// PR comments live on the PR's issue. The first 100 are plenty to spot one of ours.
+pub fn get_pr_comments(
+    repo: &GithubRepo,
+    pr_number: &str,
+    client: &reqwest::Client,
+    retries: &RetryPolicy,
+) -> Result<Vec<String>, String> {
+    let comments_url = format!("{}/issues/{}/comments?per_page=100", repo.url, pr_number);
+    let mut res = match retry::send(retries, client.get(&comments_url)) {
+        Ok(response) => response,
+        Err(e) => return Err(format!("Error in request to github for comments on PR {}: {}", pr_number, e)),
+    };
+    delay_if_running_out_of_requests(res.headers());
+    if !res.status().is_success() {
+        return Err(format!(
+            "Github responded with {} looking up comments on PR {}",
+            res.status(),
+            pr_number
+        ));
+    }
 
-    for c in prs.into_iter() {
-        if c.actual_commit.message.contains("Merge pull request #") {
-            // remove the bits we don't need: go from "Merge pull request #1890 from..." to "#1890"
-            let pr_number = c.actual_commit.message.split(' ').collect::<Vec<&str>>()[3];
-            new_body.push_str(&format!("\n* {}", pr_number));
-        }
+    let mut buffer = String::new();
+    match res.read_to_string(&mut buffer) {
+        Ok(_) => (),
+        Err(e) => eprintln!("error reading comments on PR {} for {}: {}", pr_number, repo.url, e),
+    }
+    match serde_json::from_str::<Vec<IssueComment>>(&buffer) {
+        Ok(comments) => Ok(comments.into_iter().filter_map(|c| c.body).collect()),
+        Err(e) => Err(format!("Couldn't deserialize comments on PR {} from github: {}", pr_number, e)),
     }
+}
 
-    new_body.push_str(&format!("\n\n---\nMade by `{}`.", rp_version));
+pub fn comment_on_pr(
+    repo: &GithubRepo,
+    pr_number: &str,
+    body: &str,
+    client: &reqwest::Client,
+    retries: &RetryPolicy,
+) -> Result<(), String> {
+    let mut comment = HashMap::new();
+    comment.insert("body", body);
 
-    new_body
+    let comments_url = format!("{}/issues/{}/comments", repo.url, pr_number);
+    let commented = retry::send_once(retries, client.post(&comments_url).json(&comment), || {
+        match get_pr_comments(repo, pr_number, client, retries) {
+            Ok(ref comments) if host::has_comment(comments, body) => Some(()),
+            _ => None,
+        }
+    });
+    let res = match commented {
+        Ok(Sent::Response(response)) => *response,
+        Ok(Sent::AlreadyDone(())) => return Ok(()),
+        Err(e) => return Err(format!("Error in request to github commenting on PR {}: {}", pr_number, e)),
+    };
+    delay_if_running_out_of_requests(res.headers());
+    if res.status().is_success() {
+        return Ok(());
+    }
+    Err(format!("Github responded with {} commenting on PR {}", res.status(), pr_number))
 }
 
-pub fn set_pr_body(repo: &GithubRepo, pr_number: &str, body: &str, client: &reqwest::Client) {
-    let mut pr_body = HashMap::new();
-    pr_body.insert("body", body);
-
-    let repo_pr_url = format!("{}/pulls/{}", repo.url, pr_number);
-    let res = match client.patch(&repo_pr_url).json(&pr_body).send() {
+pub fn get_pr_labels(
+    repo: &GithubRepo,
+    pr_number: &str,
+    client: &reqwest::Client,
+    retries: &RetryPolicy,
+) -> Result<Vec<String>, String> {
+    let labels_url = format!("{}/issues/{}/labels?per_page=100", repo.url, pr_number);
+    let mut res = match retry::send(retries, client.get(&labels_url)) {
         Ok(response) => response,
-        Err(e) => panic!(format!("Error in request to github creating new PR: {}", e)),
+        Err(e) => return Err(format!("Error in request to github for labels on PR {}: {}", pr_number, e)),
     };
     delay_if_running_out_of_requests(res.headers());
+    if !res.status().is_success() {
+        return Err(format!(
+            "Github responded with {} looking up labels on PR {}",
+            res.status(),
+            pr_number
+        ));
+    }
+
+    let mut buffer = String::new();
+    if let Err(e) = res.read_to_string(&mut buffer) {
+        return Err(format!("Couldn't read the labels on PR {} from github: {}", pr_number, e));
+    }
+    match serde_json::from_str::<Vec<IssueLabel>>(&buffer) {
+        Ok(labels) => Ok(labels.into_iter().map(|l| l.name).collect()),
+        Err(e) => Err(format!("Couldn't deserialize labels on PR {} from github: {}", pr_number, e)),
+    }
 }
 
-pub fn update_pr_body(
+pub fn add_pr_label(
     repo: &GithubRepo,
     pr_number: &str,
+    label: &str,
     client: &reqwest::Client,
-    rp_version: &str,
-) {
-    let new_body = get_commits_from_pr(repo, pr_number, client, rp_version);
-    set_pr_body(repo, pr_number, &new_body, client);
+    retries: &RetryPolicy,
+) -> Result<(), String> {
+    let mut labels = HashMap::new();
+    labels.insert("labels", vec![label]);
+
+    let labels_url = format!("{}/issues/{}/labels", repo.url, pr_number);
+    let res = match retry::send(retries, client.post(&labels_url).json(&labels)) {
+        Ok(response) => response,
+        Err(e) => return Err(format!("Error in request to github labelling PR {}: {}", pr_number, e)),
+    };
+    delay_if_running_out_of_requests(res.headers());
+    if res.status().is_success() {
+        return Ok(());
+    }
+    Err(format!("Github responded with {} labelling PR {}", res.status(), pr_number))
 }
 
-// Try to create the release PR and return the URL of it:
-pub fn create_release_pull_request(
+pub fn create_pull_request(
     repo: &GithubRepo,
+    title: &str,
+    head: &str,
+    base: &str,
     client: &reqwest::Client,
+    retries: &RetryPolicy,
 ) -> Result<String, String> {
     let mut pr_body = HashMap::new();
-    pr_body.insert("title", "automated release partay");
-    pr_body.insert("head", "master");
-    pr_body.insert("base", "release");
+    pr_body.insert("title", title);
+    pr_body.insert("head", head);
+    pr_body.insert("base", base);
 
     let repo_pr_url = format!("{}/{}", repo.url, "pulls");
-    let mut res = match client.post(&repo_pr_url).json(&pr_body).send() {
-        Ok(response) => response,
+    let created = retry::send_once(retries, client.post(&repo_pr_url).json(&pr_body), || {
+        existing_pr_location(repo, head, base, client, retries)
+    });
+    let mut res = match created {
+        Ok(Sent::Response(response)) => *response,
+        Ok(Sent::AlreadyDone(pr_url)) => return Ok(pr_url),
         Err(e) => return Err(format!("Error in request to github creating new PR: {}", e)),
     };
 
@@ -343,8 +860,8 @@ pub fn create_release_pull_request(
         let mut buffer = String::new();
         match res.read_to_string(&mut buffer) {
             Ok(_) => (),
-            Err(e) => println!(
-                "error reading response after creating new release PR for {}: {}",
+            Err(e) => eprintln!(
+                "error reading response after creating new PR for {}: {}",
                 repo.name, e
             ),
         }
@@ -362,7 +879,327 @@ pub fn create_release_pull_request(
     // 422 unprocessable means it's there already
     // 422 unprocessable also means the branch is up to date
 
-    Err("Release branch already up to date?".to_string())
+    Err(format!("{} branch already up to date with {}?", base, head))
+}
+
+/// Who a token belongs to and, for OAuth apps and classic personal tokens, what it's allowed to do.
+#[derive(Debug, PartialEq)]
+pub struct Identity {
+    pub login: String,
+    // From the X-OAuth-Scopes header. Fine-grained tokens don't send it, so there's nothing to check.
+    pub scopes: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct User {
+    login: String,
+}
+
+pub fn whoami(api_url: &str, client: &reqwest::Client, retries: &RetryPolicy) -> Result<Identity, String> {
+    let user_url = format!("{}/user", api_url.trim_end_matches('/'));
+    let mut res = match retry::send(retries, client.get(&user_url)) {
+        Ok(response) => response,
+        Err(e) => return Err(format!("Error in request to github for the token's user: {}", e)),
+    };
+    delay_if_running_out_of_requests(res.headers());
+    if !res.status().is_success() {
+        return Err(format!("Github responded with {} checking the token", res.status()));
+    }
+    let scopes = res
+        .headers()
+        .get("X-OAuth-Scopes")
+        .and_then(|scopes| scopes.to_str().ok())
+        .map(|scopes| {
+            scopes
+                .split(',')
+                .map(|scope| scope.trim().to_owned())
+                .filter(|scope| !scope.is_empty())
+                .collect()
+        });
+
+    let mut buffer = String::new();
+    match res.read_to_string(&mut buffer) {
+        Ok(_) => (),
+        Err(e) => eprintln!("error reading the token's user from {}: {}", user_url, e),
+    }
+    match serde_json::from_str::<User>(&buffer) {
+        Ok(user) => Ok(Identity {
+            login: user.login,
+            scopes,
+        }),
+        Err(e) => Err(format!("Couldn't deserialize the token's user: {}", e)),
+    }
+}
+
+/// The scopes in `needed` the token doesn't have. Empty when the token's scopes aren't known.
+pub fn missing_scopes(identity: &Identity, needed: &[&str]) -> Vec<String> {
+    match identity.scopes {
+        Some(ref scopes) => needed
+            .iter()
+            .filter(|scope| !scopes.iter().any(|s| s == *scope))
+            .map(|scope| scope.to_string())
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// How requests to GitHub authenticate.
+pub enum Credential {
+    /// A personal access token, or a GitHub App installation token.
+    Token(String),
+    /// A GitHub App's signed JWT, only good for asking for installation tokens.
+    AppJwt(String),
+}
+
+/// A client that sends the credential and our user agent with every request.
+pub fn client_for(credential: &Credential, http: &HttpSettings) -> reqwest::Client {
+    let authorization = match *credential {
+        Credential::Token(ref token) => format!("token {}", token),
+        Credential::AppJwt(ref jwt) => format!("Bearer {}", jwt),
+    };
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert(
+        USER_AGENT,
+        USERAGENT.parse().expect("useragent should be a string"),
+    );
+    headers.insert(
+        AUTHORIZATION,
+        authorization.parse().expect("token should be a string"),
+    );
+    match http.builder().default_headers(headers).build() {
+        Ok(new_client) => new_client,
+        Err(e) => panic!("Couldn't create new reqwest client: {}", e),
+    }
+}
+
+pub fn client_for_token(token: &str, http: &HttpSettings) -> reqwest::Client {
+    client_for(&Credential::Token(token.to_owned()), http)
+}
+
+/// Talks to the GitHub v3 REST API.
+pub struct GithubClient {
+    client: RefCell<reqwest::Client>,
+    app: Option<AppInstallation>,
+    cache: Option<ResponseCache>,
+    retries: RetryPolicy,
+    // Set when repos are looked up in GraphQL batches, with what the latest batch found.
+    graphql_url: Option<String>,
+    snapshots: RefCell<HashMap<String, RepoSnapshot>>,
+}
+
+// A GitHub App installation whose token gets swapped for a fresh one before it expires.
+struct AppInstallation {
+    app: GithubApp,
+    api_url: String,
+    org: String,
+    token: RefCell<InstallationToken>,
+}
+
+impl GithubClient {
+    pub fn new(client: reqwest::Client) -> GithubClient {
+        GithubClient {
+            client: RefCell::new(client),
+            app: None,
+            cache: None,
+            retries: RetryPolicy::default(),
+            graphql_url: None,
+            snapshots: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Authenticates as `app`'s installation on `org`.
+    pub fn for_app(app: GithubApp, api_url: &str, org: &str) -> Result<GithubClient, String> {
+        let token = app.installation_token(api_url, org)?;
+        Ok(GithubClient {
+            client: RefCell::new(client_for(&Credential::Token(token.token.clone()), app.http())),
+            app: Some(AppInstallation {
+                app,
+                api_url: api_url.to_owned(),
+                org: org.to_owned(),
+                token: RefCell::new(token),
+            }),
+            cache: None,
+            retries: RetryPolicy::default(),
+            graphql_url: None,
+            snapshots: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Revalidates repo listings, compares and PR commits against `cache` instead of fetching them again.
+    pub fn with_cache(mut self, cache: ResponseCache) -> GithubClient {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Retries requests that fail transiently as `retries` says, instead of three times.
+    pub fn with_retries(mut self, retries: RetryPolicy) -> GithubClient {
+        self.retries = retries;
+        self
+    }
+
+    /// Looks repos up in batches through the GraphQL API next to `api_url`, so the compare and PR
+    /// lookups of repos with nothing to release cost no REST requests.
+    pub fn with_graphql(mut self, api_url: &str) -> GithubClient {
+        self.graphql_url = Some(graphql::graphql_url(api_url));
+        self
+    }
+
+    fn snapshot(&self, repo: &GithubRepo) -> Option<RepoSnapshot> {
+        self.snapshots.borrow().get(&repo.url).cloned()
+    }
+
+    // Whatever a batch said about the repo is out of date once we change it.
+    fn forget_snapshot(&self, repo: &GithubRepo) {
+        self.snapshots.borrow_mut().remove(&repo.url);
+    }
+
+    pub fn whoami(&self, api_url: &str) -> Result<Identity, String> {
+        whoami(api_url, &self.client(), &self.retries)
+    }
+
+    pub fn is_app(&self) -> bool {
+        self.app.is_some()
+    }
+
+    fn client(&self) -> reqwest::Client {
+        if let Some(ref installation) = self.app {
+            if installation.token.borrow().needs_refresh(app::unix_now()) {
+                match installation.app.installation_token(&installation.api_url, &installation.org) {
+                    Ok(token) => {
+                        *self.client.borrow_mut() =
+                            client_for(&Credential::Token(token.token.clone()), installation.app.http());
+                        *installation.token.borrow_mut() = token;
+                    }
+                    Err(e) => eprintln!("Couldn't refresh the GitHub App installation token: {}", e),
+                }
+            }
+        }
+        self.client.borrow().clone()
+    }
+}
+
+impl ReleaseHost for GithubClient {
+    fn list_repos(&self, org_url: &str) -> Result<Vec<GithubRepo>, String> {
+        get_repos_at(org_url, &self.client(), &self.retries, self.cache.as_ref())
+    }
+
+    fn compare(&self, repo: &GithubRepo, base: &str, head: &str) -> Result<CompareCommitsResponse, String> {
+        if (base, head) == ("release", "master") {
+            match self.snapshot(repo) {
+                Some(ref snapshot) if !snapshot.has_release_branch => {
+                    return Err(format!("{} has no release branch", repo.name))
+                }
+                Some(snapshot) => {
+                    if let Some(compare) = snapshot.compare() {
+                        return Ok(compare);
+                    }
+                }
+                None => (),
+            }
+        }
+        compare_branches(&repo.url, base, head, &self.client(), &self.retries, self.cache.as_ref())
+    }
+
+    fn find_pr(&self, repo: &GithubRepo, head: &str, base: &str) -> Option<String> {
+        if let Some(snapshot) = self.snapshot(repo) {
+            match (head, base) {
+                ("master", "release") => return snapshot.release_pr,
+                ("release", "master") => return snapshot.back_merge_pr,
+                _ => (),
+            }
+        }
+        existing_pr_location(repo, head, base, &self.client(), &self.retries)
+    }
+
+    fn create_pr(&self, repo: &GithubRepo, title: &str, head: &str, base: &str) -> Result<String, String> {
+        self.forget_snapshot(repo);
+        create_pull_request(repo, title, head, base, &self.client(), &self.retries)
+    }
+
+    fn pr_commits(&self, repo: &GithubRepo, pr_number: &str) -> Result<Vec<CommitInPR>, String> {
+        get_commits_from_pr(repo, pr_number, &self.client(), &self.retries, self.cache.as_ref())
+    }
+
+    fn pr_body(&self, repo: &GithubRepo, pr_number: &str) -> Result<Option<String>, String> {
+        get_pr_body(repo, pr_number, &self.client(), &self.retries)
+    }
+
+    fn update_pr_body(&self, repo: &GithubRepo, pr_number: &str, body: &str) -> Result<(), String> {
+        set_pr_body(repo, pr_number, body, &self.client(), &self.retries)
+    }
+
+    fn pr_comments(&self, repo: &GithubRepo, pr_number: &str) -> Result<Vec<String>, String> {
+        get_pr_comments(repo, pr_number, &self.client(), &self.retries)
+    }
+
+    fn comment_on_pr(&self, repo: &GithubRepo, pr_number: &str, body: &str) -> Result<(), String> {
+        comment_on_pr(repo, pr_number, body, &self.client(), &self.retries)
+    }
+
+    fn pr_labels(&self, repo: &GithubRepo, pr_number: &str) -> Result<Vec<String>, String> {
+        get_pr_labels(repo, pr_number, &self.client(), &self.retries)
+    }
+
+    fn add_pr_label(&self, repo: &GithubRepo, pr_number: &str, label: &str) -> Result<(), String> {
+        add_pr_label(repo, pr_number, label, &self.client(), &self.retries)
+    }
+
+    fn tags(&self, repo: &GithubRepo) -> Vec<GithubTag> {
+        get_tags(&repo.url, &self.client(), &self.retries)
+    }
+
+    fn branch_exists(&self, repo: &GithubRepo, branch: &str) -> Result<bool, String> {
+        if branch == "release" {
+            if let Some(snapshot) = self.snapshot(repo) {
+                return Ok(snapshot.has_release_branch);
+            }
+        }
+        branch_exists(&repo.url, branch, &self.client(), &self.retries)
+    }
+
+    fn commit_sha(&self, repo: &GithubRepo, reference: &str) -> Result<String, String> {
+        commit_sha_for(&repo.url, reference, &self.client(), &self.retries)
+    }
+
+    fn first_commit(&self, repo: &GithubRepo, branch: &str) -> Result<String, String> {
+        first_commit_on(&repo.url, branch, &self.client(), &self.retries)
+    }
+
+    fn create_branch(&self, repo: &GithubRepo, branch: &str, sha: &str) -> Result<(), String> {
+        self.forget_snapshot(repo);
+        create_branch(&repo.url, branch, sha, &self.client(), &self.retries)
+    }
+
+    fn branch_protection(&self, repo: &GithubRepo, branch: &str) -> Result<Option<BranchProtection>, String> {
+        get_branch_protection(&repo.url, branch, &self.client(), &self.retries)
+    }
+
+    fn set_branch_protection(
+        &self,
+        repo: &GithubRepo,
+        branch: &str,
+        protection: &BranchProtectionUpdate,
+    ) -> Result<(), String> {
+        set_branch_protection(&repo.url, branch, protection, &self.client(), &self.retries)
+    }
+
+    fn batch_size(&self) -> Option<usize> {
+        self.graphql_url.as_ref().map(|_| graphql::BATCH_SIZE)
+    }
+
+    fn prefetch(&self, repos: &[&GithubRepo]) {
+        let graphql_url = match self.graphql_url {
+            Some(ref url) => url,
+            None => return,
+        };
+        match graphql::fetch_batch(graphql_url, repos, &self.client(), &self.retries) {
+            Ok(snapshots) => *self.snapshots.borrow_mut() = snapshots,
+            Err(e) => {
+                eprintln!("Couldn't look up a batch of repos, checking them one by one: {}", e);
+                self.snapshots.borrow_mut().clear();
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -391,6 +1228,65 @@ mod tests {
         );
     }
 
+    #[test]
+    fn missing_scopes_only_when_known() {
+        let mut identity = Identity {
+            login: "party-bot".to_owned(),
+            scopes: Some(vec!["read:org".to_owned(), "user".to_owned()]),
+        };
+        assert_eq!(vec!["repo".to_owned()], missing_scopes(&identity, &["repo"]));
+        identity.scopes = Some(vec!["repo".to_owned()]);
+        assert!(missing_scopes(&identity, &["repo"]).is_empty());
+        identity.scopes = None;
+        assert!(missing_scopes(&identity, &["repo"]).is_empty());
+    }
+
+    #[test]
+    fn compare_response_keeps_commits_and_files() {
+        let json = r#"{
+            "status": "diverged",
+            "ahead_by": 2,
+            "behind_by": 1,
+            "total_commits": 2,
+            "commits": [
+                {"sha": "abc123", "commit": {"message": "feat: new thing"}},
+                {"sha": "def456", "commit": {"message": "fix: old thing"}}
+            ],
+            "files": [
+                {"filename": "src/main.rs", "status": "modified", "additions": 10, "deletions": 2}
+            ]
+        }"#;
+        let compare: CompareCommitsResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(2, compare.ahead_by);
+        assert_eq!(1, compare.behind_by);
+        assert_eq!(2, compare.commits.len());
+        assert_eq!("src/main.rs", compare.files[0].filename);
+    }
+
+    #[test]
+    fn finds_last_link() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            LINK,
+            "<http://example.com/commits?page=2>; rel=\"next\", <http://example.com/commits?page=40>; rel=\"last\""
+                .parse()
+                .unwrap(),
+        );
+
+        let expected_uri = Url::parse("http://example.com/commits?page=40").unwrap();
+        assert_eq!(
+            expected_uri,
+            response_link_for(&headers, &RelationType::Last).unwrap()
+        );
+    }
+
+    #[test]
+    fn unprotected_and_missing_branches_404_differently() {
+        assert!(branch_not_protected(r#"{"message": "Branch not protected", "documentation_url": ""}"#));
+        assert!(!branch_not_protected(r#"{"message": "Branch not found", "documentation_url": ""}"#));
+        assert!(!branch_not_protected("<html>Not Found</html>"));
+    }
+
     #[test]
     fn no_next_link() {
         assert_eq!(false, response_has_a_next_link(&HeaderMap::new()));
@@ -0,0 +1,188 @@
+extern crate reqwest;
+
+use self::reqwest::header::{HeaderMap, RETRY_AFTER};
+use self::reqwest::{Error, RequestBuilder, Response, StatusCode};
+
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::app::unix_now;
+
+/// How many times, and how patiently, to retry a request that failed in a way that may well go away:
+/// a timeout, a dropped connection, a 502, 503 or 504, or a rate limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    // The first retry waits up to this long, doubling each time after.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy::new(3)
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+
+    /// Half the backoff for `attempt` plus up to another half at random, so parallel runs don't
+    /// all come back at the same moment.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self.base_delay.checked_mul(1 << attempt.min(16)).unwrap_or(self.max_delay);
+        let ceiling = exponential.min(self.max_delay);
+        let half = ceiling / 2;
+        let jitter_millis = half.as_millis() as u64 + 1;
+        half + Duration::from_millis(random() % jitter_millis)
+    }
+
+    /// How long to wait before retrying after `result`, or `None` when it shouldn't be retried:
+    /// it worked, failed for good, or this was the last attempt.
+    pub fn wait_before_retry(&self, result: &Result<Response, Error>, attempt: u32) -> Option<Duration> {
+        if attempt >= self.max_retries {
+            return None;
+        }
+        match *result {
+            Err(ref e) if e.is_timeout() || e.is_http() => Some(self.backoff(attempt)),
+            Err(_) => None,
+            Ok(ref res) => match res.status() {
+                StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT => {
+                    Some(self.backoff(attempt))
+                }
+                // GitHub's rate limits are a 403 or 429, saying when to come back with Retry-After or, once
+                // the requests are used up, X-RateLimit-Reset. A 429 saying neither still wants a minute's
+                // rest, but a 403 saying neither is a permissions problem that waiting won't fix.
+                status @ StatusCode::FORBIDDEN | status @ StatusCode::TOO_MANY_REQUESTS => {
+                    let wait = match (retry_after(res.headers()), rate_limit_reset(res.headers(), unix_now())) {
+                        (Some(wait), _) | (None, Some(wait)) => Some(wait),
+                        (None, None) if status == StatusCode::TOO_MANY_REQUESTS => Some(SECONDARY_RATE_LIMIT_WAIT),
+                        (None, None) => None,
+                    };
+                    wait.map(|wait| wait.max(self.backoff(attempt)))
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+// What GitHub asks of clients that hit a secondary rate limit without being told how long to wait.
+const SECONDARY_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+// With no requests left, how long until X-RateLimit-Reset (in seconds since the epoch) gives more.
+fn rate_limit_reset(headers: &HeaderMap, now: u64) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).map(|value| value.trim());
+    if header("x-ratelimit-remaining") != Some("0") {
+        return None;
+    }
+    let reset = header("x-ratelimit-reset")?.parse::<u64>().ok()?;
+    // A second late beats a second early.
+    Some(Duration::from_secs(reset.saturating_sub(now) + 1))
+}
+
+// Good enough randomness for jitter, without pulling in a crate for it.
+fn random() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.subsec_nanos())
+        .unwrap_or(0);
+    u64::from(nanos).wrapping_mul(6_364_136_223_846_793_005) >> 16
+}
+
+/// Sends `request`, sending it again after a backoff for as long as it fails transiently and the
+/// policy allows. Only for requests that are safe to repeat, see `github::create_pull_request`.
+pub fn send(retries: &RetryPolicy, request: RequestBuilder) -> Result<Response, Error> {
+    match send_once(retries, request, || None::<()>)? {
+        Sent::Response(response) => Ok(*response),
+        Sent::AlreadyDone(()) => unreachable!(),
+    }
+}
+
+/// What `send_once` got: GitHub's response, or what an earlier attempt turned out to have made.
+pub enum Sent<T> {
+    Response(Box<Response>),
+    AlreadyDone(T),
+}
+
+/// `send` for requests that mustn't happen twice, like opening a PR. A request that timed out or got
+/// a 502 may have gone through anyway, so before each retry `already_done` checks, and what it finds
+/// is given back instead of sending the request again.
+pub fn send_once<T, F>(retries: &RetryPolicy, request: RequestBuilder, mut already_done: F) -> Result<Sent<T>, Error>
+where
+    F: FnMut() -> Option<T>,
+{
+    let mut request = request;
+    let mut attempt = 0;
+    loop {
+        let again = request.try_clone();
+        let result = request.send();
+        let (again, wait) = match (again, retries.wait_before_retry(&result, attempt)) {
+            (Some(again), Some(wait)) => (again, wait),
+            _ => return result.map(|response| Sent::Response(Box::new(response))),
+        };
+        eprintln!("{}, retrying in {}ms...", describe_failure(&result), wait.as_millis());
+        thread::sleep(wait);
+        if let Some(done) = already_done() {
+            return Ok(Sent::AlreadyDone(done));
+        }
+        attempt += 1;
+        request = again;
+    }
+}
+
+// What went wrong with a request worth retrying, for the retry message.
+fn describe_failure(result: &Result<Response, Error>) -> String {
+    match *result {
+        Ok(ref res) => format!("Github responded with {} to {}", res.status(), res.url()),
+        Err(ref e) => format!("Request to github failed: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_with_jitter_up_to_the_max() {
+        let retries = RetryPolicy::new(5);
+        for attempt in 0..8 {
+            let ceiling = (retries.base_delay * (1 << attempt)).min(retries.max_delay);
+            let wait = retries.backoff(attempt);
+            assert!(wait >= ceiling / 2 && wait <= ceiling, "attempt {}: {:?}", attempt, wait);
+        }
+    }
+
+    #[test]
+    fn retry_after_is_in_seconds() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(None, retry_after(&headers));
+        headers.insert(RETRY_AFTER, "7".parse().unwrap());
+        assert_eq!(Some(Duration::from_secs(7)), retry_after(&headers));
+    }
+
+    #[test]
+    fn used_up_requests_wait_for_the_reset() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-reset", "1000".parse().unwrap());
+        headers.insert("x-ratelimit-remaining", "12".parse().unwrap());
+        assert_eq!(None, rate_limit_reset(&headers, 900));
+        headers.insert("x-ratelimit-remaining", "0".parse().unwrap());
+        assert_eq!(Some(Duration::from_secs(101)), rate_limit_reset(&headers, 900));
+        assert_eq!(Some(Duration::from_secs(1)), rate_limit_reset(&headers, 1200));
+    }
+}
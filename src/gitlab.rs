@@ -0,0 +1,525 @@
+extern crate reqwest;
+extern crate serde_json;
+
+use self::reqwest::header::{HeaderMap, USER_AGENT};
+use self::reqwest::Url;
+
+use std::collections::{HashMap, HashSet};
+
+use forge::{self, branch_protection_unsupported, pr_comments_unsupported, pr_labels_unsupported, GITLAB};
+use github::{
+    ActualCommitInPR, BranchProtection, BranchProtectionUpdate, ChangedFile, Commit, CommitInPR,
+    CompareCommitsResponse, GithubRepo, GithubTag, RepoPermissions,
+};
+use host::ReleaseHost;
+use http::HttpSettings;
+
+static USERAGENT: &str = "release-party-br";
+
+// GitLab's access levels: developers and up can push and open merge requests.
+const DEVELOPER_ACCESS: u32 = 30;
+const MAINTAINER_ACCESS: u32 = 40;
+
+#[derive(Deserialize, Debug)]
+struct Project {
+    id: u64,
+    path_with_namespace: String,
+    #[serde(default)]
+    permissions: Option<ProjectPermissions>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ProjectPermissions {
+    project_access: Option<Access>,
+    group_access: Option<Access>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Access {
+    access_level: u32,
+}
+
+#[derive(Deserialize, Debug)]
+struct Compare {
+    #[serde(default)]
+    commits: Vec<GitlabCommit>,
+    #[serde(default)]
+    diffs: Vec<Diff>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GitlabCommit {
+    id: String,
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Diff {
+    new_path: String,
+    #[serde(default)]
+    new_file: bool,
+    #[serde(default)]
+    renamed_file: bool,
+    #[serde(default)]
+    deleted_file: bool,
+    #[serde(default)]
+    diff: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct MergeRequest {
+    web_url: String,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Tag {
+    name: String,
+    commit: GitlabCommitId,
+}
+
+#[derive(Deserialize, Debug)]
+struct GitlabCommitId {
+    id: String,
+}
+
+/// Talks to the GitLab v4 REST API: a group's projects stand in for an org's repos and merge requests for PRs.
+pub struct GitlabClient {
+    client: reqwest::Client,
+}
+
+impl GitlabClient {
+    pub fn new(client: reqwest::Client) -> GitlabClient {
+        GitlabClient { client }
+    }
+
+    // Every project in the group and its subgroups, or only those we have at least `min_access_level` on.
+    fn group_projects(&self, org_url: &str, min_access_level: Option<u32>) -> Result<Vec<Project>, String> {
+        let min_access_level = min_access_level.map(|level| level.to_string());
+        let mut projects = Vec::new();
+        let mut page = "1".to_owned();
+        loop {
+            let mut params = vec![
+                ("include_subgroups", "true"),
+                ("archived", "false"),
+                ("per_page", "100"),
+                ("page", page.as_str()),
+            ];
+            if let Some(ref level) = min_access_level {
+                params.push(("min_access_level", level.as_str()));
+            }
+            let url = match Url::parse_with_params(org_url, &params) {
+                Ok(url) => url,
+                Err(e) => return Err(format!("Couldn't parse uri {:?} : {:?}", org_url, e)),
+            };
+            let (buffer, headers) = GITLAB.send(self.client.get(url), "the group's projects")?;
+            let mut listed: Vec<Project> = match serde_json::from_str(&buffer) {
+                Ok(listed) => listed,
+                Err(e) => return Err(format!("Couldn't deserialize projects from gitlab: {}", e)),
+            };
+            projects.append(&mut listed);
+            match next_page(&headers) {
+                Some(next) => page = next,
+                None => return Ok(projects),
+            }
+        }
+    }
+
+    // Every page of the project's tags: the latest version can be anywhere in them.
+    fn repo_tags(&self, repo: &GithubRepo) -> Result<Vec<Tag>, String> {
+        let mut tags = Vec::new();
+        let mut page = "1".to_owned();
+        loop {
+            let url = format!("{}/repository/tags?per_page=100&page={}", repo.url, page);
+            let (buffer, headers) = GITLAB.send(self.client.get(&url), &format!("tags of {}", repo.name))?;
+            let mut listed: Vec<Tag> = match serde_json::from_str(&buffer) {
+                Ok(listed) => listed,
+                Err(e) => return Err(format!("Couldn't deserialize tags of {} from gitlab: {}", repo.name, e)),
+            };
+            tags.append(&mut listed);
+            match next_page(&headers) {
+                Some(next) => page = next,
+                None => return Ok(tags),
+            }
+        }
+    }
+
+    fn compare_refs(&self, repo: &GithubRepo, from: &str, to: &str) -> Result<Compare, String> {
+        let url = match Url::parse_with_params(
+            &format!("{}/repository/compare", repo.url),
+            &[("from", from), ("to", to)],
+        ) {
+            Ok(url) => url,
+            Err(e) => return Err(format!("Couldn't create url for compare: {}", e)),
+        };
+        GITLAB.send_for_json(self.client.get(url), &format!("compare {}...{} of {}", from, to, repo.name))
+    }
+
+    fn merge_request(&self, repo: &GithubRepo, iid: &str) -> Result<MergeRequest, String> {
+        let url = format!("{}/merge_requests/{}", repo.url, iid);
+        GITLAB.send_for_json(self.client.get(&url), &format!("merge request !{} of {}", iid, repo.name))
+    }
+}
+
+/// A client that sends a GitLab personal access token and our user agent with every request.
+pub fn client_for_token(token: &str, http: &HttpSettings) -> reqwest::Client {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        USER_AGENT,
+        USERAGENT.parse().expect("useragent should be a string"),
+    );
+    headers.insert("PRIVATE-TOKEN", token.parse().expect("token should be a string"));
+    match http.builder().default_headers(headers).build() {
+        Ok(new_client) => new_client,
+        Err(e) => panic!("Couldn't create new reqwest client: {}", e),
+    }
+}
+
+/// The projects URL for a group, subgroups included. Nested groups look like `parent/child`.
+pub fn group_projects_url(api_url: &str, group: &str) -> String {
+    format!("{}/groups/{}/projects", api_url, forge::encode_segment(group))
+}
+
+// GitLab sends the next page number in X-Next-Page, empty on the last page.
+fn next_page(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("X-Next-Page")
+        .and_then(|page| page.to_str().ok())
+        .map(|page| page.trim().to_owned())
+        .filter(|page| !page.is_empty())
+}
+
+fn repo_from_project(project: Project, api_url: &str) -> GithubRepo {
+    let mut repo = GithubRepo::new(
+        &project.path_with_namespace,
+        &format!("{}/projects/{}", api_url, project.id),
+    );
+    repo.permissions = project.permissions.map(|permissions| {
+        let level = permissions
+            .project_access
+            .iter()
+            .chain(permissions.group_access.iter())
+            .map(|access| access.access_level)
+            .max()
+            .unwrap_or(0);
+        RepoPermissions {
+            admin: level >= MAINTAINER_ACCESS,
+            push: level >= DEVELOPER_ACCESS,
+            pull: level > 0,
+        }
+    });
+    repo
+}
+
+// Turns a GitLab compare into GitHub's shape: GitLab only lists what `head` has, `behind` is a second compare.
+fn compare_response(ahead: Compare, behind: Compare) -> CompareCommitsResponse {
+    let ahead_by = ahead.commits.len() as i32;
+    let behind_by = behind.commits.len() as i32;
+    let status = match (ahead_by, behind_by) {
+        (0, 0) => "identical",
+        (_, 0) => "ahead",
+        (0, _) => "behind",
+        _ => "diverged",
+    };
+    CompareCommitsResponse {
+        status: status.to_owned(),
+        ahead_by,
+        behind_by,
+        total_commits: ahead_by,
+        commits: ahead.commits.into_iter().map(commit_in_pr).collect(),
+        files: ahead.diffs.iter().map(changed_file).collect(),
+    }
+}
+
+fn commit_in_pr(commit: GitlabCommit) -> CommitInPR {
+    CommitInPR {
+        sha: commit.id,
+        actual_commit: ActualCommitInPR {
+            message: commit.message,
+        },
+    }
+}
+
+fn changed_file(diff: &Diff) -> ChangedFile {
+    let status = if diff.new_file {
+        "added"
+    } else if diff.deleted_file {
+        "removed"
+    } else if diff.renamed_file {
+        "renamed"
+    } else {
+        "modified"
+    };
+    let lines = diff.diff.lines();
+    ChangedFile {
+        filename: diff.new_path.clone(),
+        status: status.to_owned(),
+        additions: lines.clone().filter(|l| l.starts_with('+') && !l.starts_with("+++")).count() as i32,
+        deletions: lines.filter(|l| l.starts_with('-') && !l.starts_with("---")).count() as i32,
+    }
+}
+
+impl ReleaseHost for GitlabClient {
+    fn list_repos(&self, org_url: &str) -> Result<Vec<GithubRepo>, String> {
+        // The API root, to build each project's URL from: everything before /groups/.
+        let api_url = match org_url.find("/groups/") {
+            Some(index) => &org_url[..index],
+            None => return Err(format!("{} isn't a GitLab group projects URL", org_url)),
+        };
+        let projects = self.group_projects(org_url, None)?;
+        // Group listings don't say what we may do with each project, so ask again for the ones we can push to.
+        let pushable: HashSet<u64> = self
+            .group_projects(org_url, Some(DEVELOPER_ACCESS))?
+            .iter()
+            .map(|project| project.id)
+            .collect();
+        let repos: Vec<GithubRepo> = projects
+            .into_iter()
+            .map(|project| {
+                let can_push = pushable.contains(&project.id);
+                let mut repo = repo_from_project(project, api_url);
+                if repo.permissions.is_none() {
+                    repo.permissions = Some(RepoPermissions {
+                        admin: false,
+                        push: can_push,
+                        pull: true,
+                    });
+                }
+                repo
+            })
+            .collect();
+        eprintln!("Number of repos to check: {:?}", repos.len());
+        Ok(repos)
+    }
+
+    fn compare(&self, repo: &GithubRepo, base: &str, head: &str) -> Result<CompareCommitsResponse, String> {
+        let ahead = self.compare_refs(repo, base, head)?;
+        let behind = self.compare_refs(repo, head, base)?;
+        Ok(compare_response(ahead, behind))
+    }
+
+    fn find_pr(&self, repo: &GithubRepo, head: &str, base: &str) -> Option<String> {
+        let url = match Url::parse_with_params(
+            &format!("{}/merge_requests", repo.url),
+            &[("state", "opened"), ("source_branch", head), ("target_branch", base)],
+        ) {
+            Ok(url) => url,
+            Err(e) => {
+                eprintln!("Couldn't create url for existing merge request location: {}", e);
+                return None;
+            }
+        };
+        let merge_requests: Vec<MergeRequest> =
+            match GITLAB.send_for_json(self.client.get(url), &format!("merge requests of {}", repo.name)) {
+                Ok(merge_requests) => merge_requests,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return None;
+                }
+            };
+        merge_requests.into_iter().next().map(|mr| mr.web_url)
+    }
+
+    fn create_pr(&self, repo: &GithubRepo, title: &str, head: &str, base: &str) -> Result<String, String> {
+        let mut mr_body = HashMap::new();
+        mr_body.insert("title", title);
+        mr_body.insert("source_branch", head);
+        mr_body.insert("target_branch", base);
+
+        let url = format!("{}/merge_requests", repo.url);
+        let what = format!("a new merge request for {}", repo.name);
+        // GitLab answers 409 Conflict when there's one open already.
+        let mr: MergeRequest = GITLAB.send_for_json(self.client.post(&url).json(&mr_body), &what)?;
+        Ok(mr.web_url)
+    }
+
+    fn pr_commits(&self, repo: &GithubRepo, pr_number: &str) -> Result<Vec<CommitInPR>, String> {
+        let url = format!("{}/merge_requests/{}/commits", repo.url, pr_number);
+        let commits: Vec<GitlabCommit> =
+            GITLAB.send_for_json(self.client.get(&url), &format!("commits of merge request !{}", pr_number))?;
+        Ok(commits.into_iter().map(commit_in_pr).collect())
+    }
+
+    fn pr_body(&self, repo: &GithubRepo, pr_number: &str) -> Result<Option<String>, String> {
+        Ok(self.merge_request(repo, pr_number)?.description)
+    }
+
+    fn update_pr_body(&self, repo: &GithubRepo, pr_number: &str, body: &str) -> Result<(), String> {
+        let mut mr_body = HashMap::new();
+        mr_body.insert("description", body);
+
+        let url = format!("{}/merge_requests/{}", repo.url, pr_number);
+        GITLAB.send(
+            self.client.put(&url).json(&mr_body),
+            &format!("updating the description of merge request !{}", pr_number),
+        )
+        .map(|_| ())
+    }
+
+    fn pr_comments(&self, _repo: &GithubRepo, _pr_number: &str) -> Result<Vec<String>, String> {
+        Err(pr_comments_unsupported())
+    }
+
+    fn comment_on_pr(&self, _repo: &GithubRepo, _pr_number: &str, _body: &str) -> Result<(), String> {
+        Err(pr_comments_unsupported())
+    }
+
+    fn pr_labels(&self, _repo: &GithubRepo, _pr_number: &str) -> Result<Vec<String>, String> {
+        Err(pr_labels_unsupported())
+    }
+
+    fn add_pr_label(&self, _repo: &GithubRepo, _pr_number: &str, _label: &str) -> Result<(), String> {
+        Err(pr_labels_unsupported())
+    }
+
+    fn tags(&self, repo: &GithubRepo) -> Vec<GithubTag> {
+        match self.repo_tags(repo) {
+            Ok(tags) => tags
+                .into_iter()
+                .map(|tag| GithubTag {
+                    name: tag.name,
+                    commit: Commit { sha: tag.commit.id },
+                })
+                .collect(),
+            Err(e) => {
+                eprintln!("{}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    fn branch_exists(&self, repo: &GithubRepo, branch: &str) -> Result<bool, String> {
+        let url = format!("{}/repository/branches/{}", repo.url, forge::encode_segment(branch));
+        GITLAB.exists(self.client.get(&url), &format!("branch {}", branch))
+    }
+
+    fn commit_sha(&self, repo: &GithubRepo, reference: &str) -> Result<String, String> {
+        let url = format!("{}/repository/commits/{}", repo.url, forge::encode_segment(reference));
+        let commit: GitlabCommitId = GITLAB.send_for_json(self.client.get(&url), reference)?;
+        Ok(commit.id)
+    }
+
+    // Commits come newest first: ask for one per page and read X-Total-Pages to find the last.
+    fn first_commit(&self, repo: &GithubRepo, branch: &str) -> Result<String, String> {
+        let url = format!("{}/repository/commits", repo.url);
+        let first_page = match Url::parse_with_params(&url, &[("ref_name", branch), ("per_page", "1")]) {
+            Ok(url) => url,
+            Err(e) => return Err(format!("Couldn't create url for commits: {}", e)),
+        };
+        let (_, headers) = GITLAB.send(self.client.get(first_page), &format!("commits on {}", branch))?;
+        let last_page = headers
+            .get("X-Total-Pages")
+            .and_then(|pages| pages.to_str().ok())
+            .unwrap_or("1")
+            .to_owned();
+        let last = match Url::parse_with_params(
+            &url,
+            &[("ref_name", branch), ("per_page", "1"), ("page", last_page.as_str())],
+        ) {
+            Ok(url) => url,
+            Err(e) => return Err(format!("Couldn't create url for commits: {}", e)),
+        };
+        let mut commits: Vec<GitlabCommitId> = GITLAB.send_for_json(self.client.get(last), &format!("commits on {}", branch))?;
+        match commits.pop() {
+            Some(commit) => Ok(commit.id),
+            None => Err(format!("No commits on {}", branch)),
+        }
+    }
+
+    fn create_branch(&self, repo: &GithubRepo, branch: &str, sha: &str) -> Result<(), String> {
+        let url = match Url::parse_with_params(
+            &format!("{}/repository/branches", repo.url),
+            &[("branch", branch), ("ref", sha)],
+        ) {
+            Ok(url) => url,
+            Err(e) => return Err(format!("Couldn't create url for branches: {}", e)),
+        };
+        GITLAB.send(self.client.post(url), &format!("creating branch {}", branch)).map(|_| ())
+    }
+
+    fn branch_protection(&self, _repo: &GithubRepo, _branch: &str) -> Result<Option<BranchProtection>, String> {
+        Err(branch_protection_unsupported())
+    }
+
+    fn set_branch_protection(
+        &self,
+        _repo: &GithubRepo,
+        _branch: &str,
+        _protection: &BranchProtectionUpdate,
+    ) -> Result<(), String> {
+        Err(branch_protection_unsupported())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gitlab_commit(id: &str, message: &str) -> GitlabCommit {
+        GitlabCommit {
+            id: id.to_owned(),
+            message: message.to_owned(),
+        }
+    }
+
+    #[test]
+    fn nested_groups_are_one_path_segment() {
+        assert_eq!(
+            "https://gitlab.example.com/api/v4/groups/party%2Fapps/projects",
+            group_projects_url("https://gitlab.example.com/api/v4", "party/apps")
+        );
+    }
+
+    #[test]
+    fn compares_become_github_shaped() {
+        let ahead = Compare {
+            commits: vec![gitlab_commit("abc", "feat: a page")],
+            diffs: vec![Diff {
+                new_path: "src/page.rs".to_owned(),
+                new_file: true,
+                renamed_file: false,
+                deleted_file: false,
+                diff: "--- /dev/null\n+++ b/src/page.rs\n@@ -0,0 +1,2 @@\n+fn page() {}\n+\n".to_owned(),
+            }],
+        };
+        let behind = Compare {
+            commits: vec![gitlab_commit("def", "fix: hotfix")],
+            diffs: Vec::new(),
+        };
+
+        let compare = compare_response(ahead, behind);
+
+        assert_eq!("diverged", compare.status);
+        assert_eq!((1, 1), (compare.ahead_by, compare.behind_by));
+        assert_eq!("feat: a page", compare.commits[0].actual_commit.message);
+        assert_eq!(("added", 2, 0), (compare.files[0].status.as_str(), compare.files[0].additions, compare.files[0].deletions));
+    }
+
+    #[test]
+    fn developers_can_push() {
+        let project = Project {
+            id: 7,
+            path_with_namespace: "party/moe".to_owned(),
+            permissions: Some(ProjectPermissions {
+                project_access: None,
+                group_access: Some(Access {
+                    access_level: DEVELOPER_ACCESS,
+                }),
+            }),
+        };
+        let repo = repo_from_project(project, "https://gitlab.example.com/api/v4");
+        assert_eq!("https://gitlab.example.com/api/v4/projects/7", repo.url);
+        assert!(repo.can_push());
+
+        let reporter = Project {
+            id: 8,
+            path_with_namespace: "party/larry".to_owned(),
+            permissions: Some(ProjectPermissions {
+                project_access: Some(Access { access_level: 20 }),
+                group_access: None,
+            }),
+        };
+        assert!(!repo_from_project(reporter, "https://gitlab.example.com/api/v4").can_push());
+    }
+}
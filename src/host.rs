@@ -0,0 +1,589 @@
+use github::{
+    BranchProtection, BranchProtectionUpdate, CommitInPR, CompareCommitsResponse, GithubRepo, GithubTag,
+};
+use jira::IssueKeys;
+use version::{self, VersionProposal};
+
+/// Everything release-party-br needs from wherever the repos are hosted.
+/// `github::GithubClient` is the real thing, tests use `host::fake::InMemoryHost`.
+pub trait ReleaseHost {
+    fn list_repos(&self, org_url: &str) -> Result<Vec<GithubRepo>, String>;
+    // Compares `base...head`, see `github::compare_branches`.
+    fn compare(&self, repo: &GithubRepo, base: &str, head: &str) -> Result<CompareCommitsResponse, String>;
+    fn find_pr(&self, repo: &GithubRepo, head: &str, base: &str) -> Option<String>;
+    fn create_pr(&self, repo: &GithubRepo, title: &str, head: &str, base: &str) -> Result<String, String>;
+    fn pr_commits(&self, repo: &GithubRepo, pr_number: &str) -> Result<Vec<CommitInPR>, String>;
+    fn pr_body(&self, repo: &GithubRepo, pr_number: &str) -> Result<Option<String>, String>;
+    fn update_pr_body(&self, repo: &GithubRepo, pr_number: &str, body: &str) -> Result<(), String>;
+    // The bodies of the comments on a PR.
+    fn pr_comments(&self, repo: &GithubRepo, pr_number: &str) -> Result<Vec<String>, String>;
+    fn comment_on_pr(&self, repo: &GithubRepo, pr_number: &str, body: &str) -> Result<(), String>;
+    // The names of the labels on a PR.
+    fn pr_labels(&self, repo: &GithubRepo, pr_number: &str) -> Result<Vec<String>, String>;
+    fn add_pr_label(&self, repo: &GithubRepo, pr_number: &str, label: &str) -> Result<(), String>;
+    fn tags(&self, repo: &GithubRepo) -> Vec<GithubTag>;
+    fn branch_exists(&self, repo: &GithubRepo, branch: &str) -> Result<bool, String>;
+    fn commit_sha(&self, repo: &GithubRepo, reference: &str) -> Result<String, String>;
+    fn first_commit(&self, repo: &GithubRepo, branch: &str) -> Result<String, String>;
+    fn create_branch(&self, repo: &GithubRepo, branch: &str, sha: &str) -> Result<(), String>;
+    fn branch_protection(&self, repo: &GithubRepo, branch: &str) -> Result<Option<BranchProtection>, String>;
+    fn set_branch_protection(
+        &self,
+        repo: &GithubRepo,
+        branch: &str,
+        protection: &BranchProtectionUpdate,
+    ) -> Result<(), String>;
+
+    // How many repos `prefetch` looks up at once, for hosts that can batch their lookups.
+    fn batch_size(&self) -> Option<usize> {
+        None
+    }
+    // Looks up what a party needs to know about `repos` in one go, ahead of `compare` and `find_pr`.
+    fn prefetch(&self, _repos: &[&GithubRepo]) {}
+}
+
+// What master has that release doesn't: the commits and files a release PR would ship.
+pub fn compare_release_with_master(
+    host: &dyn ReleaseHost,
+    repo: &GithubRepo,
+) -> Result<CompareCommitsResponse, String> {
+    host.compare(repo, "release", "master")
+}
+
+pub fn is_release_up_to_date_with_master(release_diff: &CompareCommitsResponse) -> bool {
+    release_diff.ahead_by == 0
+}
+
+// Someone committed straight to release (a hotfix, usually) and master doesn't have it yet.
+pub fn release_needs_back_merge(release_diff: &CompareCommitsResponse) -> bool {
+    release_diff.behind_by > 0
+}
+
+pub fn existing_release_pr_location(host: &dyn ReleaseHost, repo: &GithubRepo) -> Option<String> {
+    host.find_pr(repo, "master", "release")
+}
+
+pub fn existing_back_merge_pr_location(host: &dyn ReleaseHost, repo: &GithubRepo) -> Option<String> {
+    host.find_pr(repo, "release", "master")
+}
+
+pub static RELEASE_PR_TITLE: &str = "automated release partay";
+
+// Try to create the release PR and return the URL of it:
+pub fn create_release_pull_request(host: &dyn ReleaseHost, repo: &GithubRepo) -> Result<String, String> {
+    host.create_pr(repo, RELEASE_PR_TITLE, "master", "release")
+}
+
+// Bring hotfixes made directly on release back into master:
+pub fn create_back_merge_pull_request(host: &dyn ReleaseHost, repo: &GithubRepo) -> Result<String, String> {
+    host.create_pr(
+        repo,
+        "automated back-merge of release into master",
+        "release",
+        "master",
+    )
+}
+
+// Look at the latest version tag and the conventional commits waiting on master to suggest the next version.
+pub fn propose_release_version(
+    host: &dyn ReleaseHost,
+    repo: &GithubRepo,
+    release_diff: &CompareCommitsResponse,
+) -> VersionProposal {
+    let commits = release_diff
+        .commits
+        .iter()
+        .map(|c| c.actual_commit.message.clone())
+        .collect::<Vec<String>>();
+    let tags = host
+        .tags(repo)
+        .into_iter()
+        .map(|t| t.name)
+        .collect::<Vec<String>>();
+    version::propose_version(&tags, &commits)
+}
+
+// Where a new release branch can start from.
+// "latest-tag" and "first-commit" are worked out per repo, anything else is a branch, tag or SHA.
+pub fn resolve_ref(host: &dyn ReleaseHost, repo: &GithubRepo, reference: &str) -> Result<String, String> {
+    match reference {
+        "latest-tag" => {
+            let tags = host.tags(repo);
+            let names = tags.iter().map(|t| t.name.clone()).collect::<Vec<String>>();
+            let latest = match version::latest_version(&names) {
+                Some(latest) => latest,
+                None => return Err("No version tags to start from".to_string()),
+            };
+            match tags
+                .into_iter()
+                .find(|t| version::Version::parse(&t.name) == Some(latest))
+            {
+                Some(tag) => Ok(tag.commit.sha),
+                None => Err("No version tags to start from".to_string()),
+            }
+        }
+        "first-commit" => host.first_commit(repo, "master"),
+        _ => host.commit_sha(repo, reference),
+    }
+}
+
+// pr_link will look like https://github.com/matthewkmayer/release-party-BR/pull/39
+// grab the chunk after the last '/'.
+pub fn pr_number(pr_link: &str) -> &str {
+    pr_link.rsplit('/').next().expect("PR link malformed?")
+}
+
+pub fn release_pr_body(
+    commits: &[CommitInPR],
+    rp_version: &str,
+    proposal: Option<&VersionProposal>,
+    issue_keys: Option<&IssueKeys>,
+) -> String {
+    let mut new_body = "automated release partay!\n\nPRs in this release:".to_string();
+
+    for c in commits {
+        if let Some(pr_number) = merged_pr_reference(&c.actual_commit.message) {
+            new_body.push_str(&format!("\n* {}", pr_number));
+        } else if let Some(reference) = merge_request_reference(&c.actual_commit.message) {
+            new_body.push_str(&format!("\n* {}", reference));
+        }
+    }
+
+    if let Some(issue_keys) = issue_keys {
+        let keys = issue_keys.find(commits);
+        if !keys.is_empty() {
+            new_body.push_str("\n\nIssues in this release:");
+            for key in keys {
+                new_body.push_str(&format!("\n* {}", issue_keys.reference(&key)));
+            }
+        }
+    }
+
+    if let Some(proposal) = proposal {
+        new_body.push_str(&format!("\n\nProposed version: {}", proposal));
+    }
+
+    new_body.push_str(&format!("\n\n---\nMade by `{}`.", rp_version));
+
+    new_body
+}
+
+// remove the bits we don't need: go from "Merge pull request #1890 from..." to "#1890"
+fn merged_pr_reference(message: &str) -> Option<&str> {
+    if !message.contains("Merge pull request #") {
+        return None;
+    }
+    message.split(' ').nth(3)
+}
+
+/// The numbers of the feature PRs merged in these commits, "1890" for "Merge pull request #1890 from...".
+pub fn merged_pr_numbers(commits: &[CommitInPR]) -> Vec<&str> {
+    commits
+        .iter()
+        .filter_map(|c| merged_pr_reference(&c.actual_commit.message))
+        .map(|reference| reference.trim_start_matches('#'))
+        .collect()
+}
+
+/// Added to feature PRs with `--label-included-prs`.
+pub static IN_RELEASE_LABEL: &str = "in-release";
+
+/// What feature PRs are told when they're in a release PR. PRs that already have it are left alone.
+pub fn included_in_release_comment(release_pr_number: &str) -> String {
+    format!("Included in release PR #{}", release_pr_number)
+}
+
+/// Whether `comments` already has `comment`, give or take the whitespace around it.
+pub fn has_comment(comments: &[String], comment: &str) -> bool {
+    comments.iter().any(|c| c.trim() == comment.trim())
+}
+
+// GitLab merge commits end with "See merge request group/project!12": grab the "!12".
+fn merge_request_reference(message: &str) -> Option<&str> {
+    let reference = message
+        .lines()
+        .find(|line| line.starts_with("See merge request "))?
+        .trim_start_matches("See merge request ")
+        .trim();
+    reference.rfind('!').map(|bang| &reference[bang..])
+}
+
+pub fn update_pr_body(
+    host: &dyn ReleaseHost,
+    repo: &GithubRepo,
+    pr_number: &str,
+    rp_version: &str,
+    proposal: Option<&VersionProposal>,
+    issue_keys: Option<&IssueKeys>,
+) -> Result<(), String> {
+    let commits = host.pr_commits(repo, pr_number)?;
+    let new_body = release_pr_body(&commits, rp_version, proposal, issue_keys);
+    host.update_pr_body(repo, pr_number, &new_body)
+}
+
+#[cfg(test)]
+pub mod fake {
+    use super::*;
+    use github::{ActualCommitInPR, Commit};
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    #[derive(Clone, Debug)]
+    pub struct FakePr {
+        pub number: u32,
+        pub head: String,
+        pub base: String,
+        pub body: Option<String>,
+    }
+
+    #[derive(Default)]
+    pub struct FakeRepoState {
+        // Comments and labels on PRs, by PR number.
+        pub comments: HashMap<String, Vec<String>>,
+        pub labels: HashMap<String, Vec<String>>,
+        pub master_commits: Vec<String>,
+        pub release_commits: Vec<String>,
+        pub has_release_branch: bool,
+        pub tags: Vec<String>,
+        pub pulls: Vec<FakePr>,
+    }
+
+    /// A `ReleaseHost` that keeps everything in memory, for unit tests.
+    #[derive(Default)]
+    pub struct InMemoryHost {
+        pub repos: RefCell<HashMap<String, FakeRepoState>>,
+        pub calls: RefCell<Vec<String>>,
+    }
+
+    pub fn commit(sha: &str, message: &str) -> CommitInPR {
+        CommitInPR {
+            sha: sha.to_owned(),
+            actual_commit: ActualCommitInPR {
+                message: message.to_owned(),
+            },
+        }
+    }
+
+    impl InMemoryHost {
+        pub fn new() -> InMemoryHost {
+            InMemoryHost::default()
+        }
+
+        pub fn add_repo(&self, name: &str, master_commits: &[&str], release_commits: &[&str]) -> GithubRepo {
+            let state = FakeRepoState {
+                master_commits: master_commits.iter().map(|m| m.to_string()).collect(),
+                release_commits: release_commits.iter().map(|m| m.to_string()).collect(),
+                has_release_branch: true,
+                ..FakeRepoState::default()
+            };
+            self.repos.borrow_mut().insert(name.to_owned(), state);
+            GithubRepo::new(name, &format!("memory://{}", name))
+        }
+
+        pub fn add_pr(&self, repo: &str, number: u32, head: &str, base: &str) {
+            let mut repos = self.repos.borrow_mut();
+            let state = repos.get_mut(repo).expect("repo should be added first");
+            state.pulls.push(FakePr {
+                number,
+                head: head.to_owned(),
+                base: base.to_owned(),
+                body: None,
+            });
+        }
+
+        pub fn push_to(&self, repo: &str, branch: &str, message: &str) {
+            let mut repos = self.repos.borrow_mut();
+            let state = repos.get_mut(repo).expect("repo should be added first");
+            match branch {
+                "master" => state.master_commits.push(message.to_owned()),
+                _ => state.release_commits.push(message.to_owned()),
+            }
+        }
+
+        pub fn pulls(&self, repo: &str) -> Vec<FakePr> {
+            self.repos.borrow()[repo].pulls.clone()
+        }
+
+        pub fn made_call(&self, prefix: &str) -> bool {
+            self.calls.borrow().iter().any(|c| c.starts_with(prefix))
+        }
+
+        fn record(&self, call: String) {
+            self.calls.borrow_mut().push(call);
+        }
+    }
+
+    fn pr_link(repo: &str, number: u32) -> String {
+        format!("https://github.com/org/{}/pull/{}", repo, number)
+    }
+
+    impl ReleaseHost for InMemoryHost {
+        fn list_repos(&self, _org_url: &str) -> Result<Vec<GithubRepo>, String> {
+            let mut names = self.repos.borrow().keys().cloned().collect::<Vec<String>>();
+            names.sort();
+            Ok(names
+                .iter()
+                .map(|name| GithubRepo::new(name, &format!("memory://{}", name)))
+                .collect())
+        }
+
+        fn compare(&self, repo: &GithubRepo, base: &str, head: &str) -> Result<CompareCommitsResponse, String> {
+            self.record(format!("compare {} {}...{}", repo.name, base, head));
+            let repos = self.repos.borrow();
+            let state = &repos[&repo.name];
+            if !state.has_release_branch {
+                return Err("Not Found".to_owned());
+            }
+            let (ahead, behind) = if base == "release" {
+                (&state.master_commits, &state.release_commits)
+            } else {
+                (&state.release_commits, &state.master_commits)
+            };
+            let status = match (ahead.is_empty(), behind.is_empty()) {
+                (true, true) => "identical",
+                (false, true) => "ahead",
+                (true, false) => "behind",
+                (false, false) => "diverged",
+            };
+            Ok(CompareCommitsResponse {
+                status: status.to_owned(),
+                ahead_by: ahead.len() as i32,
+                behind_by: behind.len() as i32,
+                total_commits: ahead.len() as i32,
+                commits: ahead
+                    .iter()
+                    .enumerate()
+                    .map(|(i, m)| commit(&format!("{:07}", i + 1), m))
+                    .collect(),
+                files: Vec::new(),
+            })
+        }
+
+        fn find_pr(&self, repo: &GithubRepo, head: &str, base: &str) -> Option<String> {
+            self.record(format!("find_pr {} {}->{}", repo.name, head, base));
+            self.repos.borrow()[&repo.name]
+                .pulls
+                .iter()
+                .find(|p| p.head == head && p.base == base)
+                .map(|p| pr_link(&repo.name, p.number))
+        }
+
+        fn create_pr(&self, repo: &GithubRepo, _title: &str, head: &str, base: &str) -> Result<String, String> {
+            self.record(format!("create_pr {} {}->{}", repo.name, head, base));
+            let mut repos = self.repos.borrow_mut();
+            let state = repos.get_mut(&repo.name).expect("repo should exist");
+            let number = 100 + state.pulls.len() as u32;
+            state.pulls.push(FakePr {
+                number,
+                head: head.to_owned(),
+                base: base.to_owned(),
+                body: None,
+            });
+            Ok(pr_link(&repo.name, number))
+        }
+
+        fn pr_commits(&self, repo: &GithubRepo, _pr_number: &str) -> Result<Vec<CommitInPR>, String> {
+            let repos = self.repos.borrow();
+            Ok(repos[&repo.name]
+                .master_commits
+                .iter()
+                .enumerate()
+                .map(|(i, m)| commit(&format!("{:07}", i + 1), m))
+                .collect())
+        }
+
+        fn pr_body(&self, repo: &GithubRepo, pr_number: &str) -> Result<Option<String>, String> {
+            match self.repos.borrow()[&repo.name]
+                .pulls
+                .iter()
+                .find(|p| p.number.to_string() == pr_number)
+            {
+                Some(pull) => Ok(pull.body.clone()),
+                None => Err(format!("No PR {}", pr_number)),
+            }
+        }
+
+        fn update_pr_body(&self, repo: &GithubRepo, pr_number: &str, body: &str) -> Result<(), String> {
+            self.record(format!("update_pr_body {} {}", repo.name, pr_number));
+            let mut repos = self.repos.borrow_mut();
+            let state = repos.get_mut(&repo.name).expect("repo should exist");
+            match state.pulls.iter_mut().find(|p| p.number.to_string() == pr_number) {
+                Some(pull) => {
+                    pull.body = Some(body.to_owned());
+                    Ok(())
+                }
+                None => Err(format!("No PR {}", pr_number)),
+            }
+        }
+
+        fn pr_comments(&self, repo: &GithubRepo, pr_number: &str) -> Result<Vec<String>, String> {
+            Ok(self.repos.borrow()[&repo.name]
+                .comments
+                .get(pr_number)
+                .cloned()
+                .unwrap_or_default())
+        }
+
+        fn comment_on_pr(&self, repo: &GithubRepo, pr_number: &str, body: &str) -> Result<(), String> {
+            self.record(format!("comment_on_pr {} {}", repo.name, pr_number));
+            let mut repos = self.repos.borrow_mut();
+            let state = repos.get_mut(&repo.name).expect("repo should exist");
+            state
+                .comments
+                .entry(pr_number.to_owned())
+                .or_default()
+                .push(body.to_owned());
+            Ok(())
+        }
+
+        fn pr_labels(&self, repo: &GithubRepo, pr_number: &str) -> Result<Vec<String>, String> {
+            Ok(self.repos.borrow()[&repo.name]
+                .labels
+                .get(pr_number)
+                .cloned()
+                .unwrap_or_default())
+        }
+
+        fn add_pr_label(&self, repo: &GithubRepo, pr_number: &str, label: &str) -> Result<(), String> {
+            self.record(format!("add_pr_label {} {}", repo.name, pr_number));
+            let mut repos = self.repos.borrow_mut();
+            let state = repos.get_mut(&repo.name).expect("repo should exist");
+            state
+                .labels
+                .entry(pr_number.to_owned())
+                .or_default()
+                .push(label.to_owned());
+            Ok(())
+        }
+
+        fn tags(&self, repo: &GithubRepo) -> Vec<GithubTag> {
+            self.repos.borrow()[&repo.name]
+                .tags
+                .iter()
+                .map(|t| GithubTag {
+                    name: t.clone(),
+                    commit: Commit {
+                        sha: format!("sha-{}", t),
+                    },
+                })
+                .collect()
+        }
+
+        fn branch_exists(&self, repo: &GithubRepo, branch: &str) -> Result<bool, String> {
+            Ok(branch != "release" || self.repos.borrow()[&repo.name].has_release_branch)
+        }
+
+        // Branch heads change whenever a commit lands on them.
+        fn commit_sha(&self, repo: &GithubRepo, reference: &str) -> Result<String, String> {
+            let repos = self.repos.borrow();
+            let state = &repos[&repo.name];
+            Ok(match reference {
+                "master" => format!("master-{}", state.master_commits.len()),
+                "release" => format!("release-{}", state.release_commits.len()),
+                _ => format!("sha-{}", reference),
+            })
+        }
+
+        fn first_commit(&self, _repo: &GithubRepo, _branch: &str) -> Result<String, String> {
+            Ok("sha-first".to_owned())
+        }
+
+        fn create_branch(&self, repo: &GithubRepo, branch: &str, sha: &str) -> Result<(), String> {
+            self.record(format!("create_branch {} {} {}", repo.name, branch, sha));
+            let mut repos = self.repos.borrow_mut();
+            let state = repos.get_mut(&repo.name).expect("repo should exist");
+            state.has_release_branch = true;
+            Ok(())
+        }
+
+        fn branch_protection(&self, _repo: &GithubRepo, _branch: &str) -> Result<Option<BranchProtection>, String> {
+            Ok(None)
+        }
+
+        fn set_branch_protection(
+            &self,
+            repo: &GithubRepo,
+            branch: &str,
+            _protection: &BranchProtectionUpdate,
+        ) -> Result<(), String> {
+            self.record(format!("set_branch_protection {} {}", repo.name, branch));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fake::{commit, InMemoryHost};
+    use super::*;
+
+    #[test]
+    fn release_pr_body_lists_merged_prs() {
+        let commits = vec![
+            commit("1", "Merge pull request #12 from org/feature\n\nfeat: a page"),
+            commit("2", "feat: a page"),
+            commit("3", "Merge pull request #14 from org/fix"),
+        ];
+        assert_eq!(
+            "automated release partay!\n\nPRs in this release:\n* #12\n* #14\n\n---\nMade by `rp 1.0`.",
+            release_pr_body(&commits, "rp 1.0", None, None)
+        );
+    }
+
+    #[test]
+    fn release_pr_body_lists_issue_keys() {
+        let commits = vec![
+            commit("1", "Merge pull request #12 from org/PAY-12-refunds"),
+            commit("2", "PAY-12: refunds, OPS-7"),
+        ];
+        let keys = IssueKeys::new("PAY-[0-9]+", Some("https://example.atlassian.net")).unwrap();
+        assert_eq!(
+            "automated release partay!\n\nPRs in this release:\n* #12\n\nIssues in this release:\n\
+             * [PAY-12](https://example.atlassian.net/browse/PAY-12)\n\n---\nMade by `rp 1.0`.",
+            release_pr_body(&commits, "rp 1.0", None, Some(&keys))
+        );
+    }
+
+    #[test]
+    fn merged_pr_numbers_from_merge_commits() {
+        let commits = vec![
+            commit("1", "Merge pull request #12 from org/feature\n\nfeat: a page"),
+            commit("2", "feat: a page"),
+            commit("3", "Merge pull request #14 from org/fix"),
+        ];
+        assert_eq!(vec!["12", "14"], merged_pr_numbers(&commits));
+    }
+
+    #[test]
+    fn release_pr_body_lists_gitlab_merge_requests() {
+        let commits = vec![commit(
+            "1",
+            "Merge branch 'feature' into 'master'\n\nfeat: a page\n\nSee merge request org/apps/moe!12",
+        )];
+        assert_eq!(
+            "automated release partay!\n\nPRs in this release:\n* !12\n\n---\nMade by `rp 1.0`.",
+            release_pr_body(&commits, "rp 1.0", None, None)
+        );
+    }
+
+    #[test]
+    fn resolves_latest_tag() {
+        let host = InMemoryHost::new();
+        let repo = host.add_repo("moe", &[], &[]);
+        host.repos.borrow_mut().get_mut("moe").unwrap().tags =
+            vec!["v1.0.0".to_owned(), "v1.10.0".to_owned(), "v1.9.0".to_owned()];
+        assert_eq!(Ok("sha-v1.10.0".to_owned()), resolve_ref(&host, &repo, "latest-tag"));
+        assert_eq!(Ok("sha-first".to_owned()), resolve_ref(&host, &repo, "first-commit"));
+        assert_eq!(Ok("sha-v1.0.0".to_owned()), resolve_ref(&host, &repo, "v1.0.0"));
+    }
+
+    #[test]
+    fn update_pr_body_writes_through_the_host() {
+        let host = InMemoryHost::new();
+        let repo = host.add_repo("moe", &["Merge pull request #3 from org/branch"], &[]);
+        host.add_pr("moe", 7, "master", "release");
+        update_pr_body(&host, &repo, "7", "rp 1.0", None, None).unwrap();
+        let body = host.pulls("moe")[0].body.clone().unwrap();
+        assert!(body.contains("* #3"));
+    }
+}
@@ -0,0 +1,121 @@
+extern crate reqwest;
+
+use self::reqwest::{Certificate, ClientBuilder, Proxy};
+
+use std::fs;
+use std::time::Duration;
+
+use config::HttpConfig;
+
+/// What every HTTP client starts from: timeouts, a proxy and extra root certificates. Checked once
+/// up front, so a bad proxy URL or CA bundle is reported before the run instead of panicking in it.
+#[derive(Debug, Clone, Default)]
+pub struct HttpSettings {
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    proxy: Option<Proxy>,
+    // Each certificate in the CA bundle, as PEM. reqwest's certificates can't be cloned.
+    certificates: Vec<String>,
+}
+
+impl HttpSettings {
+    pub fn from_config(http: &HttpConfig) -> Result<HttpSettings, String> {
+        let proxy = match http.proxy {
+            Some(ref url) => match Proxy::all(url.as_str()) {
+                Ok(proxy) => Some(proxy),
+                Err(e) => return Err(format!("The proxy {} isn't usable: {}", url, e)),
+            },
+            None => None,
+        };
+        let certificates = match http.ca_bundle {
+            Some(ref path) => {
+                let bundle = match fs::read_to_string(path) {
+                    Ok(bundle) => bundle,
+                    Err(e) => return Err(format!("Couldn't read the CA bundle {}: {}", path, e)),
+                };
+                let certificates = pem_certificates(&bundle);
+                if certificates.is_empty() {
+                    return Err(format!("The CA bundle {} has no PEM certificates in it", path));
+                }
+                for certificate in &certificates {
+                    if let Err(e) = Certificate::from_pem(certificate.as_bytes()) {
+                        return Err(format!("The CA bundle {} has a certificate that isn't valid: {}", path, e));
+                    }
+                }
+                certificates
+            }
+            None => Vec::new(),
+        };
+        Ok(HttpSettings {
+            connect_timeout: http.connect_timeout.map(Duration::from_secs),
+            timeout: http.timeout.map(Duration::from_secs),
+            proxy,
+            certificates,
+        })
+    }
+
+    /// A client builder with these settings, for the caller to add its headers to.
+    pub fn builder(&self) -> ClientBuilder {
+        let mut builder = reqwest::Client::builder().connect_timeout(self.connect_timeout);
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(ref proxy) = self.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        for certificate in &self.certificates {
+            let certificate = Certificate::from_pem(certificate.as_bytes()).expect("certificates are checked up front");
+            builder = builder.add_root_certificate(certificate);
+        }
+        builder
+    }
+}
+
+// Each `BEGIN CERTIFICATE` block in a PEM bundle, which may have comments between them.
+fn pem_certificates(bundle: &str) -> Vec<String> {
+    let end = "-----END CERTIFICATE-----";
+    bundle
+        .split_inclusive(end)
+        .filter_map(|block| {
+            let start = block.find("-----BEGIN CERTIFICATE-----")?;
+            if block.ends_with(end) {
+                Some(block[start..].to_owned())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_a_bundle_into_certificates() {
+        let bundle = "# Corp root\n-----BEGIN CERTIFICATE-----\nAAA\n-----END CERTIFICATE-----\n\
+                      # Corp intermediate\n-----BEGIN CERTIFICATE-----\nBBB\n-----END CERTIFICATE-----\n";
+        assert_eq!(
+            vec![
+                "-----BEGIN CERTIFICATE-----\nAAA\n-----END CERTIFICATE-----".to_owned(),
+                "-----BEGIN CERTIFICATE-----\nBBB\n-----END CERTIFICATE-----".to_owned(),
+            ],
+            pem_certificates(bundle)
+        );
+    }
+
+    #[test]
+    fn bad_settings_are_caught_up_front() {
+        let proxy = HttpConfig {
+            proxy: Some("not a url".to_owned()),
+            ..HttpConfig::default()
+        };
+        assert!(HttpSettings::from_config(&proxy).is_err());
+        let ca_bundle = HttpConfig {
+            ca_bundle: Some("/nonexistent/ca.pem".to_owned()),
+            ..HttpConfig::default()
+        };
+        assert!(HttpSettings::from_config(&ca_bundle).is_err());
+        assert!(HttpSettings::from_config(&HttpConfig::default()).is_ok());
+    }
+}
@@ -0,0 +1,274 @@
+extern crate base64;
+extern crate regex;
+extern crate reqwest;
+extern crate serde_json;
+
+use self::regex::Regex;
+use self::reqwest::header::{HeaderMap, AUTHORIZATION, USER_AGENT};
+use serde::de::DeserializeOwned;
+
+use std::io::Read;
+
+use config::JiraConfig;
+use github::CommitInPR;
+use http::HttpSettings;
+use party::PartyReport;
+
+static USERAGENT: &str = "release-party-br";
+
+/// What a Jira issue key looks like when `[jira]` doesn't give a `key_pattern`: `PAY-1234`.
+pub static DEFAULT_KEY_PATTERN: &str = r"\b[A-Z][A-Z0-9_]+-[0-9]+\b";
+
+// Names and numbers the default pattern mistakes for keys: UTF-8, SHA-256, ISO-8601, CVE-2024 and friends.
+static NOT_PROJECTS: &[&str] = &["UTF", "UCS", "SHA", "MD", "ISO", "CVE", "CWE", "RFC", "PEP", "TLS"];
+
+/// Finds Jira issue keys in commit messages, and links them when we know where Jira is.
+#[derive(Debug)]
+pub struct IssueKeys {
+    pattern: Regex,
+    jira_url: Option<String>,
+    // What to leave out even though it matches, see `NOT_PROJECTS`.
+    not_projects: &'static [&'static str],
+}
+
+impl IssueKeys {
+    pub fn new(pattern: &str, jira_url: Option<&str>) -> Result<IssueKeys, String> {
+        match Regex::new(pattern) {
+            Ok(regex) => Ok(IssueKeys {
+                pattern: regex,
+                jira_url: jira_url.map(|url| url.trim_end_matches('/').to_owned()),
+                not_projects: if pattern == DEFAULT_KEY_PATTERN { NOT_PROJECTS } else { &[] },
+            }),
+            Err(e) => Err(format!("The Jira key_pattern {} isn't a regex: {}", pattern, e)),
+        }
+    }
+
+    /// Commenting on and moving issues needs a `key_pattern`: the default is only good enough for listing keys.
+    pub fn from_config(jira: &JiraConfig) -> Result<IssueKeys, String> {
+        if jira.key_pattern.is_none() && jira.url.is_some() && (jira.comment || jira.transition.is_some()) {
+            return Err(
+                "Set key_pattern in [jira] to comment on or move issues, like key_pattern = \"(PAY|OPS)-[0-9]+\""
+                    .to_owned(),
+            );
+        }
+        let pattern = jira.key_pattern.as_ref().map_or(DEFAULT_KEY_PATTERN, |p| p.as_str());
+        IssueKeys::new(pattern, jira.url.as_deref())
+    }
+
+    /// Every key in the commits' messages, each once, in the order they first turn up.
+    pub fn find(&self, commits: &[CommitInPR]) -> Vec<String> {
+        let mut keys: Vec<String> = Vec::new();
+        for commit in commits {
+            for found in self.pattern.find_iter(&commit.actual_commit.message) {
+                if self.not_projects.iter().any(|p| found.as_str().starts_with(&format!("{}-", p))) {
+                    continue;
+                }
+                if !keys.iter().any(|k| k == found.as_str()) {
+                    keys.push(found.as_str().to_owned());
+                }
+            }
+        }
+        keys
+    }
+
+    /// The key as a markdown link to the issue, or just the key.
+    pub fn reference(&self, key: &str) -> String {
+        match self.jira_url {
+            Some(ref url) => format!("[{}]({}/browse/{})", key, url, key),
+            None => key.to_owned(),
+        }
+    }
+}
+
+/// A client for Jira's REST API: basic auth with `username` (Jira Cloud's email and API token),
+/// or the token alone as a bearer token (Jira Server's personal access tokens).
+pub fn client_for(username: Option<&str>, token: &str, http: &HttpSettings) -> reqwest::Client {
+    let authorization = match username {
+        Some(username) => format!("Basic {}", base64::encode(&format!("{}:{}", username, token))),
+        None => format!("Bearer {}", token),
+    };
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        USER_AGENT,
+        USERAGENT.parse().expect("useragent should be a string"),
+    );
+    headers.insert(
+        AUTHORIZATION,
+        authorization.parse().expect("token should be a string"),
+    );
+    match http.builder().default_headers(headers).build() {
+        Ok(new_client) => new_client,
+        Err(e) => panic!("Couldn't create new reqwest client: {}", e),
+    }
+}
+
+/// What the issues in a release PR are told.
+pub fn release_comment(pr_link: &str) -> String {
+    format!("Included in release PR {}", pr_link)
+}
+
+#[derive(Deserialize, Debug)]
+struct Comments {
+    comments: Vec<Comment>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Comment {
+    #[serde(default)]
+    body: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Transitions {
+    transitions: Vec<Transition>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Transition {
+    id: String,
+    name: String,
+}
+
+/// Comments on and moves the issues of each release PR, as `[jira]` asks. Issues that already have
+/// the comment don't get it again. Problems with one issue are printed and the rest carry on.
+pub fn update_issues(jira: &JiraConfig, report: &PartyReport, client: &reqwest::Client) {
+    let api_url = match jira.url {
+        Some(ref url) => format!("{}/rest/api/2", url.trim_end_matches('/')),
+        None => return,
+    };
+    for link in &report.release_prs {
+        for key in &link.issue_keys {
+            if jira.comment {
+                let comment = release_comment(&link.pr_link);
+                let commented = issue_comments(&api_url, key, client).and_then(|comments| {
+                    if comments.iter().any(|c| c.trim() == comment) {
+                        return Ok(());
+                    }
+                    comment_on_issue(&api_url, key, &comment, client)
+                });
+                if let Err(e) = commented {
+                    eprintln!("Couldn't comment on {}: {}", key, e);
+                }
+            }
+            if let Some(ref transition) = jira.transition {
+                if let Err(e) = transition_issue(&api_url, key, transition, client) {
+                    eprintln!("Couldn't move {} to {}: {}", key, transition, e);
+                }
+            }
+        }
+    }
+}
+
+fn issue_comments(api_url: &str, key: &str, client: &reqwest::Client) -> Result<Vec<String>, String> {
+    let url = format!("{}/issue/{}/comment", api_url, key);
+    let comments: Comments = get_json(&url, client)?;
+    Ok(comments.comments.into_iter().map(|c| c.body).collect())
+}
+
+fn comment_on_issue(api_url: &str, key: &str, comment: &str, client: &reqwest::Client) -> Result<(), String> {
+    let url = format!("{}/issue/{}/comment", api_url, key);
+    post_json(&url, &json!({ "body": comment }), client)
+}
+
+// Transitions are asked for by name or id. One that isn't offered is skipped quietly: the issue has
+// most likely been moved on already.
+fn transition_issue(api_url: &str, key: &str, transition: &str, client: &reqwest::Client) -> Result<(), String> {
+    let url = format!("{}/issue/{}/transitions", api_url, key);
+    let available: Transitions = get_json(&url, client)?;
+    let id = match available
+        .transitions
+        .into_iter()
+        .find(|t| t.id == transition || t.name.eq_ignore_ascii_case(transition))
+    {
+        Some(found) => found.id,
+        None => return Ok(()),
+    };
+    post_json(&url, &json!({ "transition": { "id": id } }), client)
+}
+
+fn get_json<T: DeserializeOwned>(url: &str, client: &reqwest::Client) -> Result<T, String> {
+    let mut res = match client.get(url).send() {
+        Ok(response) => response,
+        Err(e) => return Err(format!("Error in request to Jira: {}", e)),
+    };
+    if !res.status().is_success() {
+        return Err(format!("Jira responded with {} for {}", res.status(), url));
+    }
+    let mut buffer = String::new();
+    if let Err(e) = res.read_to_string(&mut buffer) {
+        return Err(format!("Couldn't read Jira's response: {}", e));
+    }
+    serde_json::from_str(&buffer).map_err(|e| format!("Couldn't deserialize Jira's response: {}", e))
+}
+
+fn post_json(url: &str, body: &serde_json::Value, client: &reqwest::Client) -> Result<(), String> {
+    let res = match client.post(url).json(body).send() {
+        Ok(response) => response,
+        Err(e) => return Err(format!("Error in request to Jira: {}", e)),
+    };
+    if res.status().is_success() {
+        return Ok(());
+    }
+    Err(format!("Jira responded with {} for {}", res.status(), url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use host::fake::commit;
+
+    #[test]
+    fn finds_each_key_once() {
+        let keys = IssueKeys::new(DEFAULT_KEY_PATTERN, None).unwrap();
+        let commits = vec![
+            commit("1", "Merge pull request #12 from org/PAY-1234-refunds\n\nPAY-1234: refunds"),
+            commit("2", "fix: rounding, see OPS-7 and PAY-1234"),
+            commit("3", "chore: utf-8 and x86-64 aren't keys"),
+        ];
+        assert_eq!(vec!["PAY-1234".to_owned(), "OPS-7".to_owned()], keys.find(&commits));
+    }
+
+    #[test]
+    fn configured_pattern_and_links() {
+        let keys = IssueKeys::new("PAY-[0-9]+", Some("https://example.atlassian.net/")).unwrap();
+        let commits = vec![commit("1", "PAY-12 and OPS-7")];
+        assert_eq!(vec!["PAY-12".to_owned()], keys.find(&commits));
+        assert_eq!(
+            "[PAY-12](https://example.atlassian.net/browse/PAY-12)",
+            keys.reference("PAY-12")
+        );
+        assert!(IssueKeys::new("PAY-[", None).is_err());
+    }
+
+    #[test]
+    fn default_pattern_skips_standards_that_look_like_keys() {
+        let keys = IssueKeys::new(DEFAULT_KEY_PATTERN, None).unwrap();
+        let commits = vec![
+            commit("1", "fix: read UTF-8 names, PAY-12"),
+            commit("2", "chore: SHA-256 checksums and ISO-8601 dates"),
+            commit("3", "fix: CVE-2024 in the parser, see RFC-7231 and SHAPE-3"),
+        ];
+        assert_eq!(vec!["PAY-12".to_owned(), "SHAPE-3".to_owned()], keys.find(&commits));
+    }
+
+    #[test]
+    fn writing_to_jira_needs_a_key_pattern() {
+        let jira = JiraConfig {
+            key_pattern: None,
+            url: Some("https://example.atlassian.net".to_owned()),
+            comment: true,
+            transition: None,
+        };
+        assert!(IssueKeys::from_config(&jira).is_err());
+        let listing_only = JiraConfig {
+            comment: false,
+            ..jira.clone()
+        };
+        assert!(IssueKeys::from_config(&listing_only).is_ok());
+        let with_pattern = JiraConfig {
+            key_pattern: Some("PAY-[0-9]+".to_owned()),
+            ..jira
+        };
+        assert!(IssueKeys::from_config(&with_pattern).is_ok());
+    }
+}
@@ -0,0 +1,63 @@
+//! Release party automation: find every repo in an organization whose `master` is ahead of `release`
+//! and open (or update) a release PR for it.
+//!
+//! The `release-party-br` binary is a thin wrapper over this crate. A party run goes:
+//!
+//! * [`config`](config/index.html): load `ignoredrepos.toml`
+//! * discovery: [`party::get_repos_we_care_about`](party/fn.get_repos_we_care_about.html) lists the org's repos,
+//!   which [`github::graphql`](github/graphql/index.html) can look up in batches
+//! * planning and execution: [`party::get_pr_links`](party/fn.get_pr_links.html) compares branches, proposes
+//!   versions and opens PRs, as described by [`party::PartyOptions`](party/struct.PartyOptions.html)
+//! * [`plan`](plan/index.html): the same work split into a reviewable plan file and an `apply` step
+//! * reporting: [`report`](report/index.html) prints the resulting
+//!   [`party::PartyReport`](party/struct.PartyReport.html) or turns it into JSON
+//! * [`history`](history/index.html): a JSON lines log of every run, for the `history` subcommand
+//! * [`http`](http/index.html): the timeouts, proxy and root certificates every HTTP client is built with
+//! * [`jira`](jira/index.html): pick Jira issue keys out of release PR commits and update the issues
+//! * [`notify`](notify/index.html): post the release PRs to a chat webhook or email them
+//!
+//! Everything talks to the repo host through the [`ReleaseHost`](host/trait.ReleaseHost.html) trait,
+//! with [`github::GithubClient`](github/struct.GithubClient.html) as the GitHub implementation, its GETs revalidated
+//! against [`github::cache`](github/cache/index.html), and
+//! [`gitlab::GitlabClient`](gitlab/struct.GitlabClient.html) for GitLab groups,
+//! [`gitea::GiteaClient`](gitea/struct.GiteaClient.html) for Gitea and Forgejo orgs and
+//! [`bitbucket::BitbucketClient`](bitbucket/struct.BitbucketClient.html) for Bitbucket Server projects.
+
+#![cfg_attr(feature = "clippy", feature(plugin))]
+#![cfg_attr(feature = "clippy", plugin(clippy))]
+
+extern crate dirs;
+extern crate indicatif;
+extern crate lettre;
+extern crate lettre_email;
+extern crate native_tls;
+extern crate reqwest;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+extern crate toml;
+extern crate yaml_rust;
+
+pub mod audit;
+pub mod bitbucket;
+pub mod config;
+pub mod credentials;
+pub mod forge;
+pub mod gitea;
+pub mod github;
+pub mod gitlab;
+pub mod history;
+pub mod host;
+pub mod http;
+pub mod jira;
+pub mod notify;
+pub mod party;
+pub mod plan;
+pub mod report;
+pub mod version;
+
+pub use github::GithubClient;
+pub use host::ReleaseHost;
+pub use party::{get_pr_links, PartyOptions, PartyReport};
@@ -3,11 +3,8 @@
 
 #[macro_use]
 extern crate clap;
-extern crate reqwest;
-#[macro_use]
-extern crate serde_derive;
-extern crate toml;
-extern crate indicatif;
+extern crate release_party;
+extern crate serde_json;
 
 #[macro_use]
 extern crate lazy_static;
@@ -16,17 +13,24 @@ extern crate lazy_static;
 extern crate hyper;
 
 use clap::App;
-use reqwest::header::{AUTHORIZATION, USER_AGENT};
-use indicatif::{ProgressBar, ProgressStyle};
-use std::env;
-use std::fs::File;
-use std::io::prelude::*;
-use std::path::Path;
-
-mod github;
-
-static GITHUB_TOKEN: &'static str = "RP_GITHUBTOKEN";
-static USERAGENT: &'static str = "release-party-br";
+use std::path::{Path, PathBuf};
+
+use release_party::party::{
+    get_pr_links, get_repos_we_care_about, preflight, PartyOptions, PartyReport, UnreleasableRepo,
+};
+use release_party::report::{
+    describe_applied_repo, describe_left_out, describe_planned_repo, describe_run, party_json, print_party_links,
+};
+use release_party::http::HttpSettings;
+use release_party::{
+    audit, bitbucket, config, credentials, gitea, github, gitlab, history, jira, notify, plan, ReleaseHost,
+};
+
+// What a personal access token needs to open PRs and update release branches.
+static REQUIRED_SCOPES: &[&str] = &["repo"];
+static GITHUB_API: &'static str = "https://api.github.com";
+static GITLAB_API: &str = "https://gitlab.com/api/v4";
+static GITEA_API: &str = "https://codeberg.org/api/v1";
 
 lazy_static! {
     static ref RP_VERSION: String = {
@@ -40,26 +44,376 @@ fn main() {
     let yaml = load_yaml!("release-party.yml");
     let matches = App::from_yaml(yaml).get_matches();
 
+    if let Some(history_matches) = matches.subcommand_matches("history") {
+        run_history(&matches, history_matches);
+        return;
+    }
+
     let org_url = make_org_url(&matches);
-    let token = match env::var(GITHUB_TOKEN) {
-        Ok(env_var) => env_var,
-        Err(_) => {
+    let config = match config::load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            print_message_and_exit(&e, -1);
+            unreachable!();
+        }
+    };
+    let http = http_settings(&matches, &config);
+    let host: Box<dyn ReleaseHost> = match forge(&matches) {
+        other_forge @ "gitlab" | other_forge @ "gitea" | other_forge @ "bitbucket" => {
+            if let Some("whoami") = matches.subcommand_name() {
+                print_message_and_exit("whoami only knows about GitHub tokens.", -1);
+            }
+            if matches.is_present("GRAPHQL") {
+                print_message_and_exit("--graphql only works with GitHub.", -1);
+            }
+            if matches.is_present("COMMENTPRS") {
+                print_message_and_exit("--comment-on-included-prs only works with GitHub.", -1);
+            }
+            make_forge_client(&matches, other_forge, &http)
+        }
+        _ => {
+            let (github_host, credential_source) = make_github_client(&matches, &http);
+            if let Some("whoami") = matches.subcommand_name() {
+                run_whoami(&matches, &github_host, &credential_source);
+                return;
+            }
+            if will_write(&matches) && !github_host.is_app() {
+                check_token_scopes(&matches, &github_host, &credential_source);
+            }
+            let github_host = with_response_cache(&matches, github_host).with_retries(retry_policy(&matches));
+            if matches.is_present("GRAPHQL") {
+                Box::new(github_host.with_graphql(api_url(&matches)))
+            } else {
+                Box::new(github_host)
+            }
+        }
+    };
+    let host = &*host;
+
+    if let Some(audit_matches) = matches.subcommand_matches("audit") {
+        run_audit(&matches, audit_matches, &org_url, host, &config);
+        return;
+    }
+    if let Some(plan_matches) = matches.subcommand_matches("plan") {
+        run_plan(&matches, plan_matches, &org_url, host, &config);
+        return;
+    }
+    if let Some(apply_matches) = matches.subcommand_matches("apply") {
+        run_apply(&matches, apply_matches, host, &config);
+        return;
+    }
+
+    let repos = repos_we_care_about(&org_url, host, &config);
+    // Said before the party starts changing things, not after.
+    let (_, unreleasable) = preflight(&repos);
+    print_left_out(&matches, "These repos can't be released with the current credentials:", &unreleasable);
+    let report = get_pr_links(&repos, host, &party_options(&matches, &config));
+
+    record_run(&matches, "party", history::party_outcomes(&report));
+
+    if is_json(&matches) {
+        match party_json(&report) {
+            Ok(json) => println!("{}", json),
+            Err(e) => print_message_and_exit(&e, -1),
+        }
+    } else {
+        print_party_links(&report);
+    }
+
+    if !report.release_prs.is_empty() {
+        send_notifications(&matches, &config, &report, &http);
+        update_jira_issues(&matches, &config, &report, &http);
+    }
+}
+
+// Comments on or moves the Jira issues in the release PRs, if `[jira]` says to.
+fn update_jira_issues(
+    matches: &clap::ArgMatches,
+    config: &config::Config,
+    report: &PartyReport,
+    http: &HttpSettings,
+) {
+    let jira = match config.jira {
+        Some(ref jira) if jira.url.is_some() && (jira.comment || jira.transition.is_some()) => jira,
+        _ => return,
+    };
+    if is_dryrun(matches) {
+        return;
+    }
+    match credentials::jira_credentials() {
+        Some((username, token)) => {
+            let client = jira::client_for(username.as_deref(), &token, http);
+            jira::update_issues(jira, report, &client);
+        }
+        None => eprintln!("Set RP_JIRATOKEN to update Jira issues"),
+    }
+}
+
+// Tells the webhook and email recipients in the config about the release PRs. A dry run says what it would send.
+fn send_notifications(
+    matches: &clap::ArgMatches,
+    config: &config::Config,
+    report: &PartyReport,
+    http: &HttpSettings,
+) {
+    let quiet = is_json(matches);
+    if let Some(ref webhook) = config.webhook {
+        if is_dryrun(matches) {
+            if !quiet {
+                println!("\nDry run: would post to the webhook:\n{}", notify::webhook_message(webhook, report));
+            }
+        } else if let Err(e) = notify::notify_webhook(webhook, report, http) {
+            eprintln!("{}", e);
+        }
+    }
+    if let Some(ref email) = config.email {
+        if is_dryrun(matches) {
+            if !quiet {
+                println!("\nDry run: would email {}:\n{}", email.to.join(", "), notify::email_text(report));
+            }
+        } else if let Err(e) = notify::send_email(email, report, credentials::smtp_credentials()) {
+            eprintln!("{}", e);
+        }
+    }
+}
+
+fn run_audit(
+    matches: &clap::ArgMatches,
+    audit_matches: &clap::ArgMatches,
+    org_url: &str,
+    host: &dyn ReleaseHost,
+    config: &config::Config,
+) {
+    let policy = match config.protection {
+        Some(ref policy) => policy,
+        None => {
             print_message_and_exit(
-                &format!("{} environment variable should be set", GITHUB_TOKEN),
+                "Add a [protection] policy to ignoredrepos.toml to audit release branches.",
                 -1,
             );
             unreachable!();
         }
     };
-    let reqwest_client = get_reqwest_client(&token);
+    let fix = audit_matches.is_present("FIX");
+    let dryrun = is_dryrun(matches);
+    let audits = audit::audit_release_protection(
+        &repos_we_care_about(org_url, host, config),
+        policy,
+        fix,
+        dryrun,
+        host,
+    );
 
-    let links = get_pr_links(
-        &get_repos_we_care_about(&org_url, &reqwest_client),
-        &reqwest_client,
-        is_dryrun(&matches),
+    if is_json(matches) {
+        match serde_json::to_string_pretty(&audits) {
+            Ok(json) => println!("{}", json),
+            Err(e) => print_message_and_exit(&format!("Couldn't serialize audit to JSON: {}", e), -1),
+        }
+        return;
+    }
+    let (missing, audits): (Vec<&audit::ProtectionAudit>, Vec<&audit::ProtectionAudit>) =
+        audits.iter().partition(|repo_audit| repo_audit.missing_branch);
+    if audits.is_empty() {
+        println!("\nEvery release branch meets the protection policy.");
+    } else {
+        println!("\nRelease branches that don't meet the protection policy:");
+        for repo_audit in &audits {
+            println!("{}: {}", repo_audit.repo, repo_audit.violations.join(", "));
+            if repo_audit.fixed {
+                println!("  fixed");
+            } else if fix && dryrun {
+                println!("  dry run: would apply the policy");
+            }
+        }
+    }
+    if !missing.is_empty() {
+        println!("\nThese repos don't have a release branch to protect:");
+        for repo_audit in &missing {
+            println!("{}", repo_audit.repo);
+        }
+    }
+}
+
+fn run_plan(
+    matches: &clap::ArgMatches,
+    plan_matches: &clap::ArgMatches,
+    org_url: &str,
+    host: &dyn ReleaseHost,
+    config: &config::Config,
+) {
+    let out = Path::new(plan_matches.value_of("OUT").expect("plan file has a default"));
+    let release_plan = plan::make_plan(
+        matches.value_of("ORG").expect("org is required"),
+        forge(matches),
+        &repos_we_care_about(org_url, host, config),
+        host,
+        &party_options(matches, config),
     );
+    if let Err(e) = plan::write_plan(&release_plan, out) {
+        print_message_and_exit(&e, -1);
+    }
 
-    print_party_links(links);
+    print_left_out(matches, "These repos were left out of the plan:", &release_plan.left_out);
+    if release_plan.repos.is_empty() {
+        println!("\nNothing to release, wrote an empty plan to {}.", out.display());
+        return;
+    }
+    println!("\nWrote the release plan to {}:", out.display());
+    for planned in &release_plan.repos {
+        println!("{}", describe_planned_repo(planned));
+    }
+}
+
+fn run_apply(
+    matches: &clap::ArgMatches,
+    apply_matches: &clap::ArgMatches,
+    host: &dyn ReleaseHost,
+    config: &config::Config,
+) {
+    let path = Path::new(apply_matches.value_of("PLAN").expect("plan file is required"));
+    let release_plan = match plan::read_plan(path) {
+        Ok(release_plan) => release_plan,
+        Err(e) => {
+            print_message_and_exit(&e, -1);
+            unreachable!();
+        }
+    };
+    if let Err(e) = release_plan.check_made_for(matches.value_of("ORG").expect("org is required"), forge(matches)) {
+        print_message_and_exit(&e, -1);
+    }
+    if is_dryrun(matches) {
+        println!("\nDry run: applying {} would do this, if the branches haven't moved:", path.display());
+        for planned in &release_plan.repos {
+            println!("{}", describe_planned_repo(planned));
+        }
+        return;
+    }
+    let applied = plan::apply_plan(&release_plan, host, &party_options(matches, config));
+    record_run(matches, "apply", history::applied_outcomes(&applied));
+
+    println!("\nApplied {}:", path.display());
+    for outcome in &applied {
+        println!("{}", describe_applied_repo(outcome));
+    }
+    if applied.iter().any(|outcome| outcome.refused.is_some()) {
+        print_message_and_exit("\nSome repos changed since planning, make a new plan for them.", -1);
+    }
+}
+
+fn history_file(matches: &clap::ArgMatches) -> PathBuf {
+    match matches.value_of("HISTORYFILE") {
+        Some(path) => PathBuf::from(path),
+        None => history::default_history_file(),
+    }
+}
+
+// `[http]` from the config, with the CLI options winning.
+fn http_settings(matches: &clap::ArgMatches, config: &config::Config) -> HttpSettings {
+    let mut http = config.http.clone().unwrap_or_default();
+    if let Some(seconds) = seconds_option(matches, "CONNECTTIMEOUT", "--connect-timeout") {
+        http.connect_timeout = Some(seconds);
+    }
+    if let Some(seconds) = seconds_option(matches, "TIMEOUT", "--timeout") {
+        http.timeout = Some(seconds);
+    }
+    if let Some(proxy) = matches.value_of("PROXY") {
+        http.proxy = Some(proxy.to_owned());
+    }
+    if let Some(ca_bundle) = matches.value_of("CABUNDLE") {
+        http.ca_bundle = Some(ca_bundle.to_owned());
+    }
+    match HttpSettings::from_config(&http) {
+        Ok(settings) => settings,
+        Err(e) => {
+            print_message_and_exit(&e, -1);
+            unreachable!();
+        }
+    }
+}
+
+fn seconds_option(matches: &clap::ArgMatches, name: &str, flag: &str) -> Option<u64> {
+    if !matches.is_present(name) {
+        return None;
+    }
+    match value_t!(matches, name, u64) {
+        Ok(seconds) => Some(seconds),
+        Err(e) => {
+            print_message_and_exit(&format!("{} should be a number of seconds: {}", flag, e), -1);
+            unreachable!();
+        }
+    }
+}
+
+fn retry_policy(matches: &clap::ArgMatches) -> github::retry::RetryPolicy {
+    match value_t!(matches, "RETRIES", u32) {
+        Ok(retries) => github::retry::RetryPolicy::new(retries),
+        Err(e) => {
+            print_message_and_exit(&format!("--retries should be a number: {}", e), -1);
+            unreachable!();
+        }
+    }
+}
+
+// GitHub GETs are revalidated against the on-disk cache unless --no-cache says otherwise, or there's
+// nowhere private to keep it.
+fn with_response_cache(matches: &clap::ArgMatches, client: github::GithubClient) -> github::GithubClient {
+    if matches.is_present("NOCACHE") {
+        return client;
+    }
+    let dir = match matches.value_of("CACHEDIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => match github::cache::ResponseCache::default_dir() {
+            Some(dir) => dir,
+            None => return client,
+        },
+    };
+    let cache = github::cache::ResponseCache::new(&dir);
+    cache.prune(github::cache::MAX_AGE);
+    client.with_cache(cache)
+}
+
+// Logs the run to the history file. Not being able to is worth a warning, not a failed run.
+fn record_run(matches: &clap::ArgMatches, command: &str, repos: Vec<history::RepoOutcome>) {
+    let org = matches.value_of("ORG").expect("org is required");
+    let options = history::redact_options(::std::env::args().skip(1));
+    let record = history::RunRecord::new(org, command, options, is_dryrun(matches), repos);
+    let path = history_file(matches);
+    if let Err(e) = history::append(&path, &record) {
+        eprintln!("Couldn't add this run to the history: {}", e);
+    }
+}
+
+fn run_history(matches: &clap::ArgMatches, history_matches: &clap::ArgMatches) {
+    let org = matches.value_of("ORG").expect("org is required");
+    let repo = history_matches.value_of("REPO");
+    let limit = match value_t!(history_matches, "LIMIT", usize) {
+        Ok(limit) => limit,
+        Err(e) => {
+            print_message_and_exit(&format!("{}", e), -1);
+            unreachable!();
+        }
+    };
+    let records = match history::read(&history_file(matches)) {
+        Ok(records) => records,
+        Err(e) => {
+            print_message_and_exit(&e, -1);
+            unreachable!();
+        }
+    };
+    let runs = history::query(&records, org, repo, limit);
+    if is_json(matches) {
+        match serde_json::to_string_pretty(&runs) {
+            Ok(json) => println!("{}", json),
+            Err(e) => print_message_and_exit(&format!("Couldn't serialize the history: {}", e), -1),
+        }
+        return;
+    }
+    if runs.is_empty() {
+        println!("No runs for {} in the history.", org);
+    }
+    for run in runs {
+        println!("{}", describe_run(run, repo));
+    }
 }
 
 fn version_string(app: &App) -> String {
@@ -73,6 +427,10 @@ fn is_dryrun(matches: &clap::ArgMatches) -> bool {
     matches.is_present("DRYRUN")
 }
 
+fn is_json(matches: &clap::ArgMatches) -> bool {
+    matches.is_present("JSON")
+}
+
 fn org_is_just_org(org: &str) -> bool {
     if org.contains("https://api.github.com") {
         return false;
@@ -95,6 +453,12 @@ fn make_org_url(matches: &clap::ArgMatches) -> String {
         .value_of("ORG")
         .expect("Please specify a github org");
 
+    match forge(matches) {
+        "gitlab" => return gitlab::group_projects_url(api_url(matches), org),
+        "bitbucket" => return bitbucket::project_repos_url(api_url(matches), org),
+        _ => (),
+    }
+
     if !org_is_just_org(&org) {
         match suggest_org_arg(&org) {
             Ok(suggestion) => {
@@ -106,187 +470,190 @@ fn make_org_url(matches: &clap::ArgMatches) -> String {
         }
     }
 
-    format!("https://api.github.com/orgs/{}/repos", org)
-}
-
-fn get_pr_links(
-    repos: &Vec<github::GithubRepo>,
-    reqwest_client: &reqwest::Client,
-    dryrun: bool,
-) -> Vec<Option<String>> {
-    let pbar = ProgressBar::new(repos.len() as u64);
-    pbar.set_style(ProgressStyle::default_bar().template("[{elapsed_precise}] {bar:50.cyan/blue} {pos:>7}/{len:7} {msg}"));
-    let mut pr_links: Vec<Option<String>> = repos
-        .iter()
-        .map(|repo| {
-            pbar.inc(1);
-            let i = match get_release_pr_for(&repo, reqwest_client, dryrun) {
-                Some(pr_url) => Some(pr_url),
-                None => None,
-            };
-            // update the PR body
-            // pr_url will look like https://github.com/matthewkmayer/release-party-BR/pull/39
-            // split by '/' and grab last chunk.
-            if let Some(ref pr_url) = i {
-                let pr_split = pr_url.split('/').collect::<Vec<&str>>();
-                let pr_num = pr_split.last().expect("PR link malformed?");
-                github::update_pr_body(repo, pr_num, reqwest_client, &RP_VERSION);
-            }
-            i
-        })
-        .collect();
-    pbar.finish();
-    // only keep the Some(PR_URL) items:
-    pr_links.retain(|maybe_pr_link| maybe_pr_link.is_some());
-    pr_links
-}
-
-fn get_reqwest_client(token: &str) -> reqwest::Client {
-    let mut headers = reqwest::header::HeaderMap::new();
-    headers.insert(
-        USER_AGENT,
-        USERAGENT.parse().expect("useragent should be a string"),
-    );
-    headers.insert(
-        AUTHORIZATION,
-        format!("token {}", token)
-            .parse()
-            .expect("token should be a string"),
-    );
-    match reqwest::Client::builder().default_headers(headers).build() {
-        Ok(new_client) => new_client,
-        Err(e) => panic!("Couldn't create new reqwest client: {}", e),
-    }
+    format!("{}/orgs/{}/repos", api_url(matches), org)
 }
 
-fn get_repos_we_care_about(
-    github_org_url: &str,
-    reqwest_client: &reqwest::Client,
-) -> Vec<github::GithubRepo> {
-    let mut repos = match github::get_repos_at(github_org_url, reqwest_client) {
-        Ok(repos) => repos,
-        Err(e) => panic!(format!("Couldn't get repos from github: {}", e)),
-    };
-    let repos_to_ignore = ignored_repos();
-
-    // remove repos we don't care about:
-    repos.retain(|repo| !repos_to_ignore.contains(&repo.name));
-
-    repos
+// Where the repos live: "github" unless --forge says otherwise.
+fn forge<'a>(matches: &'a clap::ArgMatches) -> &'a str {
+    matches.value_of("FORGE").unwrap_or("github")
 }
 
-fn get_release_pr_for(
-    repo: &github::GithubRepo,
-    client: &reqwest::Client,
-    dryrun: bool,
-) -> Option<String> {
-    match github::existing_release_pr_location(repo, client) {
-        Some(url) => Some(url),
-        None => {
-            if !github::is_release_up_to_date_with_master(&repo.url, client) {
-                if dryrun {
-                    Some(format!("Dry run: {} would get a release PR.", repo.url))
-                } else {
-                    match github::create_release_pull_request(repo, client) {
-                        Ok(pr_url) => Some(pr_url),
-                        Err(_) => None,
-                    }
-                }
-            } else {
-                None
-            }
+// GitHub Enterprise, a self-hosted forge, or a stand-in when testing:
+fn api_url<'a>(matches: &'a clap::ArgMatches) -> &'a str {
+    let default_api = match forge(matches) {
+        "gitlab" => GITLAB_API,
+        "gitea" => GITEA_API,
+        // Bitbucket Server is always self-hosted, there's nothing to default to.
+        "bitbucket" if matches.value_of("APIURL").is_none() => {
+            print_message_and_exit(
+                "Use --api-url to say where Bitbucket is, like https://bitbucket.example.com/rest/api/1.0",
+                -1,
+            );
+            unreachable!();
         }
-    }
+        _ => GITHUB_API,
+    };
+    matches
+        .value_of("APIURL")
+        .unwrap_or(default_api)
+        .trim_end_matches('/')
 }
 
-fn print_party_links(pr_links: Vec<Option<String>>) {
-    if !pr_links.is_empty() {
-        println!("\nIt's a release party!  PRs to review and approve:");
-        for link in pr_links {
-            match link {
-                Some(pr_link) => println!("{}", pr_link),
-                None => println!("Party link is None: this shouldn't happen."),
+// A GitHub App when one is given, otherwise the first token we can find.
+fn make_github_client(matches: &clap::ArgMatches, http: &HttpSettings) -> (github::GithubClient, String) {
+    if let (Some(app_id), Some(key_path)) = (matches.value_of("APPID"), matches.value_of("APPKEY")) {
+        let org = matches.value_of("ORG").expect("Please specify a github org");
+        let client = github::app::GithubApp::from_key_file(app_id, Path::new(key_path))
+            .and_then(|app| github::GithubClient::for_app(app.with_http(http.clone()), api_url(matches), org));
+        return match client {
+            Ok(client) => (client, format!("GitHub App {}", app_id)),
+            Err(e) => {
+                print_message_and_exit(&format!("Couldn't authenticate as the GitHub App: {}", e), -1);
+                unreachable!();
             }
-        }
-    } else {
-        println!("\nNo party today, all releases are done.");
+        };
     }
-}
 
-#[derive(Deserialize, Debug)]
-struct IgnoredRepo {
-    ignore: Option<Vec<String>>,
+    let token_file = matches.value_of("TOKENFILE").map(Path::new);
+    match credentials::find_token(token_file, &credentials::credential_host(api_url(matches))) {
+        Ok(found) => (
+            github::GithubClient::new(github::client_for_token(&found.token, http)),
+            found.source,
+        ),
+        Err(e) => {
+            print_message_and_exit(&e, -1);
+            unreachable!();
+        }
+    }
 }
 
-fn ignored_repos() -> Vec<String> {
-    let hfi = match dirs::home_dir() {
-        Some(path) => {
-            if Path::new(&path).join(".ignoredrepos.toml").exists() {
-                Some(Path::new(&path).join(".ignoredrepos.toml"))
-            } else {
-                None
-            }
-        },
-        None => None,
+// GitLab, Gitea or Bitbucket, with a token from the file, the forge's environment variables or a git credential helper.
+fn make_forge_client(matches: &clap::ArgMatches, forge: &str, http: &HttpSettings) -> Box<dyn ReleaseHost> {
+    let env_vars: &[&str] = match forge {
+        "gitlab" => &["RP_GITLABTOKEN", "GITLAB_TOKEN"],
+        "bitbucket" => &["RP_BITBUCKETTOKEN", "BITBUCKET_TOKEN"],
+        _ => &["RP_GITEATOKEN", "GITEA_TOKEN"],
     };
-
-    let lfi = match Path::new("ignoredrepos.toml").exists() {
-        true  => Some(Path::new("ignoredrepos.toml").to_path_buf()),
-        false => None,
+    let token_file = matches.value_of("TOKENFILE").map(Path::new);
+    let host = credentials::credential_host(api_url(matches));
+    let token = match credentials::find_forge_token(token_file, &host, env_vars) {
+        Ok(found) => found.token,
+        Err(e) => {
+            print_message_and_exit(&e, -1);
+            unreachable!();
+        }
     };
+    match forge {
+        "gitlab" => Box::new(gitlab::GitlabClient::new(gitlab::client_for_token(&token, http))),
+        "bitbucket" => Box::new(bitbucket::BitbucketClient::new(bitbucket::client_for_token(&token, http))),
+        _ => Box::new(gitea::GiteaClient::new(github::client_for_token(&token, http), api_url(matches))),
+    }
+}
 
-    let fi = match (lfi, hfi) {
-        (Some(a), _) => a,
-        (None, Some(b)) => b,
-        (_, _) => {println!("The ignoredrepos.toml file not found"); return Vec::new()},
+// Whether this run can change anything on GitHub. Every subcommand that writes only previews on a dry run.
+fn will_write(matches: &clap::ArgMatches) -> bool {
+    let writes = match matches.subcommand() {
+        ("plan", _) | ("whoami", _) => false,
+        ("audit", Some(audit_matches)) => audit_matches.is_present("FIX"),
+        _ => true,
     };
+    writes && !is_dryrun(matches)
+}
 
-    let mut f = match File::open(&fi) {
-        Ok(file) => file,
+// Catch a token that can't open PRs before we open any, rather than part way through the org.
+fn check_token_scopes(matches: &clap::ArgMatches, host: &github::GithubClient, source: &str) {
+    let identity = match host.whoami(api_url(matches)) {
+        Ok(identity) => identity,
         Err(e) => {
-            println!(
-                "Couldn't load ignoredrepos.toml, not ignoring any repos. Reason: {}",
-                e
-            );
-            return Vec::new();
+            print_message_and_exit(&format!("Couldn't check the GitHub token from {}: {}", source, e), -1);
+            unreachable!();
         }
     };
+    let missing = github::missing_scopes(&identity, REQUIRED_SCOPES);
+    if !missing.is_empty() {
+        print_message_and_exit(
+            &format!(
+                "The GitHub token from {} (user {}) is missing the {} scope needed to open PRs.",
+                source,
+                identity.login,
+                missing.join(", ")
+            ),
+            -1,
+        );
+    }
+}
 
-    println!(
-        "Found ignoredrepos.toml file at {:#?}",
-        fi
-    );
-
-    let mut buffer = String::new();
-    match f.read_to_string(&mut buffer) {
-        Ok(_) => (),
-        Err(e) => {
-            println!(
-                "Couldn't read from ignoredrepos.toml, not ignoring any repos. Reason: {}",
-                e
-            );
-            return Vec::new();
+fn run_whoami(matches: &clap::ArgMatches, host: &github::GithubClient, source: &str) {
+    if host.is_app() {
+        println!("Authenticated as {}.", source);
+        return;
+    }
+    match host.whoami(api_url(matches)) {
+        Ok(identity) => {
+            println!("Authenticated as {} with the token from {}.", identity.login, source);
+            match identity.scopes {
+                Some(ref scopes) => println!("Token scopes: {}", scopes.join(", ")),
+                None => println!("The token doesn't list its scopes (a fine-grained token?)."),
+            }
+            let missing = github::missing_scopes(&identity, REQUIRED_SCOPES);
+            if !missing.is_empty() {
+                println!("Missing the {} scope needed to open PRs.", missing.join(", "));
+            }
         }
+        Err(e) => print_message_and_exit(&format!("Couldn't check the GitHub token from {}: {}", source, e), -1),
+    }
+}
+
+fn party_options(matches: &clap::ArgMatches, config: &config::Config) -> PartyOptions {
+    let issue_keys = match config.jira {
+        Some(ref jira) => match jira::IssueKeys::from_config(jira) {
+            Ok(issue_keys) => Some(issue_keys),
+            Err(e) => {
+                print_message_and_exit(&e, -1);
+                unreachable!();
+            }
+        },
+        None => None,
+    };
+    PartyOptions {
+        dryrun: is_dryrun(matches),
+        back_merge: matches.is_present("BACKMERGE"),
+        create_release_branch_from: matches.value_of("CREATERELEASE").map(|r| r.to_owned()),
+        rp_version: RP_VERSION.clone(),
+        comment_on_included_prs: matches.is_present("COMMENTPRS"),
+        label_included_prs: matches.is_present("LABELPRS"),
+        issue_keys,
     }
+}
 
-    let ignored: IgnoredRepo = match toml::from_str(&buffer) {
-        Ok(ignored_repos) => ignored_repos,
+fn repos_we_care_about(
+    org_url: &str,
+    host: &dyn ReleaseHost,
+    config: &config::Config,
+) -> Vec<github::GithubRepo> {
+    match get_repos_we_care_about(org_url, host, config) {
+        Ok(repos) => repos,
         Err(e) => {
-            println!(
-                "Couldn't parse toml from ignoredrepos.toml, not ignoring any repos. Reason: {}",
-                e
-            );
-            return Vec::new();
+            print_message_and_exit(&e, -1);
+            unreachable!();
         }
-    };
-    match ignored.ignore {
-        Some(repos_to_ignore) => repos_to_ignore,
-        None => Vec::new(),
+    }
+}
+
+// Repos a run leaves out go with the report, or to stderr when stdout is for JSON.
+fn print_left_out(matches: &clap::ArgMatches, heading: &str, left_out: &[UnreleasableRepo]) {
+    if left_out.is_empty() {
+        return;
+    }
+    if is_json(matches) {
+        eprintln!("{}", describe_left_out(heading, left_out));
+    } else {
+        println!("{}", describe_left_out(heading, left_out));
     }
 }
 
 fn print_message_and_exit(message: &str, exit_code: i32) {
-    println!("{}", message);
+    eprintln!("{}", message);
     ::std::process::exit(exit_code);
 }
 
@@ -294,12 +661,6 @@ fn print_message_and_exit(message: &str, exit_code: i32) {
 mod tests {
     use super::*;
 
-    #[test]
-    fn get_ignored_repos_happy_path() {
-        let ignored_repositories = vec!["calagator".to_owned(), "moe".to_owned()];
-        assert_eq!(ignored_repositories, ignored_repos());
-    }
-
     #[test]
     fn handle_malformed_org() {
         assert_eq!(
@@ -0,0 +1,277 @@
+use lettre::smtp::authentication::Credentials;
+use lettre::{ClientSecurity, ClientTlsParameters, SmtpClient, Transport};
+use lettre_email::EmailBuilder;
+use native_tls::TlsConnector;
+use reqwest::header::USER_AGENT;
+
+use config::{EmailConfig, WebhookConfig, WebhookFormat};
+use http::HttpSettings;
+use party::{PartyLink, PartyReport};
+use report::describe_party_link;
+
+/// The email subject when the config doesn't give a `subject`.
+pub static DEFAULT_SUBJECT: &str = "Release party: {count} release PRs to review";
+
+/// The webhook message when the config doesn't give a `template`.
+pub static DEFAULT_TEMPLATE: &str = "It's a release party! {count} PRs to review and approve:\n{prs}\n{mentions}";
+
+/// Fills in a webhook template: `{count}` release PRs, `{prs}` one line each, `{mentions}` space separated.
+pub fn render_message(report: &PartyReport, template: &str, mentions: &[String]) -> String {
+    let prs = report
+        .release_prs
+        .iter()
+        .map(|link| format!("{}: {}", link.repo, describe_party_link(link)))
+        .collect::<Vec<String>>()
+        .join("\n");
+    template
+        .replace("{count}", &report.release_prs.len().to_string())
+        .replace("{prs}", &prs)
+        .replace("{mentions}", &mentions.join(" "))
+        .trim()
+        .to_owned()
+}
+
+/// What to POST: Slack and generic JSON hooks take `text`, Teams connectors a MessageCard.
+/// Generic JSON also gets the release PRs themselves, for hooks that do their own formatting.
+pub fn webhook_payload(format: WebhookFormat, message: &str, report: &PartyReport) -> serde_json::Value {
+    match format {
+        WebhookFormat::Slack => json!({ "text": message }),
+        // Teams only breaks lines on blank lines.
+        WebhookFormat::Teams => json!({
+            "@type": "MessageCard",
+            "@context": "https://schema.org/extensions",
+            "summary": "Release party",
+            "text": message.replace('\n', "\n\n"),
+        }),
+        WebhookFormat::Json => json!({
+            "text": message,
+            "release_prs": report
+                .release_prs
+                .iter()
+                .map(|link| json!({
+                    "repo": link.repo,
+                    "pr_link": link.pr_link,
+                    "proposed_version": link.proposed_version.as_ref().map(|proposal| proposal.to_string()),
+                }))
+                .collect::<Vec<serde_json::Value>>(),
+        }),
+    }
+}
+
+/// The message for the webhook's template and mentions.
+pub fn webhook_message(webhook: &WebhookConfig, report: &PartyReport) -> String {
+    let template = webhook.template.as_ref().map_or(DEFAULT_TEMPLATE, |t| t.as_str());
+    render_message(report, template, &webhook.mentions)
+}
+
+/// Posts the release PRs to the webhook. Nothing is sent when there are no release PRs.
+pub fn notify_webhook(webhook: &WebhookConfig, report: &PartyReport, http: &HttpSettings) -> Result<(), String> {
+    if report.release_prs.is_empty() {
+        return Ok(());
+    }
+    let message = webhook_message(webhook, report);
+    let payload = webhook_payload(webhook.format, &message, report);
+    let client = match http.builder().build() {
+        Ok(client) => client,
+        Err(e) => return Err(format!("Couldn't create the webhook client: {}", e)),
+    };
+    let res = match client
+        .post(&webhook.url)
+        .header(USER_AGENT, "release-party-br")
+        .json(&payload)
+        .send()
+    {
+        Ok(response) => response,
+        Err(e) => return Err(format!("Couldn't post to the webhook: {}", e)),
+    };
+    if !res.status().is_success() {
+        return Err(format!("The webhook responded with {}", res.status()));
+    }
+    Ok(())
+}
+
+/// The plain text email: each release PR with the commits it ships.
+pub fn email_text(report: &PartyReport) -> String {
+    let mut text = format!("It's a release party! {} PRs to review and approve:\n", report.release_prs.len());
+    for link in &report.release_prs {
+        text.push_str(&format!("\n{}: {}\n", link.repo, describe_party_link(link)));
+        for summary in release_notes(link) {
+            text.push_str(&format!("* {}\n", summary));
+        }
+    }
+    text
+}
+
+/// The same as `email_text`, as HTML.
+pub fn email_html(report: &PartyReport) -> String {
+    let mut html = format!(
+        "<p>It's a release party! {} PRs to review and approve:</p>\n<ul>\n",
+        report.release_prs.len()
+    );
+    for link in &report.release_prs {
+        html.push_str(&format!(
+            "<li><a href=\"{}\">{}</a>: {}",
+            escape_html(&link.pr_link),
+            escape_html(&link.repo),
+            escape_html(&describe_party_link(link))
+        ));
+        let notes = release_notes(link);
+        if !notes.is_empty() {
+            html.push_str("\n<ul>\n");
+            for summary in notes {
+                html.push_str(&format!("<li>{}</li>\n", escape_html(summary)));
+            }
+            html.push_str("</ul>\n");
+        }
+        html.push_str("</li>\n");
+    }
+    html.push_str("</ul>\n");
+    html
+}
+
+// The release notes for a PR: the summary of each commit it ships.
+fn release_notes(link: &PartyLink) -> Vec<&str> {
+    match link.release_diff {
+        Some(ref diff) => diff.commits.iter().map(|c| c.summary.as_str()).collect(),
+        None => Vec::new(),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Emails the release PRs and their release notes, logging in with `credentials` if given.
+/// Nothing is sent when there are no release PRs.
+pub fn send_email(
+    email: &EmailConfig,
+    report: &PartyReport,
+    credentials: Option<(String, String)>,
+) -> Result<(), String> {
+    if report.release_prs.is_empty() {
+        return Ok(());
+    }
+    let subject = email
+        .subject
+        .as_ref()
+        .map_or(DEFAULT_SUBJECT, |s| s.as_str())
+        .replace("{count}", &report.release_prs.len().to_string());
+    let mut builder = EmailBuilder::new().from(email.from.as_str()).subject(subject);
+    for to in &email.to {
+        builder = builder.to(to.as_str());
+    }
+    let message = match builder.alternative(email_html(report), email_text(report)).build() {
+        Ok(message) => message,
+        Err(e) => return Err(format!("Couldn't build the release party email: {}", e)),
+    };
+
+    let security = if email.starttls {
+        let connector = match TlsConnector::new() {
+            Ok(connector) => connector,
+            Err(e) => return Err(format!("Couldn't set up TLS for {}: {}", email.host, e)),
+        };
+        ClientSecurity::Required(ClientTlsParameters::new(email.host.clone(), connector))
+    } else {
+        ClientSecurity::None
+    };
+    let mut client = match SmtpClient::new((email.host.as_str(), email.port), security) {
+        Ok(client) => client,
+        Err(e) => return Err(format!("Couldn't reach {}:{}: {}", email.host, email.port, e)),
+    };
+    if let Some((username, password)) = credentials {
+        client = client.credentials(Credentials::new(username, password));
+    }
+    let mut transport = client.transport();
+    let result = transport.send(message.into());
+    transport.close();
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Couldn't send the release party email through {}: {}", email.host, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use party::{ReleaseCommit, ReleaseDiff};
+
+    fn report_with_prs(repos: &[&str]) -> PartyReport {
+        PartyReport {
+            release_prs: repos
+                .iter()
+                .enumerate()
+                .map(|(i, repo)| PartyLink {
+                    repo: repo.to_string(),
+                    pr_link: format!("https://github.com/org/{}/pull/{}", repo, i + 1),
+                    release_diff: None,
+                    proposed_version: None,
+                    dry_run: None,
+                    commented_prs: Vec::new(),
+                    issue_keys: Vec::new(),
+                })
+                .collect(),
+            ..PartyReport::default()
+        }
+    }
+
+    #[test]
+    fn renders_the_template() {
+        let report = report_with_prs(&["moe", "calagator"]);
+        assert_eq!(
+            "2 to review: moe: https://github.com/org/moe/pull/1\ncalagator: https://github.com/org/calagator/pull/2 cc <@U1> <!here>",
+            render_message(&report, "{count} to review: {prs} cc {mentions}", &["<@U1>".to_owned(), "<!here>".to_owned()])
+        );
+        assert_eq!(
+            "It's a release party! 1 PRs to review and approve:\nmoe: https://github.com/org/moe/pull/1",
+            render_message(&report_with_prs(&["moe"]), DEFAULT_TEMPLATE, &[])
+        );
+    }
+
+    #[test]
+    fn payload_per_format() {
+        let report = report_with_prs(&["moe"]);
+        assert_eq!(json!({"text": "hi\nthere"}), webhook_payload(WebhookFormat::Slack, "hi\nthere", &report));
+        let teams = webhook_payload(WebhookFormat::Teams, "hi\nthere", &report);
+        assert_eq!("MessageCard", teams["@type"]);
+        assert_eq!("hi\n\nthere", teams["text"]);
+        let generic = webhook_payload(WebhookFormat::Json, "hi", &report);
+        assert_eq!("https://github.com/org/moe/pull/1", generic["release_prs"][0]["pr_link"]);
+    }
+
+    #[test]
+    fn nothing_to_say_without_release_prs() {
+        let webhook = WebhookConfig {
+            url: "http://127.0.0.1:1/unreachable".to_owned(),
+            format: WebhookFormat::Slack,
+            template: None,
+            mentions: Vec::new(),
+        };
+        assert_eq!(Ok(()), notify_webhook(&webhook, &PartyReport::default(), &HttpSettings::default()));
+    }
+
+    #[test]
+    fn email_lists_release_notes_as_text_and_html() {
+        let mut report = report_with_prs(&["moe"]);
+        report.release_prs[0].release_diff = Some(ReleaseDiff {
+            status: "ahead".to_owned(),
+            commits_to_release: 1,
+            release_only_commits: 0,
+            total_commits: 1,
+            commits: vec![ReleaseCommit {
+                sha: "abc1234".to_owned(),
+                summary: "Fix <script> escaping".to_owned(),
+            }],
+            files: Vec::new(),
+        });
+
+        let text = email_text(&report);
+        assert!(text.contains("moe: https://github.com/org/moe/pull/1 (1 commits, 0 files)"), "{}", text);
+        assert!(text.contains("* Fix <script> escaping"), "{}", text);
+        let html = email_html(&report);
+        assert!(html.contains("<a href=\"https://github.com/org/moe/pull/1\">moe</a>"), "{}", html);
+        assert!(html.contains("<li>Fix &lt;script&gt; escaping</li>"), "{}", html);
+    }
+}
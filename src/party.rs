@@ -0,0 +1,711 @@
+use indicatif::{ProgressBar, ProgressStyle};
+
+use config;
+use github;
+use host::{self, ReleaseHost};
+use jira::IssueKeys;
+use version;
+
+/// A release PR, open or (on a dry run) still to be opened, for one repo.
+#[derive(Serialize, Debug)]
+pub struct PartyLink {
+    pub repo: String,
+    pub pr_link: String,
+    pub release_diff: Option<ReleaseDiff>,
+    pub proposed_version: Option<version::VersionProposal>,
+    // Dry run: the request we'd have made for this PR instead of making it.
+    pub dry_run: Option<PrPreview>,
+    // Merged feature PRs told they're in this release, or on a dry run the ones that would be.
+    pub commented_prs: Vec<String>,
+    // Jira issue keys in the commits being released.
+    pub issue_keys: Vec<String>,
+}
+
+/// A release PR request a dry run held back: opening a new PR, or rewriting an existing PR's body.
+#[derive(Serialize, Debug)]
+pub struct PrPreview {
+    // "POST .../pulls" or "PATCH .../pulls/39".
+    pub request: String,
+    // Only set when opening a new PR.
+    pub title: Option<String>,
+    // The body the PR has now, when it already exists.
+    pub current_body: Option<String>,
+    pub body: String,
+}
+
+// What's shipping from master to release, plus anything on release that master doesn't have.
+#[derive(Serialize, Debug)]
+pub struct ReleaseDiff {
+    // GitHub's status of master compared to release: "ahead", "behind", "diverged" or "identical".
+    pub status: String,
+    pub commits_to_release: i32,
+    pub release_only_commits: i32,
+    pub total_commits: i32,
+    pub commits: Vec<ReleaseCommit>,
+    pub files: Vec<ReleaseFile>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ReleaseCommit {
+    pub sha: String,
+    pub summary: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ReleaseFile {
+    pub filename: String,
+    pub status: String,
+    pub additions: i32,
+    pub deletions: i32,
+}
+
+impl ReleaseDiff {
+    fn from_compare(compare: &github::CompareCommitsResponse) -> ReleaseDiff {
+        ReleaseDiff {
+            status: compare.status.clone(),
+            commits_to_release: compare.ahead_by,
+            release_only_commits: compare.behind_by,
+            total_commits: compare.total_commits,
+            commits: compare
+                .commits
+                .iter()
+                .map(|c| ReleaseCommit {
+                    sha: c.sha.clone(),
+                    summary: c.actual_commit.message.lines().next().unwrap_or("").to_owned(),
+                })
+                .collect(),
+            files: compare
+                .files
+                .iter()
+                .map(|f| ReleaseFile {
+                    filename: f.filename.clone(),
+                    status: f.status.clone(),
+                    additions: f.additions,
+                    deletions: f.deletions,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A release branch with commits master doesn't have.
+#[derive(Serialize, Debug)]
+pub struct BackMerge {
+    pub repo: String,
+    // GitHub's status of master compared to release: "behind" or "diverged".
+    pub status: String,
+    pub release_only_commits: i32,
+    pub back_merge_pr: Option<String>,
+}
+
+/// A repo with no release branch, and whether we created one.
+#[derive(Serialize, Debug)]
+pub struct MissingReleaseBranch {
+    pub repo: String,
+    // The ref we were asked to start the release branch from, and the commit it pointed to.
+    pub created_from: Option<String>,
+    pub sha: Option<String>,
+    pub created: bool,
+    // Why creating the branch failed, when it did.
+    pub error: Option<String>,
+}
+
+/// A repo left out of the run, like one the current credentials can't release, and why.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct UnreleasableRepo {
+    pub repo: String,
+    pub reason: String,
+}
+
+/// Everything a party run found and did, printed by `report` or serialized as `--json`.
+#[derive(Serialize, Debug, Default)]
+pub struct PartyReport {
+    pub release_prs: Vec<PartyLink>,
+    pub back_merges: Vec<BackMerge>,
+    pub missing_release_branches: Vec<MissingReleaseBranch>,
+    pub unreleasable: Vec<UnreleasableRepo>,
+}
+
+/// How a party run should behave, the library side of the CLI flags.
+pub struct PartyOptions {
+    /// Say what would change without opening PRs or creating branches.
+    pub dryrun: bool,
+    /// Open back-merge PRs for release branches that have commits master doesn't.
+    pub back_merge: bool,
+    /// Create missing release branches from this ref: a branch, tag, SHA, `latest-tag` or `first-commit`.
+    pub create_release_branch_from: Option<String>,
+    /// Signed at the bottom of release PR bodies.
+    pub rp_version: String,
+    /// Comment on each merged feature PR in a release PR to say it's included.
+    pub comment_on_included_prs: bool,
+    /// Also label the feature PRs commented on with `host::IN_RELEASE_LABEL`.
+    pub label_included_prs: bool,
+    /// Pick Jira issue keys out of the commits, for the PR body and the report.
+    pub issue_keys: Option<IssueKeys>,
+}
+
+/// Opens or finds the release PR for each repo, keeping their bodies up to date.
+pub fn get_pr_links(
+    repos: &[github::GithubRepo],
+    host: &dyn ReleaseHost,
+    options: &PartyOptions,
+) -> PartyReport {
+    let (repos, unreleasable) = preflight(repos);
+    // Hosts that look repos up in batches move the progress bar a batch at a time.
+    let batch_size = host.batch_size().unwrap_or(1);
+    let pbar = ProgressBar::new(repos.chunks(batch_size).len() as u64);
+    pbar.set_style(ProgressStyle::default_bar().template("[{elapsed_precise}] {bar:50.cyan/blue} {pos:>7}/{len:7} {msg}"));
+    if batch_size > 1 {
+        pbar.set_message(&format!("batches of {} repos", batch_size));
+    }
+    let mut report = PartyReport {
+        unreleasable,
+        ..PartyReport::default()
+    };
+    for batch in repos.chunks(batch_size) {
+        pbar.inc(1);
+        host.prefetch(batch);
+        for repo in batch {
+            release_repo(repo, host, options, &mut report);
+        }
+    }
+    pbar.finish();
+    report
+}
+
+// Finds or opens the repo's release PR, and back-merge PR if needed, adding them to the report.
+fn release_repo(repo: &github::GithubRepo, host: &dyn ReleaseHost, options: &PartyOptions, report: &mut PartyReport) {
+    let compare = compare_or_create_release_branch(repo, host, options, report);
+    if let Some(ref diff) = compare {
+        if host::release_needs_back_merge(diff) {
+            report
+                .back_merges
+                .push(get_back_merge_for(repo, diff, host, options));
+        }
+    }
+    let release_pr = match get_release_pr_for(repo, compare.as_ref(), host, options.dryrun) {
+        Some(release_pr) => release_pr,
+        None => return,
+    };
+    let proposal = compare
+        .as_ref()
+        .map(|diff| host::propose_release_version(host, repo, diff));
+    let mut dry_run = None;
+    let mut commented_prs = Vec::new();
+    let pr_url = match release_pr {
+        ReleasePr::Open(pr_url) => {
+            // update the PR body, or on a dry run just show what it would become
+            let pr_num = host::pr_number(&pr_url);
+            if options.dryrun {
+                dry_run = preview_body_update(host, repo, pr_num, options, proposal.as_ref());
+            } else if let Err(e) = host::update_pr_body(
+                host,
+                repo,
+                pr_num,
+                &options.rp_version,
+                proposal.as_ref(),
+                options.issue_keys.as_ref(),
+            ) {
+                eprintln!("Couldn't update the release PR body for {}: {}", repo.name, e);
+            }
+            if options.comment_on_included_prs {
+                commented_prs = comment_on_included_prs(host, repo, pr_num, options);
+            }
+            pr_url
+        }
+        ReleasePr::WouldOpen(message) => {
+            dry_run = compare.as_ref().map(|diff| PrPreview {
+                request: format!("POST {}/pulls", repo.url),
+                title: Some(host::RELEASE_PR_TITLE.to_owned()),
+                current_body: None,
+                body: host::release_pr_body(
+                    &diff.commits,
+                    &options.rp_version,
+                    proposal.as_ref(),
+                    options.issue_keys.as_ref(),
+                ),
+            });
+            message
+        }
+    };
+    report.release_prs.push(PartyLink {
+        repo: repo.name.clone(),
+        pr_link: pr_url,
+        release_diff: compare.as_ref().map(ReleaseDiff::from_compare),
+        proposed_version: proposal,
+        dry_run,
+        commented_prs,
+        issue_keys: match (compare.as_ref(), options.issue_keys.as_ref()) {
+            (Some(diff), Some(issue_keys)) => issue_keys.find(&diff.commits),
+            _ => Vec::new(),
+        },
+    });
+}
+
+// Builds the body the release PR would get without touching the PR.
+fn preview_body_update(
+    host: &dyn ReleaseHost,
+    repo: &github::GithubRepo,
+    pr_number: &str,
+    options: &PartyOptions,
+    proposal: Option<&version::VersionProposal>,
+) -> Option<PrPreview> {
+    let preview = host.pr_commits(repo, pr_number).and_then(|commits| {
+        let current_body = host.pr_body(repo, pr_number)?;
+        Ok(PrPreview {
+            request: format!("PATCH {}/pulls/{}", repo.url, pr_number),
+            title: None,
+            current_body,
+            body: host::release_pr_body(&commits, &options.rp_version, proposal, options.issue_keys.as_ref()),
+        })
+    });
+    match preview {
+        Ok(preview) => Some(preview),
+        Err(e) => {
+            eprintln!("Couldn't work out the release PR body for {}: {}", repo.name, e);
+            None
+        }
+    }
+}
+
+// Tells the authors of the feature PRs merged into this release PR that their change is on its way,
+// skipping PRs that already have the comment. A dry run only works out who it would tell.
+fn comment_on_included_prs(
+    host: &dyn ReleaseHost,
+    repo: &github::GithubRepo,
+    release_pr_number: &str,
+    options: &PartyOptions,
+) -> Vec<String> {
+    let commits = match host.pr_commits(repo, release_pr_number) {
+        Ok(commits) => commits,
+        Err(e) => {
+            eprintln!("Couldn't find the PRs in the release PR for {}: {}", repo.name, e);
+            return Vec::new();
+        }
+    };
+    let comment = host::included_in_release_comment(release_pr_number);
+    let mut commented = Vec::new();
+    for pr_number in host::merged_pr_numbers(&commits) {
+        let already_commented = match host.pr_comments(repo, pr_number) {
+            Ok(ref comments) => host::has_comment(comments, &comment),
+            Err(e) => {
+                eprintln!("Couldn't read the comments on {} #{}: {}", repo.name, pr_number, e);
+                continue;
+            }
+        };
+        if !already_commented {
+            if !options.dryrun {
+                if let Err(e) = host.comment_on_pr(repo, pr_number, &comment) {
+                    eprintln!("Couldn't comment on {} #{}: {}", repo.name, pr_number, e);
+                    continue;
+                }
+            }
+            commented.push(format!("#{}", pr_number));
+        }
+        // Checked on its own, so a label that failed after the comment went up is added on the next run.
+        if options.label_included_prs && !options.dryrun {
+            label_included_pr(host, repo, pr_number);
+        }
+    }
+    commented
+}
+
+fn label_included_pr(host: &dyn ReleaseHost, repo: &github::GithubRepo, pr_number: &str) {
+    match host.pr_labels(repo, pr_number) {
+        Ok(ref labels) if labels.iter().any(|l| l == host::IN_RELEASE_LABEL) => (),
+        Ok(_) => {
+            if let Err(e) = host.add_pr_label(repo, pr_number, host::IN_RELEASE_LABEL) {
+                eprintln!("Couldn't label {} #{}: {}", repo.name, pr_number, e);
+            }
+        }
+        Err(e) => eprintln!("Couldn't read the labels on {} #{}: {}", repo.name, pr_number, e),
+    }
+}
+
+// A missing release branch makes the compare fail: report it instead of quietly skipping the repo,
+// and create the branch if we were asked to so the repo can join this release.
+fn compare_or_create_release_branch(
+    repo: &github::GithubRepo,
+    host: &dyn ReleaseHost,
+    options: &PartyOptions,
+    report: &mut PartyReport,
+) -> Option<github::CompareCommitsResponse> {
+    let compare_error = match host::compare_release_with_master(host, repo) {
+        Ok(compare) => return Some(compare),
+        Err(e) => e,
+    };
+    match host.branch_exists(repo, "release") {
+        Ok(false) => (),
+        Ok(true) => {
+            eprintln!("Couldn't compare release with master for {}: {}", repo.name, compare_error);
+            return None;
+        }
+        Err(e) => {
+            eprintln!("Couldn't compare release with master for {}: {}", repo.name, e);
+            return None;
+        }
+    }
+
+    let mut missing = MissingReleaseBranch {
+        repo: repo.name.clone(),
+        created_from: options.create_release_branch_from.clone(),
+        sha: None,
+        created: false,
+        error: None,
+    };
+    let from = match options.create_release_branch_from {
+        Some(ref from) => from,
+        None => {
+            report.missing_release_branches.push(missing);
+            return None;
+        }
+    };
+    match host::resolve_ref(host, repo, from) {
+        Ok(sha) => missing.sha = Some(sha),
+        Err(e) => eprintln!("Couldn't find {} to start a release branch for {}: {}", from, repo.name, e),
+    }
+    if options.dryrun {
+        report.missing_release_branches.push(missing);
+        return None;
+    }
+    if let Some(ref sha) = missing.sha {
+        match host.create_branch(repo, "release", sha) {
+            Ok(_) => missing.created = true,
+            Err(e) => {
+                eprintln!("Couldn't create release branch for {}: {}", repo.name, e);
+                missing.error = Some(e);
+            }
+        }
+    }
+    let created = missing.created;
+    report.missing_release_branches.push(missing);
+    if !created {
+        return None;
+    }
+    match host::compare_release_with_master(host, repo) {
+        Ok(compare) => Some(compare),
+        Err(e) => {
+            eprintln!("Couldn't compare release with master for {}: {}", repo.name, e);
+            None
+        }
+    }
+}
+
+fn get_back_merge_for(
+    repo: &github::GithubRepo,
+    release_diff: &github::CompareCommitsResponse,
+    host: &dyn ReleaseHost,
+    options: &PartyOptions,
+) -> BackMerge {
+    let back_merge_pr = if options.back_merge {
+        match host::existing_back_merge_pr_location(host, repo) {
+            Some(url) => Some(url),
+            None => {
+                if options.dryrun {
+                    Some(format!("Dry run: {} would get a back-merge PR.", repo.url))
+                } else {
+                    match host::create_back_merge_pull_request(host, repo) {
+                        Ok(pr_url) => Some(pr_url),
+                        Err(e) => {
+                            eprintln!("Couldn't create back-merge PR for {}: {}", repo.name, e);
+                            None
+                        }
+                    }
+                }
+            }
+        }
+    } else {
+        None
+    };
+    BackMerge {
+        repo: repo.name.clone(),
+        status: release_diff.status.clone(),
+        release_only_commits: release_diff.behind_by,
+        back_merge_pr,
+    }
+}
+
+/// Splits off the repos we can't push to, going by the permissions in the repo listing, so they're
+/// reported up front instead of failing one by one part way through a run.
+pub fn preflight(repos: &[github::GithubRepo]) -> (Vec<&github::GithubRepo>, Vec<UnreleasableRepo>) {
+    let mut releasable = Vec::new();
+    let mut unreleasable = Vec::new();
+    for repo in repos {
+        if repo.can_push() {
+            releasable.push(repo);
+        } else {
+            unreleasable.push(UnreleasableRepo {
+                repo: repo.name.clone(),
+                reason: "no push permission with these credentials".to_owned(),
+            });
+        }
+    }
+    (releasable, unreleasable)
+}
+
+/// Every repo in the org that isn't on the config's ignore list.
+pub fn get_repos_we_care_about(
+    github_org_url: &str,
+    host: &dyn ReleaseHost,
+    config: &config::Config,
+) -> Result<Vec<github::GithubRepo>, String> {
+    let mut repos = match host.list_repos(github_org_url) {
+        Ok(repos) => repos,
+        Err(e) => return Err(format!("Couldn't get repos from github: {}", e)),
+    };
+    let repos_to_ignore = config.ignored_repos();
+
+    // remove repos we don't care about:
+    repos.retain(|repo| !repos_to_ignore.contains(&repo.name));
+
+    Ok(repos)
+}
+
+/// The release PR for one repo.
+pub enum ReleasePr {
+    // A PR on GitHub, whether it was already there or we just opened it.
+    Open(String),
+    // Dry run: the repo needs a release PR but there isn't one to link to.
+    WouldOpen(String),
+}
+
+/// The release PR for `repo`: the one already open, or a new one when master is ahead of release.
+pub fn get_release_pr_for(
+    repo: &github::GithubRepo,
+    release_diff: Option<&github::CompareCommitsResponse>,
+    host: &dyn ReleaseHost,
+    dryrun: bool,
+) -> Option<ReleasePr> {
+    match host::existing_release_pr_location(host, repo) {
+        Some(url) => Some(ReleasePr::Open(url)),
+        None => {
+            // If we couldn't compare the branches there's nothing we can confidently release.
+            let up_to_date = match release_diff {
+                Some(diff) => host::is_release_up_to_date_with_master(diff),
+                None => true,
+            };
+            if !up_to_date {
+                if dryrun {
+                    Some(ReleasePr::WouldOpen(format!(
+                        "Dry run: {} would get a release PR.",
+                        repo.url
+                    )))
+                } else {
+                    match host::create_release_pull_request(host, repo) {
+                        Ok(pr_url) => Some(ReleasePr::Open(pr_url)),
+                        Err(e) => {
+                            eprintln!("Couldn't create release PR for {}: {}", repo.name, e);
+                            None
+                        }
+                    }
+                }
+            } else {
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use host::fake::InMemoryHost;
+
+    fn options(dryrun: bool) -> PartyOptions {
+        PartyOptions {
+            dryrun,
+            back_merge: false,
+            create_release_branch_from: None,
+            rp_version: "release-party-br test".to_owned(),
+            comment_on_included_prs: false,
+            label_included_prs: false,
+            issue_keys: None,
+        }
+    }
+
+    #[test]
+    fn existing_release_pr_is_reused_and_its_body_updated() {
+        let host = InMemoryHost::new();
+        let repo = host.add_repo("moe", &["Merge pull request #12 from org/feature"], &[]);
+        host.add_pr("moe", 7, "master", "release");
+
+        let report = get_pr_links(&[repo], &host, &options(false));
+
+        assert_eq!("https://github.com/org/moe/pull/7", report.release_prs[0].pr_link);
+        assert!(!host.made_call("create_pr"));
+        let body = host.pulls("moe")[0].body.clone().unwrap();
+        assert!(body.contains("* #12"));
+    }
+
+    #[test]
+    fn release_pr_is_created_when_master_is_ahead() {
+        let host = InMemoryHost::new();
+        let repo = host.add_repo("moe", &["feat: a page"], &[]);
+
+        let report = get_pr_links(&[repo], &host, &options(false));
+
+        assert_eq!(1, report.release_prs.len());
+        let pulls = host.pulls("moe");
+        assert_eq!(1, pulls.len());
+        assert_eq!(("master", "release"), (pulls[0].head.as_str(), pulls[0].base.as_str()));
+    }
+
+    #[test]
+    fn dry_run_opens_nothing() {
+        let host = InMemoryHost::new();
+        let repo = host.add_repo("moe", &["feat: a page"], &[]);
+
+        match get_release_pr_for(&repo, host.compare(&repo, "release", "master").ok().as_ref(), &host, true) {
+            Some(ReleasePr::WouldOpen(message)) => assert!(message.starts_with("Dry run")),
+            _ => panic!("dry run should only say what it would open"),
+        }
+        assert!(host.pulls("moe").is_empty());
+        assert!(!host.made_call("update_pr_body"));
+    }
+
+    #[test]
+    fn included_prs_are_commented_on_once_and_labelled() {
+        let host = InMemoryHost::new();
+        let repo = host.add_repo(
+            "moe",
+            &["Merge pull request #12 from org/feature", "Merge pull request #14 from org/fix"],
+            &[],
+        );
+        host.add_pr("moe", 7, "master", "release");
+        host.repos
+            .borrow_mut()
+            .get_mut("moe")
+            .unwrap()
+            .comments
+            .insert("14".to_owned(), vec!["Included in release PR #7".to_owned()]);
+        let options = PartyOptions {
+            comment_on_included_prs: true,
+            label_included_prs: true,
+            ..options(false)
+        };
+
+        let report = get_pr_links(&[repo], &host, &options);
+
+        assert_eq!(vec!["#12".to_owned()], report.release_prs[0].commented_prs);
+        let repos = host.repos.borrow();
+        assert_eq!(vec!["Included in release PR #7".to_owned()], repos["moe"].comments["12"]);
+        assert_eq!(1, repos["moe"].comments["14"].len());
+        assert_eq!(vec!["in-release".to_owned()], repos["moe"].labels["12"]);
+        // #14 was commented on before, but never got its label.
+        assert_eq!(vec!["in-release".to_owned()], repos["moe"].labels["14"]);
+    }
+
+    #[test]
+    fn labelled_prs_are_not_labelled_again() {
+        let host = InMemoryHost::new();
+        let repo = host.add_repo("moe", &["Merge pull request #12 from org/feature"], &[]);
+        host.add_pr("moe", 7, "master", "release");
+        {
+            let mut repos = host.repos.borrow_mut();
+            let state = repos.get_mut("moe").unwrap();
+            state
+                .comments
+                .insert("12".to_owned(), vec!["Included in release PR #7\n".to_owned()]);
+            state.labels.insert("12".to_owned(), vec!["in-release".to_owned()]);
+        }
+        let options = PartyOptions {
+            comment_on_included_prs: true,
+            label_included_prs: true,
+            ..options(false)
+        };
+
+        let report = get_pr_links(&[repo], &host, &options);
+
+        assert!(report.release_prs[0].commented_prs.is_empty());
+        assert!(!host.made_call("comment_on_pr"));
+        assert!(!host.made_call("add_pr_label"));
+    }
+
+    #[test]
+    fn dry_run_only_says_which_prs_it_would_comment_on() {
+        let host = InMemoryHost::new();
+        let repo = host.add_repo("moe", &["Merge pull request #12 from org/feature"], &[]);
+        host.add_pr("moe", 7, "master", "release");
+        let options = PartyOptions {
+            comment_on_included_prs: true,
+            ..options(true)
+        };
+
+        let report = get_pr_links(&[repo], &host, &options);
+
+        assert_eq!(vec!["#12".to_owned()], report.release_prs[0].commented_prs);
+        assert!(!host.made_call("comment_on_pr"));
+    }
+
+    #[test]
+    fn dry_run_leaves_existing_pr_body_alone_and_previews_it() {
+        let host = InMemoryHost::new();
+        let repo = host.add_repo("moe", &["Merge pull request #12 from org/feature"], &[]);
+        host.add_pr("moe", 7, "master", "release");
+
+        let report = get_pr_links(&[repo], &host, &options(true));
+
+        assert!(!host.made_call("update_pr_body"));
+        let preview = report.release_prs[0].dry_run.as_ref().unwrap();
+        assert_eq!("PATCH memory://moe/pulls/7", preview.request);
+        assert_eq!(None, preview.current_body);
+        assert!(preview.body.contains("* #12"));
+    }
+
+    #[test]
+    fn dry_run_previews_a_new_pr() {
+        let host = InMemoryHost::new();
+        let repo = host.add_repo("moe", &["Merge pull request #12 from org/feature"], &[]);
+
+        let report = get_pr_links(&[repo], &host, &options(true));
+
+        let preview = report.release_prs[0].dry_run.as_ref().unwrap();
+        assert_eq!("POST memory://moe/pulls", preview.request);
+        assert_eq!(Some("automated release partay".to_owned()), preview.title);
+        assert!(preview.body.contains("* #12"));
+        assert!(host.pulls("moe").is_empty());
+    }
+
+    #[test]
+    fn up_to_date_release_gets_no_pr() {
+        let host = InMemoryHost::new();
+        let repo = host.add_repo("moe", &[], &[]);
+
+        let report = get_pr_links(&[repo], &host, &options(false));
+
+        assert!(report.release_prs.is_empty());
+        assert!(host.pulls("moe").is_empty());
+    }
+
+    #[test]
+    fn diverged_release_gets_a_back_merge_pr() {
+        let host = InMemoryHost::new();
+        let repo = host.add_repo("moe", &["feat: a page"], &["fix: hotfix"]);
+        let mut back_merge_options = options(false);
+        back_merge_options.back_merge = true;
+
+        let report = get_pr_links(&[repo], &host, &back_merge_options);
+
+        assert_eq!("diverged", report.back_merges[0].status);
+        assert!(host.made_call("create_pr moe release->master"));
+        assert!(host.made_call("create_pr moe master->release"));
+    }
+
+    #[test]
+    fn repos_without_push_permission_are_reported_and_left_alone() {
+        let host = InMemoryHost::new();
+        let moe = host.add_repo("moe", &["feat: a page"], &[]);
+        let mut calagator = host.add_repo("calagator", &["feat: a page"], &[]);
+        calagator.permissions = Some(github::RepoPermissions {
+            pull: true,
+            ..github::RepoPermissions::default()
+        });
+
+        let report = get_pr_links(&[moe, calagator], &host, &options(false));
+
+        assert_eq!(1, report.release_prs.len());
+        assert_eq!("calagator", report.unreleasable[0].repo);
+        assert!(!host.made_call("compare calagator"));
+        assert!(host.pulls("calagator").is_empty());
+    }
+}
@@ -0,0 +1,406 @@
+use std::fs;
+use std::path::Path;
+
+use github::GithubRepo;
+use host::{self, ReleaseHost};
+use party::{self, PartyOptions, UnreleasableRepo};
+
+/// What `apply` will do, written to a file by `plan` so it can be reviewed first.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ReleasePlan {
+    // The release-party-br version that made the plan.
+    pub made_by: String,
+    // The org and forge it was made for, so it isn't applied to another one.
+    pub org: String,
+    pub forge: String,
+    pub repos: Vec<PlannedRepo>,
+    // The repos that couldn't be planned for, and why.
+    #[serde(default)]
+    pub left_out: Vec<UnreleasableRepo>,
+}
+
+/// One repo's branches as they were when planning, and what to do about them.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PlannedRepo {
+    pub repo: String,
+    pub url: String,
+    pub master_sha: String,
+    pub release_sha: String,
+    pub actions: Vec<PlannedAction>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum PlannedAction {
+    OpenReleasePr,
+    UpdateReleasePr { pr_link: String },
+    OpenBackMergePr,
+}
+
+/// What `apply` did for one planned repo.
+#[derive(Serialize, Debug, Default)]
+pub struct AppliedRepo {
+    pub repo: String,
+    // The heads the plan was made at, which are the ones it was applied at unless it was refused.
+    pub master_sha: String,
+    pub release_sha: String,
+    // The PRs opened or updated, the release PR first.
+    pub pr_links: Vec<String>,
+    pub done: Vec<String>,
+    pub errors: Vec<String>,
+    // Set when a branch moved since planning: nothing was done to the repo.
+    pub refused: Option<String>,
+}
+
+/// Looks at every repo and writes down the release and back-merge PRs a party run would open or update.
+/// Repos without a release branch are left out, `--create-release-branch` is for party runs only,
+/// and so are repos we can't push to.
+pub fn make_plan(
+    org: &str,
+    forge: &str,
+    repos: &[GithubRepo],
+    host: &dyn ReleaseHost,
+    options: &PartyOptions,
+) -> ReleasePlan {
+    let (repos, unreleasable) = party::preflight(repos);
+    let mut plan = ReleasePlan {
+        made_by: options.rp_version.clone(),
+        org: org.to_owned(),
+        forge: forge.to_owned(),
+        repos: Vec::new(),
+        left_out: unreleasable,
+    };
+    for repo in repos {
+        // Look up the heads first: whatever the compare sees is at least as new as these.
+        let (master_sha, release_sha) = match branch_heads(host, repo) {
+            Ok(heads) => heads,
+            Err(e) => {
+                plan.left_out.push(UnreleasableRepo {
+                    repo: repo.name.clone(),
+                    reason: format!("couldn't find the master and release heads: {}", e),
+                });
+                continue;
+            }
+        };
+        let diff = match host::compare_release_with_master(host, repo) {
+            Ok(diff) => diff,
+            Err(e) => {
+                plan.left_out.push(UnreleasableRepo {
+                    repo: repo.name.clone(),
+                    reason: format!("couldn't compare release with master: {}", e),
+                });
+                continue;
+            }
+        };
+
+        let mut actions = Vec::new();
+        match host::existing_release_pr_location(host, repo) {
+            Some(pr_link) => actions.push(PlannedAction::UpdateReleasePr { pr_link }),
+            None => {
+                if !host::is_release_up_to_date_with_master(&diff) {
+                    actions.push(PlannedAction::OpenReleasePr);
+                }
+            }
+        }
+        if options.back_merge
+            && host::release_needs_back_merge(&diff)
+            && host::existing_back_merge_pr_location(host, repo).is_none()
+        {
+            actions.push(PlannedAction::OpenBackMergePr);
+        }
+        if actions.is_empty() {
+            continue;
+        }
+
+        plan.repos.push(PlannedRepo {
+            repo: repo.name.clone(),
+            url: repo.url.clone(),
+            master_sha,
+            release_sha,
+            actions,
+        });
+    }
+    plan
+}
+
+impl ReleasePlan {
+    /// Whether the plan was made for `org` on `forge`: its repo URLs only mean something there.
+    pub fn check_made_for(&self, org: &str, forge: &str) -> Result<(), String> {
+        if self.org == org && self.forge == forge {
+            return Ok(());
+        }
+        Err(format!("The release plan is for {} on {}, not {} on {}.", self.org, self.forge, org, forge))
+    }
+}
+
+/// Carries out a plan exactly, refusing any repo whose master or release moved since it was made.
+/// Release PR bodies are written the way a party run with `options` would write them.
+pub fn apply_plan(plan: &ReleasePlan, host: &dyn ReleaseHost, options: &PartyOptions) -> Vec<AppliedRepo> {
+    let mut applied = Vec::new();
+    for planned in &plan.repos {
+        let repo = GithubRepo::new(&planned.repo, &planned.url);
+        let mut outcome = AppliedRepo {
+            repo: planned.repo.clone(),
+            master_sha: planned.master_sha.clone(),
+            release_sha: planned.release_sha.clone(),
+            ..AppliedRepo::default()
+        };
+        outcome.refused = match branch_heads(host, &repo) {
+            Ok((master_sha, release_sha)) => moved_since_planning(planned, &master_sha, &release_sha),
+            Err(e) => Some(format!("couldn't check the branches haven't moved: {}", e)),
+        };
+        if outcome.refused.is_none() {
+            for action in &planned.actions {
+                match apply_action(host, &repo, action, options) {
+                    Ok((done, pr_link)) => {
+                        outcome.done.push(done);
+                        outcome.pr_links.push(pr_link);
+                    }
+                    Err(e) => outcome.errors.push(e),
+                }
+            }
+        }
+        applied.push(outcome);
+    }
+    applied
+}
+
+fn branch_heads(host: &dyn ReleaseHost, repo: &GithubRepo) -> Result<(String, String), String> {
+    Ok((host.commit_sha(repo, "master")?, host.commit_sha(repo, "release")?))
+}
+
+fn moved_since_planning(planned: &PlannedRepo, master_sha: &str, release_sha: &str) -> Option<String> {
+    let mut moved = Vec::new();
+    if planned.master_sha != master_sha {
+        moved.push(format!("master moved from {} to {}", planned.master_sha, master_sha));
+    }
+    if planned.release_sha != release_sha {
+        moved.push(format!("release moved from {} to {}", planned.release_sha, release_sha));
+    }
+    if moved.is_empty() {
+        return None;
+    }
+    Some(format!("{} since planning", moved.join(", ")))
+}
+
+// What was done, and the PR it was done to.
+fn apply_action(
+    host: &dyn ReleaseHost,
+    repo: &GithubRepo,
+    action: &PlannedAction,
+    options: &PartyOptions,
+) -> Result<(String, String), String> {
+    match *action {
+        PlannedAction::OpenReleasePr => {
+            // Someone may have opened it since planning, don't try to open a second one.
+            let pr_link = match host::existing_release_pr_location(host, repo) {
+                Some(pr_link) => pr_link,
+                None => host::create_release_pull_request(host, repo)
+                    .map_err(|e| format!("couldn't open release PR: {}", e))?,
+            };
+            update_release_pr_body(host, repo, &pr_link, options)?;
+            Ok((format!("opened release PR {}", pr_link), pr_link))
+        }
+        PlannedAction::UpdateReleasePr { ref pr_link } => {
+            update_release_pr_body(host, repo, pr_link, options)?;
+            Ok((format!("updated release PR {}", pr_link), pr_link.clone()))
+        }
+        PlannedAction::OpenBackMergePr => {
+            let pr_link = match host::existing_back_merge_pr_location(host, repo) {
+                Some(pr_link) => pr_link,
+                None => host::create_back_merge_pull_request(host, repo)
+                    .map_err(|e| format!("couldn't open back-merge PR: {}", e))?,
+            };
+            Ok((format!("opened back-merge PR {}", pr_link), pr_link))
+        }
+    }
+}
+
+fn update_release_pr_body(
+    host: &dyn ReleaseHost,
+    repo: &GithubRepo,
+    pr_link: &str,
+    options: &PartyOptions,
+) -> Result<(), String> {
+    let proposal = host::compare_release_with_master(host, repo)
+        .ok()
+        .map(|diff| host::propose_release_version(host, repo, &diff));
+    host::update_pr_body(
+        host,
+        repo,
+        host::pr_number(pr_link),
+        &options.rp_version,
+        proposal.as_ref(),
+        options.issue_keys.as_ref(),
+    )
+    .map_err(|e| format!("couldn't update release PR body: {}", e))
+}
+
+pub fn write_plan(plan: &ReleasePlan, path: &Path) -> Result<(), String> {
+    let json = match serde_json::to_string_pretty(plan) {
+        Ok(json) => json,
+        Err(e) => return Err(format!("Couldn't serialize the release plan: {}", e)),
+    };
+    fs::write(path, json).map_err(|e| format!("Couldn't write the release plan to {:?}: {}", path, e))
+}
+
+pub fn read_plan(path: &Path) -> Result<ReleasePlan, String> {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) => return Err(format!("Couldn't read the release plan from {:?}: {}", path, e)),
+    };
+    serde_json::from_str(&json).map_err(|e| format!("Couldn't parse the release plan in {:?}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use github::RepoPermissions;
+    use host::fake::InMemoryHost;
+    use jira::IssueKeys;
+
+    fn options() -> PartyOptions {
+        PartyOptions {
+            dryrun: false,
+            back_merge: true,
+            create_release_branch_from: None,
+            rp_version: "release-party-br test".to_owned(),
+            comment_on_included_prs: false,
+            label_included_prs: false,
+            issue_keys: None,
+        }
+    }
+
+    #[test]
+    fn plan_records_heads_and_actions_without_changing_anything() {
+        let host = InMemoryHost::new();
+        let moe = host.add_repo("moe", &["feat: a page"], &["fix: hotfix"]);
+        let calagator = host.add_repo("calagator", &["fix: a bug"], &[]);
+        host.add_pr("calagator", 7, "master", "release");
+        let done = host.add_repo("done", &[], &[]);
+
+        let plan = make_plan("org", "github", &[moe, calagator, done], &host, &options());
+
+        assert_eq!(2, plan.repos.len());
+        assert_eq!("master-1", plan.repos[0].master_sha);
+        assert_eq!("release-1", plan.repos[0].release_sha);
+        assert_eq!(
+            vec![PlannedAction::OpenReleasePr, PlannedAction::OpenBackMergePr],
+            plan.repos[0].actions
+        );
+        assert_eq!(
+            vec![PlannedAction::UpdateReleasePr {
+                pr_link: "https://github.com/org/calagator/pull/7".to_owned()
+            }],
+            plan.repos[1].actions
+        );
+        assert!(!host.made_call("create_pr"));
+        assert!(!host.made_call("update_pr_body"));
+    }
+
+    #[test]
+    fn plan_says_which_repos_it_left_out() {
+        let host = InMemoryHost::new();
+        let moe = host.add_repo("moe", &["feat: a page"], &[]);
+        let mut read_only = host.add_repo("read-only", &["feat: a page"], &[]);
+        read_only.permissions = Some(RepoPermissions::default());
+        let no_release = host.add_repo("no-release", &["feat: a page"], &[]);
+        host.repos.borrow_mut().get_mut("no-release").unwrap().has_release_branch = false;
+
+        let plan = make_plan("org", "github", &[moe, read_only, no_release], &host, &options());
+
+        assert_eq!(1, plan.repos.len());
+        let left_out: Vec<&str> = plan.left_out.iter().map(|repo| repo.repo.as_str()).collect();
+        assert_eq!(vec!["read-only", "no-release"], left_out);
+        assert_eq!("no push permission with these credentials", plan.left_out[0].reason);
+    }
+
+    #[test]
+    fn plan_survives_a_round_trip_through_json() {
+        let plan = ReleasePlan {
+            made_by: "release-party-br test".to_owned(),
+            org: "org".to_owned(),
+            forge: "github".to_owned(),
+            repos: vec![PlannedRepo {
+                repo: "moe".to_owned(),
+                url: "https://api.github.com/repos/org/moe".to_owned(),
+                master_sha: "abc".to_owned(),
+                release_sha: "def".to_owned(),
+                actions: vec![
+                    PlannedAction::OpenReleasePr,
+                    PlannedAction::UpdateReleasePr {
+                        pr_link: "https://github.com/org/moe/pull/1".to_owned(),
+                    },
+                ],
+            }],
+            left_out: vec![UnreleasableRepo {
+                repo: "calagator".to_owned(),
+                reason: "no push permission with these credentials".to_owned(),
+            }],
+        };
+        let json = serde_json::to_string(&plan).unwrap();
+        assert!(json.contains("\"action\":\"open_release_pr\""));
+        assert_eq!(plan, serde_json::from_str(&json).unwrap());
+    }
+
+    #[test]
+    fn plan_is_only_for_the_org_and_forge_it_was_made_for() {
+        let host = InMemoryHost::new();
+        let moe = host.add_repo("moe", &["feat: a page"], &[]);
+        let plan = make_plan("org", "github", &[moe], &host, &options());
+
+        assert_eq!(Ok(()), plan.check_made_for("org", "github"));
+        assert_eq!(
+            Err("The release plan is for org on github, not other-org on github.".to_owned()),
+            plan.check_made_for("other-org", "github")
+        );
+        assert!(plan.check_made_for("org", "gitlab").is_err());
+    }
+
+    #[test]
+    fn apply_does_what_was_planned() {
+        let host = InMemoryHost::new();
+        let moe = host.add_repo("moe", &["Merge pull request #4 from org/feature"], &["fix: hotfix"]);
+        let plan = make_plan("org", "github", &[moe], &host, &options());
+
+        let applied = apply_plan(&plan, &host, &options());
+
+        assert_eq!(None, applied[0].refused);
+        assert!(applied[0].errors.is_empty());
+        let pulls = host.pulls("moe");
+        assert_eq!(2, pulls.len());
+        assert!(pulls[0].body.clone().unwrap().contains("* #4"));
+    }
+
+    #[test]
+    fn apply_lists_issue_keys_like_a_party_run() {
+        let host = InMemoryHost::new();
+        let moe = host.add_repo("moe", &["PAY-12: fix: refunds"], &[]);
+        let options = PartyOptions {
+            issue_keys: Some(IssueKeys::new("PAY-[0-9]+", None).unwrap()),
+            ..options()
+        };
+        let plan = make_plan("org", "github", &[moe], &host, &options);
+
+        apply_plan(&plan, &host, &options);
+
+        assert!(host.pulls("moe")[0].body.clone().unwrap().contains("* PAY-12"));
+    }
+
+    #[test]
+    fn apply_refuses_repos_whose_branches_moved() {
+        let host = InMemoryHost::new();
+        let moe = host.add_repo("moe", &["feat: a page"], &[]);
+        let plan = make_plan("org", "github", &[moe], &host, &options());
+        host.push_to("moe", "master", "feat: sneaked in after review");
+
+        let applied = apply_plan(&plan, &host, &options());
+
+        assert_eq!(
+            Some("master moved from master-1 to master-2 since planning".to_owned()),
+            applied[0].refused
+        );
+        assert!(applied[0].done.is_empty());
+        assert!(host.pulls("moe").is_empty());
+    }
+}
@@ -0,0 +1,364 @@
+use serde_json;
+
+use history::RunRecord;
+use party::{BackMerge, MissingReleaseBranch, PartyLink, PartyReport, PrPreview, UnreleasableRepo};
+use plan::{AppliedRepo, PlannedAction, PlannedRepo};
+
+/// Prints the report the way the CLI shows it.
+pub fn print_party_links(report: &PartyReport) {
+    if !report.release_prs.is_empty() {
+        println!("\nIt's a release party!  PRs to review and approve:");
+        for link in &report.release_prs {
+            println!("{}", describe_party_link(link));
+            if let Some(ref preview) = link.dry_run {
+                println!("{}", describe_pr_preview(preview));
+            }
+            if !link.commented_prs.is_empty() {
+                println!("{}", describe_commented_prs(link));
+            }
+            if !link.issue_keys.is_empty() {
+                println!("  issues: {}", link.issue_keys.join(", "));
+            }
+        }
+    } else {
+        println!("\nNo party today, all releases are done.");
+    }
+    if !report.back_merges.is_empty() {
+        println!("\nThese release branches have commits master doesn't, they need a back-merge:");
+        for back_merge in &report.back_merges {
+            println!("{}", describe_back_merge(back_merge));
+        }
+    }
+    if !report.missing_release_branches.is_empty() {
+        println!("\nThese repos don't have a release branch:");
+        for missing in &report.missing_release_branches {
+            println!("{}", describe_missing_release_branch(missing));
+        }
+    }
+}
+
+/// The repos a run leaves out and why, under `heading`, for the CLI to show before it changes anything.
+pub fn describe_left_out(heading: &str, left_out: &[UnreleasableRepo]) -> String {
+    let mut lines = vec![format!("\n{}", heading)];
+    lines.extend(left_out.iter().map(|repo| format!("{}: {}", repo.repo, repo.reason)));
+    lines.join("\n")
+}
+
+/// One line about a repo without a release branch.
+pub fn describe_missing_release_branch(missing: &MissingReleaseBranch) -> String {
+    match (missing.created_from.as_ref(), missing.sha.as_ref(), missing.error.as_ref()) {
+        (None, _, _) => format!("{}: no release branch", missing.repo),
+        (Some(from), Some(sha), _) if missing.created => format!(
+            "{}: created release branch from {} ({})",
+            missing.repo, from, sha
+        ),
+        (Some(from), Some(sha), Some(error)) => format!(
+            "{}: no release branch, failed to create it from {} ({}): {}",
+            missing.repo, from, sha, error
+        ),
+        (Some(from), Some(sha), None) => format!(
+            "{}: no release branch, would create it from {} ({})",
+            missing.repo, from, sha
+        ),
+        (Some(from), None, _) => format!(
+            "{}: no release branch, couldn't find {} to create it from",
+            missing.repo, from
+        ),
+    }
+}
+
+/// One line about a release branch that needs a back-merge.
+pub fn describe_back_merge(back_merge: &BackMerge) -> String {
+    let mut description = format!(
+        "{}: release is {} with {} commits not on master",
+        back_merge.repo, back_merge.status, back_merge.release_only_commits
+    );
+    if let Some(ref pr) = back_merge.back_merge_pr {
+        description.push_str(&format!(", back-merge PR: {}", pr));
+    }
+    description
+}
+
+/// The release PR link with what it's shipping.
+pub fn describe_party_link(party_link: &PartyLink) -> String {
+    let mut details = Vec::new();
+    if let Some(ref diff) = party_link.release_diff {
+        details.push(format!(
+            "{} commits, {} files",
+            diff.commits_to_release,
+            diff.files.len()
+        ));
+        if diff.release_only_commits > 0 {
+            details.push(format!(
+                "release has {} commits not on master",
+                diff.release_only_commits
+            ));
+        }
+    }
+    if let Some(ref proposal) = party_link.proposed_version {
+        details.push(format!("proposed version {}", proposal));
+    }
+    if details.is_empty() {
+        return party_link.pr_link.clone();
+    }
+    format!("{} ({})", party_link.pr_link, details.join(", "))
+}
+
+/// The merged PRs told they're in the release PR.
+pub fn describe_commented_prs(party_link: &PartyLink) -> String {
+    let verb = if party_link.dry_run.is_some() { "would comment on" } else { "commented on" };
+    format!("  {} {}", verb, party_link.commented_prs.join(", "))
+}
+
+/// What a dry run held back: the new PR's title and body, or a diff of an existing PR's body.
+pub fn describe_pr_preview(preview: &PrPreview) -> String {
+    let mut lines = vec![format!("  would {}", preview.request)];
+    if let Some(ref title) = preview.title {
+        lines.push(format!("  title: {}", title));
+    }
+    match (preview.title.as_ref(), preview.current_body.as_ref()) {
+        (Some(_), _) => {
+            lines.push("  body:".to_owned());
+            lines.extend(preview.body.lines().map(|line| format!("    {}", line)));
+        }
+        (None, Some(current)) if current.replace("\r\n", "\n") == preview.body => {
+            lines.push("  body is already up to date".to_owned());
+        }
+        (None, current) => {
+            lines.push("  body changes:".to_owned());
+            let current = current.map(|c| c.replace("\r\n", "\n")).unwrap_or_default();
+            lines.extend(line_diff(&current, &preview.body).into_iter().map(|line| format!("    {}", line)));
+        }
+    }
+    lines.join("\n")
+}
+
+// A unified-style diff of two texts, one "-", "+" or " " prefixed line per line.
+pub fn line_diff(old: &str, new: &str) -> Vec<String> {
+    let old = old.lines().collect::<Vec<&str>>();
+    let new = new.lines().collect::<Vec<&str>>();
+    // longest[i][j] is the longest common subsequence of old[i..] and new[j..]
+    let mut longest = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            longest[i][j] = if old[i] == new[j] {
+                longest[i + 1][j + 1] + 1
+            } else {
+                longest[i + 1][j].max(longest[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            diff.push(format!("  {}", old[i]));
+            i += 1;
+            j += 1;
+        } else if longest[i + 1][j] >= longest[i][j + 1] {
+            diff.push(format!("- {}", old[i]));
+            i += 1;
+        } else {
+            diff.push(format!("+ {}", new[j]));
+            j += 1;
+        }
+    }
+    diff.extend(old[i..].iter().map(|line| format!("- {}", line)));
+    diff.extend(new[j..].iter().map(|line| format!("+ {}", line)));
+    diff
+}
+
+/// One line about what `apply` will do to a repo.
+pub fn describe_planned_repo(planned: &PlannedRepo) -> String {
+    let actions = planned
+        .actions
+        .iter()
+        .map(|action| match *action {
+            PlannedAction::OpenReleasePr => "open a release PR".to_owned(),
+            PlannedAction::UpdateReleasePr { ref pr_link } => format!("update release PR {}", pr_link),
+            PlannedAction::OpenBackMergePr => "open a back-merge PR".to_owned(),
+        })
+        .collect::<Vec<String>>();
+    format!(
+        "{}: {} (master {}, release {})",
+        planned.repo,
+        actions.join(", "),
+        planned.master_sha,
+        planned.release_sha
+    )
+}
+
+/// One line about what `apply` did to a repo.
+pub fn describe_applied_repo(applied: &AppliedRepo) -> String {
+    if let Some(ref reason) = applied.refused {
+        return format!("{}: refused, {}", applied.repo, reason);
+    }
+    let mut outcomes = applied.done.clone();
+    outcomes.extend(applied.errors.iter().cloned());
+    format!("{}: {}", applied.repo, outcomes.join(", "))
+}
+
+/// A run from the history: when, who and what, then each repo's outcome, or only `repo`'s.
+pub fn describe_run(record: &RunRecord, repo: Option<&str>) -> String {
+    let mut lines = vec![format!(
+        "{} {} by {}{}",
+        record.timestamp,
+        record.command,
+        record.user,
+        if record.dry_run { " (dry run)" } else { "" }
+    )];
+    for outcome in &record.repos {
+        if repo.map(|repo| repo != outcome.repo).unwrap_or(false) {
+            continue;
+        }
+        let mut line = format!("  {}: {}", outcome.repo, outcome.outcome);
+        if let Some(ref pr_link) = outcome.pr_link {
+            line.push_str(&format!(" {}", pr_link));
+        }
+        if let Some(ref detail) = outcome.detail {
+            line.push_str(&format!(" ({})", detail));
+        }
+        if let Some(head) = outcome.shas.last() {
+            line.push_str(&format!(" at {}", head));
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+/// The report as pretty-printed JSON, what `--json` prints.
+pub fn party_json(report: &PartyReport) -> Result<String, String> {
+    serde_json::to_string_pretty(report)
+        .map_err(|e| format!("Couldn't serialize party report to JSON: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use party::ReleaseDiff;
+    use plan::PlannedRepo;
+
+    #[test]
+    fn describe_party_link_with_diff() {
+        let link = PartyLink {
+            repo: "calagator".to_owned(),
+            pr_link: "https://github.com/org/calagator/pull/7".to_owned(),
+            release_diff: Some(ReleaseDiff {
+                status: "diverged".to_owned(),
+                commits_to_release: 3,
+                release_only_commits: 1,
+                total_commits: 3,
+                commits: Vec::new(),
+                files: Vec::new(),
+            }),
+            proposed_version: None,
+            dry_run: None,
+            commented_prs: Vec::new(),
+            issue_keys: Vec::new(),
+        };
+        assert_eq!(
+            "https://github.com/org/calagator/pull/7 (3 commits, 0 files, release has 1 commits not on master)",
+            describe_party_link(&link)
+        );
+    }
+
+    #[test]
+    fn describe_back_merge_with_pr() {
+        let back_merge = BackMerge {
+            repo: "moe".to_owned(),
+            status: "diverged".to_owned(),
+            release_only_commits: 2,
+            back_merge_pr: Some("https://github.com/org/moe/pull/3".to_owned()),
+        };
+        assert_eq!(
+            "moe: release is diverged with 2 commits not on master, back-merge PR: https://github.com/org/moe/pull/3",
+            describe_back_merge(&back_merge)
+        );
+    }
+
+    #[test]
+    fn describe_missing_release_branches() {
+        let mut missing = MissingReleaseBranch {
+            repo: "moe".to_owned(),
+            created_from: None,
+            sha: None,
+            created: false,
+            error: None,
+        };
+        assert_eq!("moe: no release branch", describe_missing_release_branch(&missing));
+
+        missing.created_from = Some("latest-tag".to_owned());
+        missing.sha = Some("abc123".to_owned());
+        assert_eq!(
+            "moe: no release branch, would create it from latest-tag (abc123)",
+            describe_missing_release_branch(&missing)
+        );
+
+        missing.error = Some("Github responded with 403 Forbidden".to_owned());
+        assert_eq!(
+            "moe: no release branch, failed to create it from latest-tag (abc123): Github responded with 403 Forbidden",
+            describe_missing_release_branch(&missing)
+        );
+
+        missing.error = None;
+        missing.created = true;
+        assert_eq!(
+            "moe: created release branch from latest-tag (abc123)",
+            describe_missing_release_branch(&missing)
+        );
+    }
+
+    #[test]
+    fn describe_planned_and_applied_repos() {
+        let planned = PlannedRepo {
+            repo: "moe".to_owned(),
+            url: "https://api.github.com/repos/org/moe".to_owned(),
+            master_sha: "abc".to_owned(),
+            release_sha: "def".to_owned(),
+            actions: vec![PlannedAction::OpenReleasePr, PlannedAction::OpenBackMergePr],
+        };
+        assert_eq!(
+            "moe: open a release PR, open a back-merge PR (master abc, release def)",
+            describe_planned_repo(&planned)
+        );
+
+        let refused = AppliedRepo {
+            repo: "moe".to_owned(),
+            refused: Some("master moved from abc to 123 since planning".to_owned()),
+            ..AppliedRepo::default()
+        };
+        assert_eq!(
+            "moe: refused, master moved from abc to 123 since planning",
+            describe_applied_repo(&refused)
+        );
+    }
+
+    #[test]
+    fn line_diff_marks_removed_and_added_lines() {
+        assert_eq!(
+            vec![
+                "  PRs in this release:".to_owned(),
+                "  * #1".to_owned(),
+                "- * #2".to_owned(),
+                "+ * #3".to_owned(),
+                "  Made by rp".to_owned(),
+            ],
+            line_diff("PRs in this release:\n* #1\n* #2\nMade by rp", "PRs in this release:\n* #1\n* #3\nMade by rp")
+        );
+    }
+
+    #[test]
+    fn describe_preview_of_unchanged_body() {
+        let preview = PrPreview {
+            request: "PATCH https://api.github.com/repos/org/moe/pulls/7".to_owned(),
+            title: None,
+            current_body: Some("same\r\nbody".to_owned()),
+            body: "same\nbody".to_owned(),
+        };
+        assert_eq!(
+            "  would PATCH https://api.github.com/repos/org/moe/pulls/7\n  body is already up to date",
+            describe_pr_preview(&preview)
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A plain `major.minor.patch` version, as found in a repo's tags.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Bump {
+    Patch,
+    Minor,
+    Major,
+}
+
+/// What we think the next release of a repo should be called.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct VersionProposal {
+    pub current: Option<Version>,
+    pub bump: Bump,
+    pub next: Version,
+}
+
+impl Version {
+    pub fn new(major: u64, minor: u64, patch: u64) -> Version {
+        Version {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    // Accepts "1.2.3" and "v1.2.3". Pre-release and build suffixes aren't release tags, skip them.
+    pub fn parse(tag: &str) -> Option<Version> {
+        let trimmed = tag.trim();
+        let trimmed = if trimmed.starts_with('v') || trimmed.starts_with('V') {
+            &trimmed[1..]
+        } else {
+            trimmed
+        };
+        let parts = trimmed.split('.').collect::<Vec<&str>>();
+        if parts.len() != 3 {
+            return None;
+        }
+        let mut numbers = Vec::new();
+        for part in parts {
+            match part.parse::<u64>() {
+                Ok(n) => numbers.push(n),
+                Err(_) => return None,
+            }
+        }
+        Some(Version::new(numbers[0], numbers[1], numbers[2]))
+    }
+
+    pub fn bumped(&self, bump: Bump) -> Version {
+        match bump {
+            Bump::Major => Version::new(self.major + 1, 0, 0),
+            Bump::Minor => Version::new(self.major, self.minor + 1, 0),
+            Bump::Patch => Version::new(self.major, self.minor, self.patch + 1),
+        }
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Version) -> Ordering {
+        (self.major, self.minor, self.patch).cmp(&(other.major, other.minor, other.patch))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Version) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl fmt::Display for Bump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Bump::Major => "major",
+            Bump::Minor => "minor",
+            Bump::Patch => "patch",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for VersionProposal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.current {
+            Some(ref current) => write!(f, "{} ({} bump from {})", self.next, self.bump, current),
+            None => write!(f, "{} (no previous version tag found)", self.next),
+        }
+    }
+}
+
+/// The highest release version among the tag names, if any of them look like versions.
+pub fn latest_version(tag_names: &[String]) -> Option<Version> {
+    tag_names.iter().filter_map(|name| Version::parse(name)).max()
+}
+
+// Conventional Commits: `type(scope)!: description`, with an optional `BREAKING CHANGE:` footer.
+// Anything that isn't a breaking change or a `feat` still ships in the release, so it's a patch.
+pub fn bump_for_commit(message: &str) -> Bump {
+    if has_breaking_change_footer(message) {
+        return Bump::Major;
+    }
+    let header = message.lines().next().unwrap_or("");
+    let prefix = match header.find(':') {
+        Some(colon) => &header[..colon],
+        None => return Bump::Patch,
+    };
+    if prefix.ends_with('!') {
+        return Bump::Major;
+    }
+    let commit_type = match prefix.find('(') {
+        Some(paren) => &prefix[..paren],
+        None => prefix,
+    };
+    if commit_type.trim().eq_ignore_ascii_case("feat") {
+        return Bump::Minor;
+    }
+    Bump::Patch
+}
+
+// A footer is a line of its own after the header, `BREAKING CHANGE: ...` or `BREAKING CHANGE #...`.
+// The words anywhere else, like "fix the BREAKING CHANGE note in the docs", don't count.
+fn has_breaking_change_footer(message: &str) -> bool {
+    message.lines().skip(1).any(|line| {
+        ["BREAKING CHANGE", "BREAKING-CHANGE"]
+            .iter()
+            .any(|token| line.starts_with(&format!("{}:", token)) || line.starts_with(&format!("{} #", token)))
+    })
+}
+
+pub fn bump_for_commits(messages: &[String]) -> Bump {
+    messages
+        .iter()
+        .map(|message| bump_for_commit(message))
+        .max()
+        .unwrap_or(Bump::Patch)
+}
+
+pub fn propose_version(tag_names: &[String], commit_messages: &[String]) -> VersionProposal {
+    let current = latest_version(tag_names);
+    let bump = bump_for_commits(commit_messages);
+    let next = current.unwrap_or_else(|| Version::new(0, 0, 0)).bumped(bump);
+    VersionProposal {
+        current,
+        bump,
+        next,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_versions_from_tags() {
+        assert_eq!(Some(Version::new(1, 2, 3)), Version::parse("1.2.3"));
+        assert_eq!(Some(Version::new(0, 6, 0)), Version::parse("v0.6.0"));
+        assert_eq!(None, Version::parse("1.2.3-beta.1"));
+        assert_eq!(None, Version::parse("release-2019"));
+        assert_eq!(None, Version::parse("1.2"));
+    }
+
+    #[test]
+    fn latest_version_ignores_other_tags() {
+        let tags = vec![
+            "v0.9.0".to_owned(),
+            "nightly".to_owned(),
+            "v0.10.1".to_owned(),
+            "0.10.0".to_owned(),
+        ];
+        assert_eq!(Some(Version::new(0, 10, 1)), latest_version(&tags));
+    }
+
+    #[test]
+    fn conventional_commit_bumps() {
+        assert_eq!(Bump::Minor, bump_for_commit("feat: add audit subcommand"));
+        assert_eq!(Bump::Minor, bump_for_commit("feat(github): page through tags"));
+        assert_eq!(Bump::Patch, bump_for_commit("fix: handle 404 on compare"));
+        assert_eq!(Bump::Patch, bump_for_commit("Merge pull request #12 from org/branch"));
+        assert_eq!(Bump::Major, bump_for_commit("feat!: drop the --org url form"));
+        assert_eq!(Bump::Major, bump_for_commit("refactor(api)!: rename fields"));
+        assert_eq!(
+            Bump::Major,
+            bump_for_commit("fix: new config format\n\nBREAKING CHANGE: old files are rejected")
+        );
+        assert_eq!(
+            Bump::Major,
+            bump_for_commit("feat: rename flags\n\nSee #3.\nBREAKING-CHANGE: --org takes a name")
+        );
+        assert_eq!(Bump::Patch, bump_for_commit("docs: explain BREAKING CHANGE footers"));
+        assert_eq!(
+            Bump::Patch,
+            bump_for_commit("fix: typo\n\nThe BREAKING CHANGE: note was misspelled.")
+        );
+    }
+
+    #[test]
+    fn proposal_takes_the_biggest_bump() {
+        let tags = vec!["v1.4.2".to_owned()];
+        let commits = vec!["fix: a thing".to_owned(), "feat: a new thing".to_owned()];
+        let proposal = propose_version(&tags, &commits);
+        assert_eq!(Bump::Minor, proposal.bump);
+        assert_eq!(Version::new(1, 5, 0), proposal.next);
+        assert_eq!("1.5.0 (minor bump from 1.4.2)", proposal.to_string());
+    }
+
+    #[test]
+    fn proposal_without_tags_starts_from_zero() {
+        let proposal = propose_version(&[], &["chore: tidy".to_owned()]);
+        assert_eq!(None, proposal.current);
+        assert_eq!(Version::new(0, 0, 1), proposal.next);
+    }
+}
@@ -0,0 +1,980 @@
+extern crate hyper;
+extern crate openssl;
+#[macro_use]
+extern crate serde_json;
+
+mod support;
+
+use std::fs;
+
+use openssl::rsa::Rsa;
+
+use support::{FakeGithub, FakeRepo, SmtpSink};
+
+#[test]
+fn no_party_when_everything_is_released() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator"))
+        .start();
+
+    let run = github.run_party(&[]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run.stdout.contains("No party today, all releases are done."));
+    assert!(!github.requests().iter().any(|r| r.starts_with("POST")));
+}
+
+#[test]
+fn dry_run_creates_nothing() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["feat: new page"]))
+        .start();
+
+    let run = github.run_party(&["--dry-run"]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run.stdout.contains("would get a release PR."));
+    assert!(github.pulls_for("calagator").is_empty());
+    assert!(!github.requests().iter().any(|r| r.starts_with("POST")));
+}
+
+#[test]
+fn existing_release_pr_gets_its_body_updated() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(
+            FakeRepo::new("calagator")
+                .with_master_commits(&["Merge pull request #12 from party-org/feature", "feat: new page"])
+                .with_tags(&["v1.2.0"])
+                .with_release_pr(7),
+        )
+        .start();
+
+    let run = github.run_party(&[]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run.stdout.contains("https://github.com/party-org/calagator/pull/7"));
+    assert!(!github.requests().iter().any(|r| r.starts_with("POST")));
+    let pulls = github.pulls_for("calagator");
+    assert_eq!(1, pulls.len());
+    let body = pulls[0].body.clone().expect("release PR body should be set");
+    assert!(body.contains("* #12"));
+    assert!(body.contains("Proposed version: 1.3.0 (minor bump from 1.2.0)"));
+}
+
+#[test]
+fn dry_run_shows_body_changes_without_patching() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(
+            FakeRepo::new("calagator")
+                .with_master_commits(&["Merge pull request #12 from party-org/feature"])
+                .with_release_pr(7),
+        )
+        .with_repo(FakeRepo::new("moe").with_master_commits(&["Merge pull request #3 from party-org/fix"]))
+        .start();
+
+    let run = github.run_party(&["--dry-run"]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(!github
+        .requests()
+        .iter()
+        .any(|r| r.starts_with("POST") || r.starts_with("PATCH")));
+    assert!(run
+        .stdout
+        .contains(&format!("would PATCH {}/repos/party-org/calagator/pulls/7", github.api_url())));
+    assert!(run.stdout.contains("    + * #12"));
+    assert!(run
+        .stdout
+        .contains(&format!("would POST {}/repos/party-org/moe/pulls", github.api_url())));
+    assert!(run.stdout.contains("  title: automated release partay"));
+    assert!(run.stdout.contains("    * #3"));
+    assert_eq!(None, github.pulls_for("calagator")[0].body);
+}
+
+#[test]
+fn proposed_version_reads_every_page_of_tags() {
+    // The highest version is on the second page of 100.
+    let mut tags: Vec<String> = (0..120).map(|patch| format!("v0.1.{}", patch)).collect();
+    tags.push("v2.0.0".to_owned());
+    let tags: Vec<&str> = tags.iter().map(|t| t.as_str()).collect();
+    let github = FakeGithub::new("party-org")
+        .with_repo(
+            FakeRepo::new("calagator")
+                .with_master_commits(&["feat: new page"])
+                .with_tags(&tags)
+                .with_release_pr(7),
+        )
+        .start();
+
+    let run = github.run_party(&[]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    let body = github.pulls_for("calagator")[0].body.clone().expect("release PR body should be set");
+    assert!(body.contains("Proposed version: 2.1.0 (minor bump from 2.0.0)"), "body: {}", body);
+    assert_eq!(
+        2,
+        github.requests().iter().filter(|r| r.ends_with("/calagator/tags")).count()
+    );
+}
+
+#[test]
+fn new_release_pr_is_created() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["fix: typo"]))
+        .with_repo(FakeRepo::new("moe"))
+        .start();
+
+    let run = github.run_party(&[]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run.stdout.contains("https://github.com/party-org/calagator/pull/100"));
+    assert!(!run.stdout.contains("party-org/moe/pull"));
+    let pulls = github.pulls_for("calagator");
+    assert_eq!(1, pulls.len());
+    assert_eq!("master", pulls[0].head);
+    assert_eq!("release", pulls[0].base);
+    assert!(github.pulls_for("moe").is_empty());
+}
+
+#[test]
+fn every_page_of_repos_is_checked() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("one"))
+        .with_repo(FakeRepo::new("two"))
+        .with_repo(FakeRepo::new("three").with_master_commits(&["fix: on the last page"]))
+        .start();
+
+    let run = github.run_party(&["--json"]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run.stderr.contains("Number of repos to check: 3"), "stderr: {}", run.stderr);
+    assert_eq!(vec!["https://github.com/party-org/three/pull/100".to_owned()], run.release_pr_links());
+    let repo_listings = github
+        .requests()
+        .iter()
+        .filter(|r| r.as_str() == "GET /orgs/party-org/repos")
+        .count();
+    assert_eq!(2, repo_listings);
+}
+
+#[test]
+fn throttles_when_running_low_on_requests() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator"))
+        .with_rate_limit_remaining(5)
+        .start();
+
+    let run = github.run_party(&[]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run.stderr.contains("Running low on requests, throttling back..."));
+}
+
+#[test]
+fn running_out_of_requests_waits_for_the_reset() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["fix: typo"]))
+        .with_rate_limit_remaining(0)
+        // What GitHub answers once the requests are used up, saying when they come back.
+        .failing_once("GET /repos/party-org/calagator/compare/release...master", 403)
+        .start();
+
+    let run = github.run_party(&[]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run.stderr.contains("Github responded with 403 Forbidden to "), "stderr: {}", run.stderr);
+    assert_eq!(1, github.pulls_for("calagator").len());
+}
+
+#[test]
+fn a_config_that_doesnt_parse_stops_the_party() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["fix: typo"]))
+        .start();
+    // The ignore list is fine, the [jira] section after it isn't: calagator must not be released regardless.
+    fs::write(
+        github.workdir().join("ignoredrepos.toml"),
+        "ignore = [\"calagator\"]\n[jira]\ncomment = yes\n",
+    )
+    .unwrap();
+
+    let run = github.run_party(&[]);
+    fs::remove_file(github.workdir().join("ignoredrepos.toml")).unwrap();
+
+    assert!(!run.success);
+    assert!(run.stderr.contains("Couldn't parse ignoredrepos.toml"), "stderr: {}", run.stderr);
+    assert!(github.requests().is_empty(), "requests: {:?}", github.requests());
+}
+
+#[test]
+fn repo_listing_error_stops_the_party() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator"))
+        .with_repos_failing(500)
+        .start();
+
+    let run = github.run_party(&[]);
+
+    assert!(!run.success);
+    assert!(run.stderr.contains("Github responded with 500 Internal Server Error listing repos"), "stderr: {}", run.stderr);
+    assert!(!run.stderr.contains("panicked"), "stderr: {}", run.stderr);
+}
+
+#[test]
+fn compare_error_skips_just_that_repo() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").with_compare_failing(502))
+        .with_repo(FakeRepo::new("moe").with_master_commits(&["fix: still released"]))
+        .start();
+
+    let run = github.run_party(&[]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run.stderr.contains("Couldn't compare release with master for calagator"));
+    assert!(run.stdout.contains("https://github.com/party-org/moe/pull/100"));
+    assert!(github.pulls_for("calagator").is_empty());
+}
+
+#[test]
+fn diverged_release_gets_a_back_merge_pr() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(
+            FakeRepo::new("calagator")
+                .with_master_commits(&["feat: new page"])
+                .with_release_commits(&["fix: hotfix straight to release"]),
+        )
+        .start();
+
+    let run = github.run_party(&["--back-merge"]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run.stdout.contains("calagator: release is diverged with 1 commits not on master"));
+    let pulls = github.pulls_for("calagator");
+    assert!(pulls.iter().any(|p| p.head == "release" && p.base == "master"));
+    assert!(pulls.iter().any(|p| p.head == "master" && p.base == "release"));
+}
+
+#[test]
+fn missing_release_branch_is_reported() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").without_release_branch())
+        .start();
+
+    let run = github.run_party(&[]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run.stdout.contains("calagator: no release branch"));
+    assert!(github.pulls_for("calagator").is_empty());
+}
+
+#[test]
+fn plan_then_apply_opens_the_planned_pr() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["Merge pull request #12 from org/feature"]))
+        .with_repo(FakeRepo::new("moe"))
+        .start();
+
+    let plan = github.run_party(&["plan"]);
+
+    assert!(plan.success, "stderr: {}", plan.stderr);
+    assert!(plan
+        .stdout
+        .contains("calagator: open a release PR (master master-1, release release-0)"));
+    assert!(!github.requests().iter().any(|r| r.starts_with("POST") || r.starts_with("PATCH")));
+    let plan_file = fs::read_to_string(github.workdir().join("release-plan.json")).unwrap();
+    let plan_json: serde_json::Value = serde_json::from_str(&plan_file).unwrap();
+    assert_eq!(1, plan_json["repos"].as_array().unwrap().len());
+    assert_eq!("open_release_pr", plan_json["repos"][0]["actions"][0]["action"]);
+
+    let apply = github.run_party(&["apply", "--plan", "release-plan.json"]);
+
+    assert!(apply.success, "stderr: {}", apply.stderr);
+    let pulls = github.pulls_for("calagator");
+    assert_eq!(1, pulls.len());
+    assert!(pulls[0].body.clone().unwrap().contains("* #12"));
+    assert!(github.pulls_for("moe").is_empty());
+}
+
+#[test]
+fn dry_run_apply_only_shows_the_plan() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["feat: new page"]))
+        .with_repo(FakeRepo::new("moe").with_master_commits(&["fix: typo"]).with_release_pr(7))
+        .start();
+
+    let plan = github.run_party(&["plan", "--out", "dry-plan.json"]);
+    assert!(plan.success, "stderr: {}", plan.stderr);
+
+    let apply = github.run_party(&["--dry-run", "apply", "--plan", "dry-plan.json"]);
+
+    assert!(apply.success, "stderr: {}", apply.stderr);
+    assert!(apply.stdout.contains("Dry run: applying dry-plan.json would do this"), "stdout: {}", apply.stdout);
+    assert!(apply.stdout.contains("calagator: open a release PR"), "stdout: {}", apply.stdout);
+    assert!(apply.stdout.contains("moe: update release PR"), "stdout: {}", apply.stdout);
+    assert!(!github.requests().iter().any(|r| r.starts_with("POST") || r.starts_with("PATCH")));
+}
+
+#[test]
+fn apply_refuses_when_master_moved_since_planning() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["feat: new page"]))
+        .start();
+
+    let plan = github.run_party(&["plan", "--out", "reviewed-plan.json"]);
+    assert!(plan.success, "stderr: {}", plan.stderr);
+    github.push_to_master("calagator", "feat: not reviewed");
+
+    let apply = github.run_party(&["apply", "--plan", "reviewed-plan.json"]);
+
+    assert!(!apply.success);
+    assert!(apply
+        .stdout
+        .contains("calagator: refused, master moved from master-1 to master-2 since planning"));
+    assert!(github.pulls_for("calagator").is_empty());
+}
+
+#[test]
+fn apply_refuses_a_plan_for_another_org() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["feat: new page"]))
+        .start();
+    let plan = github.run_party(&["plan", "--out", "other-plan.json"]);
+    assert!(plan.success, "stderr: {}", plan.stderr);
+    let path = github.workdir().join("other-plan.json");
+    let plan_file = fs::read_to_string(&path).unwrap();
+    fs::write(&path, plan_file.replace("\"org\": \"party-org\"", "\"org\": \"other-org\"")).unwrap();
+
+    let apply = github.run_party(&["apply", "--plan", "other-plan.json"]);
+
+    assert!(!apply.success);
+    assert!(apply
+        .stderr
+        .contains("The release plan is for other-org on github, not party-org on github."));
+    assert!(github.pulls_for("calagator").is_empty());
+}
+
+fn app_private_key(github: &support::FakeServer) -> String {
+    let key = Rsa::generate(2048).unwrap();
+    let path = github.workdir().join("app-key.pem");
+    fs::write(&path, key.private_key_to_pem().unwrap()).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn github_app_uses_an_installation_token() {
+    let github = FakeGithub::new("party-org")
+        .with_github_app("2099-01-01T00:00:00Z")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["fix: typo"]))
+        .start();
+    let key = app_private_key(&github);
+
+    let run = github.run_party(&["--app-id", "1234", "--app-private-key", &key]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert_eq!(1, github.pulls_for("calagator").len());
+    let requests = github.requests();
+    let authorizations = github.authorizations();
+    assert_eq!("GET /orgs/party-org/installation", requests[0]);
+    assert!(authorizations[0].starts_with("Bearer "));
+    assert!(authorizations[1].starts_with("Bearer "));
+    assert!(authorizations[2..]
+        .iter()
+        .all(|a| a == "token installation-token-1"));
+}
+
+#[test]
+fn github_app_token_is_refreshed_when_it_expires() {
+    let github = FakeGithub::new("party-org")
+        .with_github_app("2001-01-01T00:00:00Z")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["fix: typo"]))
+        .start();
+    let key = app_private_key(&github);
+
+    let run = github.run_party(&["--app-id", "1234", "--app-private-key", &key]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(github.state.lock().unwrap().installation_tokens_issued > 1);
+    assert!(github
+        .authorizations()
+        .iter()
+        .any(|a| a == "token installation-token-2"));
+}
+
+#[test]
+fn token_file_is_used_before_the_environment() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator"))
+        .start();
+    let token_file = github.workdir().join("token");
+    fs::write(&token_file, "file-token\n").unwrap();
+
+    let run = github.run_party(&["--token-file", &token_file.to_string_lossy()]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(github.authorizations().iter().all(|a| a == "token file-token"));
+}
+
+#[test]
+fn token_without_repo_scope_stops_before_any_writes() {
+    let github = FakeGithub::new("party-org")
+        .with_token_scopes("read:org, user")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["fix: typo"]))
+        .start();
+
+    let run = github.run_party(&[]);
+
+    assert!(!run.success);
+    assert!(run
+        .stderr
+        .contains("(user party-bot) is missing the repo scope needed to open PRs"));
+    assert_eq!(vec!["GET /user".to_owned()], github.requests());
+}
+
+#[test]
+fn repos_without_push_permission_are_reported_before_any_writes() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(
+            FakeRepo::new("calagator")
+                .with_master_commits(&["fix: typo"])
+                .without_push_permission(),
+        )
+        .with_repo(FakeRepo::new("moe").with_master_commits(&["fix: still released"]))
+        // Opening moe's release PR is the first write, and its retry message marks when it happened.
+        .failing_once("POST /repos/party-org/moe/pulls", 502)
+        .start();
+
+    // With --json both the report of left out repos and the retry go to stderr, in the order they happened.
+    let run = github.run_party(&["--json"]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    let reported = run
+        .stderr
+        .find("These repos can't be released with the current credentials:\ncalagator: no push permission with these credentials")
+        .expect("unpushable repos should be reported");
+    let first_write = run
+        .stderr
+        .find("Github responded with 502 Bad Gateway to")
+        .expect("the first write should be retried");
+    assert!(reported < first_write, "stderr: {}", run.stderr);
+    assert_eq!(vec!["https://github.com/party-org/moe/pull/100".to_owned()], run.release_pr_links());
+    assert!(!github.requests().iter().any(|r| r.contains("/calagator/")));
+}
+
+#[test]
+fn token_scopes_are_not_needed_for_a_dry_run() {
+    let github = FakeGithub::new("party-org")
+        .with_token_scopes("read:org")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["fix: typo"]))
+        .start();
+
+    let run = github.run_party(&["--dry-run"]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(!github.requests().contains(&"GET /user".to_owned()));
+}
+
+#[test]
+fn whoami_shows_the_token_and_its_scopes() {
+    let github = FakeGithub::new("party-org")
+        .with_token_scopes("repo, read:org")
+        .start();
+
+    let run = github.run_party(&["whoami"]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run
+        .stdout
+        .contains("Authenticated as party-bot with the token from RP_GITHUBTOKEN environment variable."));
+    assert!(run.stdout.contains("Token scopes: repo, read:org"));
+}
+
+#[test]
+fn gitlab_proposed_version_reads_every_page_of_tags() {
+    // The highest version is on the second page of 100.
+    let mut tags: Vec<String> = (0..120).map(|patch| format!("v0.1.{}", patch)).collect();
+    tags.push("v2.0.0".to_owned());
+    let tags: Vec<&str> = tags.iter().map(|t| t.as_str()).collect();
+    let github = FakeGithub::new("party-org")
+        .on_gitlab()
+        .with_repo(
+            FakeRepo::new("calagator")
+                .with_master_commits(&["feat: new page"])
+                .with_tags(&tags)
+                .with_release_pr(7),
+        )
+        .start();
+
+    let run = github.run_party(&[]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    let body = github.pulls_for("calagator")[0].body.clone().expect("release MR description should be set");
+    assert!(body.contains("Proposed version: 2.1.0 (minor bump from 2.0.0)"), "body: {}", body);
+    assert_eq!(
+        2,
+        github.requests().iter().filter(|r| r.ends_with("/repository/tags")).count()
+    );
+}
+
+#[test]
+fn gitlab_group_gets_release_merge_requests() {
+    let github = FakeGithub::new("party-org")
+        .on_gitlab()
+        .with_repo(
+            FakeRepo::new("calagator").with_master_commits(&[
+                "Merge branch 'feature' into 'master'\n\nfeat: new page\n\nSee merge request party-org/calagator!12",
+            ]),
+        )
+        .with_repo(FakeRepo::new("moe"))
+        .with_repo(
+            FakeRepo::new("apps/larry")
+                .with_master_commits(&["fix: typo"])
+                .with_release_pr(7),
+        )
+        .start();
+
+    let run = github.run_party(&[]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run
+        .stdout
+        .contains("https://gitlab.example.com/party-org/calagator/-/merge_requests/100"));
+    assert!(run
+        .stdout
+        .contains("https://gitlab.example.com/party-org/apps/larry/-/merge_requests/7"));
+    let calagator = github.pulls_for("calagator");
+    assert_eq!(("master", "release"), (calagator[0].head.as_str(), calagator[0].base.as_str()));
+    assert!(calagator[0].body.clone().unwrap().contains("* !12"));
+    assert!(github.pulls_for("apps/larry")[0].body.is_some());
+    assert!(github.pulls_for("moe").is_empty());
+    assert!(github
+        .requests()
+        .contains(&"GET /groups/party-org/projects".to_owned()));
+}
+
+#[test]
+fn gitlab_projects_without_developer_access_are_reported() {
+    let github = FakeGithub::new("party-org")
+        .on_gitlab()
+        .with_repo(
+            FakeRepo::new("calagator")
+                .with_master_commits(&["fix: typo"])
+                .without_push_permission(),
+        )
+        .with_repo(FakeRepo::new("moe").with_master_commits(&["fix: still released"]))
+        .start();
+
+    let run = github.run_party(&[]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run
+        .stdout
+        .contains("These repos can't be released with the current credentials:\nparty-org/calagator: no push permission"));
+    assert!(github.pulls_for("calagator").is_empty());
+    assert_eq!(1, github.pulls_for("moe").len());
+    assert!(!github.requests().iter().any(|r| r.contains("/projects/1/")));
+}
+
+#[test]
+fn gitea_org_is_paged_with_page_and_limit() {
+    let github = FakeGithub::new("party-org")
+        .on_gitea()
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["Merge pull request #12 from party-org/feature"]))
+        .with_repo(FakeRepo::new("moe"))
+        .with_repo(FakeRepo::new("larry").with_master_commits(&["fix: typo"]).with_release_pr(7))
+        .start();
+
+    let run = github.run_party(&["--json"]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run.stderr.contains("Number of repos to check: 3"), "stderr: {}", run.stderr);
+    assert_eq!(
+        vec![
+            "https://github.com/party-org/calagator/pull/100".to_owned(),
+            "https://github.com/party-org/larry/pull/7".to_owned(),
+        ],
+        run.release_pr_links()
+    );
+    assert!(github.pulls_for("calagator")[0].body.clone().unwrap().contains("* #12"));
+    assert!(github.pulls_for("moe").is_empty());
+    let repo_pages = github
+        .requests()
+        .iter()
+        .filter(|r| *r == "GET /orgs/party-org/repos")
+        .count();
+    assert_eq!(2, repo_pages);
+    assert!(github.authorizations().iter().all(|a| a == "token fake-gitea-token"));
+}
+
+#[test]
+fn bitbucket_project_gets_release_pull_requests() {
+    let github = FakeGithub::new("PARTY")
+        .on_bitbucket()
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["Merge pull request #12 from PARTY/feature"]))
+        .with_repo(FakeRepo::new("moe"))
+        .with_repo(FakeRepo::new("larry").with_master_commits(&["fix: typo"]).with_release_pr(7))
+        .start();
+
+    let run = github.run_party(&["--json"]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run.stderr.contains("Number of repos to check: 3"), "stderr: {}", run.stderr);
+    assert_eq!(
+        vec![
+            "https://bitbucket.example.com/projects/PARTY/repos/calagator/pull-requests/100".to_owned(),
+            "https://bitbucket.example.com/projects/PARTY/repos/larry/pull-requests/7".to_owned(),
+        ],
+        run.release_pr_links()
+    );
+    assert!(github.pulls_for("calagator")[0].body.clone().unwrap().contains("* #12"));
+    assert!(github.pulls_for("larry")[0].body.is_some());
+    assert!(github.pulls_for("moe").is_empty());
+    assert!(github.authorizations().iter().all(|a| a == "Bearer fake-bitbucket-token"));
+}
+
+#[test]
+fn bitbucket_needs_an_api_url() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_release-party-br"))
+        .args(["--org", "PARTY", "--forge", "bitbucket"])
+        .env("BITBUCKET_TOKEN", "fake-bitbucket-token")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Use --api-url to say where Bitbucket is"));
+}
+
+#[test]
+fn audit_fix_protects_release_branches_and_reports_missing_ones() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").without_release_branch())
+        .with_repo(FakeRepo::new("moe"))
+        .start();
+    fs::write(
+        github.workdir().join("ignoredrepos.toml"),
+        "[protection]\nrequired_approving_reviews = 1\n",
+    )
+    .unwrap();
+
+    let run = github.run_party(&["audit", "--fix"]);
+    fs::remove_file(github.workdir().join("ignoredrepos.toml")).unwrap();
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run.stdout.contains("moe: release branch isn't protected"), "stdout: {}", run.stdout);
+    assert!(
+        run.stdout.contains("These repos don't have a release branch to protect:\ncalagator"),
+        "stdout: {}",
+        run.stdout
+    );
+    let puts: Vec<String> = github.requests().into_iter().filter(|r| r.starts_with("PUT")).collect();
+    assert_eq!(vec!["PUT /repos/party-org/moe/branches/release/protection".to_owned()], puts);
+}
+
+#[test]
+fn release_prs_are_posted_to_the_webhook() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["fix: typo"]))
+        .with_repo(FakeRepo::new("moe"))
+        .start();
+    let config = format!(
+        "[webhook]\nurl = \"{}/hooks/slack\"\nmentions = [\"@release-team\"]\n",
+        github.api_url()
+    );
+    fs::write(github.workdir().join("ignoredrepos.toml"), config).unwrap();
+
+    let run = github.run_party(&[]);
+    fs::remove_file(github.workdir().join("ignoredrepos.toml")).unwrap();
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    let webhooks = github.webhooks();
+    assert_eq!(1, webhooks.len());
+    let payload: serde_json::Value = serde_json::from_str(&webhooks[0]).unwrap();
+    let text = payload["text"].as_str().unwrap();
+    assert!(text.contains("It's a release party! 1 PRs"), "text: {}", text);
+    assert!(text.contains("calagator: https://github.com/party-org/calagator/pull/"), "text: {}", text);
+    assert!(text.ends_with("@release-team"), "text: {}", text);
+}
+
+#[test]
+fn nothing_is_posted_to_the_webhook_without_release_prs() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("moe"))
+        .start();
+    let config = format!("[webhook]\nurl = \"{}/hooks/slack\"\n", github.api_url());
+    fs::write(github.workdir().join("ignoredrepos.toml"), config).unwrap();
+
+    let run = github.run_party(&[]);
+    fs::remove_file(github.workdir().join("ignoredrepos.toml")).unwrap();
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(github.webhooks().is_empty());
+}
+
+#[test]
+fn release_prs_are_emailed_with_release_notes() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["fix: typo"]))
+        .start();
+    let sink = SmtpSink::start();
+    let config = format!(
+        "[email]\nhost = \"127.0.0.1\"\nport = {}\nstarttls = false\nfrom = \"party@example.com\"\nto = [\"cab@example.com\"]\n",
+        sink.port
+    );
+    fs::write(github.workdir().join("ignoredrepos.toml"), config).unwrap();
+
+    let run = github.run_party(&[]);
+    fs::remove_file(github.workdir().join("ignoredrepos.toml")).unwrap();
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    let messages = sink.messages();
+    assert_eq!(1, messages.len(), "stdout: {}", run.stdout);
+    let message = &messages[0];
+    assert!(message.contains("To: <cab@example.com>"), "{}", message);
+    assert!(message.contains("Subject: Release party: 1 release PRs to review"), "{}", message);
+    assert!(message.contains("Content-Type: text/plain"), "{}", message);
+    assert!(message.contains("Content-Type: text/html"), "{}", message);
+    assert!(message.contains("* fix: typo"), "{}", message);
+    assert!(message.contains("<li>fix: typo</li>"), "{}", message);
+}
+
+#[test]
+fn merged_prs_are_told_they_are_in_the_release_once() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(
+            FakeRepo::new("calagator")
+                .with_master_commits(&[
+                    "Merge pull request #12 from party-org/feature",
+                    "Merge pull request #14 from party-org/fix",
+                ])
+                .with_release_pr(7)
+                .with_comment(14, "Included in release PR #7"),
+        )
+        .start();
+
+    let run = github.run_party(&["--comment-on-included-prs", "--label-included-prs"]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run.stdout.contains("commented on #12"), "stdout: {}", run.stdout);
+    let repo = github.repo("calagator");
+    assert_eq!(
+        vec![
+            ("14".to_owned(), "Included in release PR #7".to_owned()),
+            ("12".to_owned(), "Included in release PR #7".to_owned()),
+        ],
+        repo.comments
+    );
+    // #14 was commented on by an earlier run that couldn't label it.
+    assert_eq!(
+        vec![
+            ("12".to_owned(), "in-release".to_owned()),
+            ("14".to_owned(), "in-release".to_owned()),
+        ],
+        repo.labels
+    );
+}
+
+#[test]
+fn commenting_on_merged_prs_is_refused_off_github() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").with_release_pr(7))
+        .on_gitlab()
+        .start();
+
+    let run = github.run_party(&["--comment-on-included-prs"]);
+
+    assert!(!run.success);
+    assert!(
+        run.stderr.contains("--comment-on-included-prs only works with GitHub."),
+        "stderr: {}",
+        run.stderr
+    );
+    assert!(github.requests().is_empty(), "requests: {:?}", github.requests());
+}
+
+#[test]
+fn jira_issues_are_listed_commented_on_and_moved() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(
+            FakeRepo::new("calagator")
+                .with_master_commits(&["Merge pull request #12 from party-org/PAY-12-refunds", "OPS-7: fix: typo"])
+                .with_release_pr(7),
+        )
+        .start();
+    let config = format!(
+        "[jira]\nkey_pattern = \"PAY-[0-9]+\"\nurl = \"{}/jira\"\ncomment = true\ntransition = \"ready for release\"\n",
+        github.api_url()
+    );
+    fs::write(github.workdir().join("ignoredrepos.toml"), config).unwrap();
+
+    let run = github.run_party(&[]);
+    let second_run = github.run_party(&[]);
+    fs::remove_file(github.workdir().join("ignoredrepos.toml")).unwrap();
+
+    assert!(run.success && second_run.success, "stderr: {}", run.stderr);
+    assert!(run.stdout.contains("issues: PAY-12"), "stdout: {}", run.stdout);
+    let body = github.pulls_for("calagator")[0].body.clone().unwrap();
+    assert!(body.contains("Issues in this release:\n* [PAY-12]("), "body: {}", body);
+    let state = github.state.lock().unwrap();
+    let comment = "Included in release PR https://github.com/party-org/calagator/pull/7".to_owned();
+    assert_eq!(vec![("PAY-12".to_owned(), comment)], state.jira_comments);
+    assert_eq!(
+        vec![("PAY-12".to_owned(), "31".to_owned()), ("PAY-12".to_owned(), "31".to_owned())],
+        state.jira_transitions
+    );
+}
+
+#[test]
+fn runs_are_logged_and_shown_by_history() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["fix: typo"]))
+        .with_repo(FakeRepo::new("moe").without_release_branch())
+        .start();
+    let history_file = github.workdir().join("history.jsonl");
+    let history_file = history_file.to_string_lossy();
+    let _ = fs::remove_file(&*history_file);
+
+    let run = github.run_party(&["--history-file", &history_file]);
+    let dry_run = github.run_party(&["--history-file", &history_file, "--dry-run"]);
+    let history = github.run_party(&["--history-file", &history_file, "history", "--repo", "calagator"]);
+    let latest = github.run_party(&["--history-file", &history_file, "--json", "history", "--limit", "1"]);
+    fs::remove_file(&*history_file).unwrap();
+
+    assert!(run.success && dry_run.success, "stderr: {}", run.stderr);
+    assert!(history.success, "stderr: {}", history.stderr);
+    let lines = history.stdout.lines().collect::<Vec<&str>>();
+    assert_eq!(4, lines.len(), "stdout: {}", history.stdout);
+    assert!(lines[0].ends_with(" party by test-user (dry run)"), "stdout: {}", history.stdout);
+    assert!(lines[2].ends_with(" party by test-user"), "stdout: {}", history.stdout);
+    assert!(lines[3].starts_with("  calagator: release PR https://github.com/party-org/calagator/pull/"), "stdout: {}", history.stdout);
+    assert!(lines[3].ends_with(" at 0000001"), "stdout: {}", history.stdout);
+
+    let runs: serde_json::Value = serde_json::from_str(&latest.stdout).unwrap();
+    assert_eq!(1, runs.as_array().unwrap().len());
+    assert_eq!(true, runs[0]["dry_run"]);
+    assert_eq!("no release branch", runs[0]["repos"][1]["outcome"]);
+}
+
+#[test]
+fn unchanged_responses_are_revalidated_from_the_cache() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["fix: typo"]))
+        .with_repo(FakeRepo::new("moe"))
+        .with_repo(FakeRepo::new("sprout").with_master_commits(&["feat: sprouts"]))
+        .with_etags()
+        .start();
+    let cache_dir = github.workdir().join("cache");
+    let _ = fs::remove_dir_all(&cache_dir);
+    let cache_dir = cache_dir.to_string_lossy();
+
+    let first = github.run_party(&["--dry-run", "--json", "--cache-dir", &cache_dir]);
+    assert_eq!(0, github.not_modified());
+    let second = github.run_party(&["--dry-run", "--json", "--cache-dir", &cache_dir]);
+    let revalidated = github.not_modified();
+    let uncached = github.run_party(&["--dry-run", "--json", "--no-cache"]);
+    fs::remove_dir_all(&*cache_dir).unwrap();
+
+    assert!(first.success && second.success, "stderr: {}", second.stderr);
+    // Both listing pages and every compare came back 304, and the second page was still found.
+    assert!(revalidated >= 4, "only {} responses were 304s", revalidated);
+    assert_eq!(first.json(), second.json());
+    assert!(second.stderr.contains("Number of repos to check: 3"), "stderr: {}", second.stderr);
+    assert_eq!(revalidated, github.not_modified());
+    assert_eq!(first.json(), uncached.json());
+}
+
+#[test]
+fn graphql_batches_find_the_same_release_prs_with_fewer_requests() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["fix: typo"]).with_release_pr(3))
+        .with_repo(FakeRepo::new("moe"))
+        .with_repo(FakeRepo::new("sprout").with_release_commits(&["hotfix: sprouts"]))
+        .with_repo(FakeRepo::new("gone").without_release_branch())
+        .start();
+
+    let rest = github.run_party(&["--dry-run", "--json", "--back-merge", "--no-cache"]);
+    let rest_requests = github.requests().len();
+    let graphql = github.run_party(&["--dry-run", "--json", "--back-merge", "--no-cache", "--graphql"]);
+    let graphql_requests = github.requests()[rest_requests..].to_vec();
+
+    assert!(rest.success && graphql.success, "stderr: {}", graphql.stderr);
+    assert_eq!(rest.stdout, graphql.stdout);
+    assert_eq!(1, graphql_requests.iter().filter(|r| *r == "POST /graphql").count());
+    // Only calagator has anything to release, so only its compare goes over REST.
+    let compares: Vec<&String> = graphql_requests.iter().filter(|r| r.contains("/compare/")).collect();
+    assert_eq!(vec!["GET /repos/party-org/calagator/compare/release...master"], compares);
+    assert!(!graphql_requests.iter().any(|r| r.ends_with("/pulls") || r.contains("/branches/")), "{:?}", graphql_requests);
+    assert!(graphql_requests.len() < rest_requests, "{:?}", graphql_requests);
+}
+
+#[test]
+fn transient_failures_are_retried() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["fix: typo"]))
+        .failing_once("GET /orgs/party-org/repos", 503)
+        .failing_once("GET /repos/party-org/calagator/compare/release...master", 502)
+        .failing_once("GET /repos/party-org/calagator/pulls", 403)
+        .start();
+
+    let run = github.run_party(&[]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run.stderr.contains("Github responded with 502 Bad Gateway to "), "stderr: {}", run.stderr);
+    assert_eq!(3, run.stderr.matches(", retrying in ").count(), "stderr: {}", run.stderr);
+    assert_eq!(1, github.pulls_for("calagator").len());
+}
+
+#[test]
+fn a_release_pr_whose_response_was_lost_isnt_opened_twice() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["fix: typo"]))
+        .losing_response_once("POST /repos/party-org/calagator/pulls")
+        .start();
+
+    let run = github.run_party(&[]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert_eq!(1, github.pulls_for("calagator").len());
+    let creates = github.requests().iter().filter(|r| *r == "POST /repos/party-org/calagator/pulls").count();
+    assert_eq!(1, creates);
+    assert!(run.stdout.contains("https://github.com/party-org/calagator/pull/100"), "stdout: {}", run.stdout);
+}
+
+#[test]
+fn no_retries_with_retries_zero() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["fix: typo"]))
+        .failing_once("GET /repos/party-org/calagator/compare/release...master", 502)
+        .start();
+
+    let run = github.run_party(&["--retries", "0"]);
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run.stderr.contains("Couldn't compare release with master for calagator"), "stderr: {}", run.stderr);
+    assert!(github.pulls_for("calagator").is_empty());
+}
+
+#[test]
+fn requests_go_through_the_configured_proxy() {
+    let github = FakeGithub::new("party-org")
+        .with_repo(FakeRepo::new("calagator").with_master_commits(&["fix: typo"]))
+        .start();
+    let config = format!("[http]\nproxy = \"{}\"\nconnect_timeout = 5\n", github.api_url());
+    fs::write(github.workdir().join("ignoredrepos.toml"), config).unwrap();
+
+    let run = github.run_party(&["--timeout", "10"]);
+    fs::remove_file(github.workdir().join("ignoredrepos.toml")).unwrap();
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert_eq!(1, github.pulls_for("calagator").len());
+    assert_eq!(github.requests().len() as u32, github.proxied());
+}
+
+#[test]
+fn a_ca_bundle_that_cant_be_read_stops_the_run() {
+    let github = FakeGithub::new("party-org").with_repo(FakeRepo::new("calagator")).start();
+
+    let run = github.run_party(&["--ca-bundle", "/nonexistent/corp-ca.pem"]);
+
+    assert!(!run.success);
+    assert!(run.stderr.contains("Couldn't read the CA bundle /nonexistent/corp-ca.pem"), "stderr: {}", run.stderr);
+    assert!(github.requests().is_empty());
+}
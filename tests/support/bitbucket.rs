@@ -0,0 +1,216 @@
+// The fake org served as a Bitbucket Server project through /rest/api/1.0, the org name standing in for the project key.
+
+use hyper::{Body, Method, Response, StatusCode};
+use serde_json::Value;
+
+use super::{param, FakeGithub, FakePull, FakeRepo};
+
+impl FakeGithub {
+    pub(super) fn handle_bitbucket(
+        &mut self,
+        method: &Method,
+        segments: &[&str],
+        params: &[(String, String)],
+        body: &str,
+    ) -> Response<Body> {
+        match (method, segments) {
+            (&Method::GET, ["projects", project, "repos"]) if *project == self.org => self.list_project_repos(params),
+            (_, ["projects", project, "repos", slug, rest @ ..]) if *project == self.org => {
+                let index = match self.repos.iter().position(|r| r.name == *slug) {
+                    Some(index) => index,
+                    None => return self.respond(StatusCode::NOT_FOUND, error_json("Repository does not exist.")),
+                };
+                let (status, response) = self.handle_bitbucket_repo(method, index, rest, params, body);
+                self.respond(status, response)
+            }
+            _ => self.respond(StatusCode::NOT_FOUND, error_json("Not Found")),
+        }
+    }
+
+    fn list_project_repos(&mut self, params: &[(String, String)]) -> Response<Body> {
+        if let Some(status) = self.repos_status {
+            let status = StatusCode::from_u16(status).unwrap();
+            return self.respond(status, error_json("Server Error"));
+        }
+        let start = param(params, "start")
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(0);
+        let values = self
+            .repos
+            .iter()
+            .skip(start)
+            .take(self.page_size)
+            .map(|r| json!({"slug": r.name, "name": r.name, "project": {"key": self.org}}))
+            .collect::<Vec<Value>>();
+        let next_start = start + values.len();
+        let mut page = page_json(values);
+        if next_start < self.repos.len() {
+            page["isLastPage"] = json!(false);
+            page["nextPageStart"] = json!(next_start);
+        }
+        self.respond(StatusCode::OK, page)
+    }
+
+    fn handle_bitbucket_repo(
+        &mut self,
+        method: &Method,
+        index: usize,
+        rest: &[&str],
+        params: &[(String, String)],
+        body: &str,
+    ) -> (StatusCode, Value) {
+        let org = self.org.clone();
+        let id = if *method == Method::POST && rest == ["pull-requests"] {
+            self.next_pr_number()
+        } else {
+            0
+        };
+        let repo = &mut self.repos[index];
+        match (method, rest) {
+            (&Method::GET, ["compare", "commits"]) | (&Method::GET, ["compare", "changes"]) => {
+                compare(repo, rest[1], param(params, "from"), param(params, "to"))
+            }
+            (&Method::GET, ["branches"]) => {
+                let mut branches = vec![json!({"displayId": "master", "id": "refs/heads/master"})];
+                if repo.has_release_branch {
+                    branches.push(json!({"displayId": "release", "id": "refs/heads/release"}));
+                }
+                (StatusCode::OK, page_json(branches))
+            }
+            (&Method::POST, ["branches"]) => {
+                repo.has_release_branch = true;
+                (StatusCode::OK, json!({"displayId": "release", "id": "refs/heads/release"}))
+            }
+            (&Method::GET, ["commits"]) => match param(params, "until") {
+                Some("master") => (
+                    StatusCode::OK,
+                    page_json(vec![json!({"id": format!("master-{}", repo.master_commits.len())})]),
+                ),
+                Some("release") if repo.has_release_branch => (
+                    StatusCode::OK,
+                    page_json(vec![json!({"id": format!("release-{}", repo.release_commits.len())})]),
+                ),
+                _ => (StatusCode::NOT_FOUND, error_json("Commit does not exist")),
+            },
+            (&Method::GET, ["tags"]) => (
+                StatusCode::OK,
+                page_json(
+                    repo.tags
+                        .iter()
+                        .map(|t| json!({"displayId": t, "latestCommit": format!("sha-{}", t)}))
+                        .collect(),
+                ),
+            ),
+            (&Method::GET, ["pull-requests"]) => {
+                let at = param(params, "at").map(|at| at.replace("%2F", "/"));
+                let pulls = repo
+                    .pulls
+                    .iter()
+                    .filter(|p| at == Some(format!("refs/heads/{}", p.head)))
+                    .map(|p| pull_request_json(&org, &repo.name, p))
+                    .collect();
+                (StatusCode::OK, page_json(pulls))
+            }
+            (&Method::POST, ["pull-requests"]) => {
+                let request: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+                let head = branch_name(&request["fromRef"]["id"]);
+                let base = branch_name(&request["toRef"]["id"]);
+                if repo.pulls.iter().any(|p| p.head == head && p.base == base) {
+                    return (StatusCode::CONFLICT, error_json("Only one pull request may be open for a given source and target branch"));
+                }
+                let pull = FakePull {
+                    number: id,
+                    head,
+                    base,
+                    title: request["title"].as_str().unwrap_or("").to_owned(),
+                    body: None,
+                };
+                let response = pull_request_json(&org, &repo.name, &pull);
+                repo.pulls.push(pull);
+                (StatusCode::CREATED, response)
+            }
+            (&Method::GET, ["pull-requests", id]) => match repo.pulls.iter().find(|p| p.number.to_string() == *id) {
+                Some(pull) => (StatusCode::OK, pull_request_json(&org, &repo.name, pull)),
+                None => (StatusCode::NOT_FOUND, error_json("Pull request does not exist")),
+            },
+            (&Method::PUT, ["pull-requests", id]) => {
+                let request: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+                // Bitbucket refuses updates that don't say which version they're changing.
+                if request["version"].as_u64().is_none() {
+                    return (StatusCode::BAD_REQUEST, error_json("version is required"));
+                }
+                match repo.pulls.iter_mut().find(|p| p.number.to_string() == *id) {
+                    Some(pull) => {
+                        pull.body = request["description"].as_str().map(|b| b.to_owned());
+                        (StatusCode::OK, pull_request_json(&org, &repo.name, pull))
+                    }
+                    None => (StatusCode::NOT_FOUND, error_json("Pull request does not exist")),
+                }
+            }
+            (&Method::GET, ["pull-requests", _, "commits"]) => (
+                StatusCode::OK,
+                page_json(
+                    repo.master_commits
+                        .iter()
+                        .enumerate()
+                        .map(|(i, message)| commit_json(i, message))
+                        .collect(),
+                ),
+            ),
+            _ => (StatusCode::NOT_FOUND, error_json("Not Found")),
+        }
+    }
+}
+
+// Only master and release are asked about: the commits on `from` that `to` doesn't have.
+fn compare(repo: &FakeRepo, what: &str, from: Option<&str>, to: Option<&str>) -> (StatusCode, Value) {
+    if let Some(status) = repo.compare_status {
+        return (StatusCode::from_u16(status).unwrap(), error_json("Server Error"));
+    }
+    if !repo.has_release_branch {
+        return (StatusCode::NOT_FOUND, error_json("Commit 'release' does not exist"));
+    }
+    let messages = match (from, to) {
+        (Some("master"), Some("release")) => &repo.master_commits,
+        _ => &repo.release_commits,
+    };
+    let values = messages
+        .iter()
+        .enumerate()
+        .map(|(i, message)| match what {
+            "changes" => json!({"path": {"toString": format!("src/file{}.rs", i)}, "type": "MODIFY"}),
+            _ => commit_json(i, message),
+        })
+        .collect();
+    (StatusCode::OK, page_json(values))
+}
+
+fn page_json(values: Vec<Value>) -> Value {
+    json!({"size": values.len(), "limit": 25, "start": 0, "isLastPage": true, "values": values})
+}
+
+fn commit_json(index: usize, message: &str) -> Value {
+    json!({"id": format!("{:07}", index + 1), "displayId": format!("{:07}", index + 1), "message": message})
+}
+
+fn branch_name(ref_id: &Value) -> String {
+    ref_id.as_str().unwrap_or("").trim_start_matches("refs/heads/").to_owned()
+}
+
+fn pull_request_json(project: &str, repo: &str, pull: &FakePull) -> Value {
+    json!({
+        "id": pull.number,
+        "version": 0,
+        "title": pull.title,
+        "description": pull.body,
+        "fromRef": {"id": format!("refs/heads/{}", pull.head)},
+        "toRef": {"id": format!("refs/heads/{}", pull.base)},
+        "links": {"self": [{
+            "href": format!("https://bitbucket.example.com/projects/{}/repos/{}/pull-requests/{}", project, repo, pull.number)
+        }]}
+    })
+}
+
+fn error_json(message: &str) -> Value {
+    json!({"errors": [{"message": message}]})
+}
@@ -0,0 +1,109 @@
+// The fake org served the way Gitea does. Most routes are GitHub's, these are the ones that differ.
+
+use hyper::{Body, Method, Response, StatusCode};
+use serde_json::Value;
+
+use super::{commit_json, param, FakeGithub, FakeRepo};
+
+impl FakeGithub {
+    pub(super) fn handle_gitea(
+        &mut self,
+        method: &Method,
+        segments: &[&str],
+        params: &[(String, String)],
+    ) -> Option<Response<Body>> {
+        match (method, segments) {
+            (&Method::GET, ["orgs", org, "repos"]) if *org == self.org => Some(self.list_gitea_repos(params)),
+            (&Method::GET, ["repos", _, name, "compare", range]) => {
+                let repo = self.repos.iter().find(|r| r.name == *name)?;
+                let (status, response) = compare(repo, range);
+                Some(self.respond(status, response))
+            }
+            (&Method::GET, ["repos", _, name, "commits"]) => {
+                let repo = self.repos.iter().find(|r| r.name == *name)?;
+                let (status, response, total) = match param(params, "sha") {
+                    Some("master") => (
+                        StatusCode::OK,
+                        json!([{"sha": format!("master-{}", repo.master_commits.len())}]),
+                        repo.master_commits.len(),
+                    ),
+                    Some("release") if repo.has_release_branch => (
+                        StatusCode::OK,
+                        json!([{"sha": format!("release-{}", repo.release_commits.len())}]),
+                        repo.release_commits.len(),
+                    ),
+                    _ => (StatusCode::NOT_FOUND, json!({"message": "object does not exist"}), 0),
+                };
+                let mut response = self.respond(status, response);
+                response
+                    .headers_mut()
+                    .insert("X-Total-Count", total.to_string().parse().unwrap());
+                Some(response)
+            }
+            _ => None,
+        }
+    }
+
+    // No Link headers: callers page with `page` and `limit` and stop at X-Total-Count.
+    fn list_gitea_repos(&mut self, params: &[(String, String)]) -> Response<Body> {
+        if let Some(status) = self.repos_status {
+            let status = StatusCode::from_u16(status).unwrap();
+            return self.respond(status, json!({"message": "Server Error"}));
+        }
+        let page = param(params, "page")
+            .and_then(|p| p.parse::<usize>().ok())
+            .unwrap_or(1);
+        let limit = param(params, "limit")
+            .and_then(|l| l.parse::<usize>().ok())
+            .unwrap_or(self.page_size)
+            .min(self.page_size);
+        let repos = self
+            .repos
+            .iter()
+            .enumerate()
+            .skip((page - 1) * limit)
+            .take(limit)
+            .map(|(i, r)| {
+                json!({
+                    "id": i,
+                    "name": r.name,
+                    "full_name": format!("{}/{}", self.org, r.name),
+                    "permissions": {"admin": false, "push": r.push, "pull": true}
+                })
+            })
+            .collect();
+        let mut response = self.respond(StatusCode::OK, Value::Array(repos));
+        response
+            .headers_mut()
+            .insert("X-Total-Count", self.repos.len().to_string().parse().unwrap());
+        response
+    }
+}
+
+// Gitea's compare has no ahead/behind counts, only the commits `head` has that `base` doesn't.
+fn compare(repo: &FakeRepo, range: &str) -> (StatusCode, Value) {
+    if let Some(status) = repo.compare_status {
+        return (StatusCode::from_u16(status).unwrap(), json!({"message": "Server Error"}));
+    }
+    if !repo.has_release_branch {
+        return (StatusCode::NOT_FOUND, json!({"message": "GetRefCommitID"}));
+    }
+    let messages = if range == "release...master" {
+        &repo.master_commits
+    } else {
+        &repo.release_commits
+    };
+    let commits: Vec<Value> = messages
+        .iter()
+        .enumerate()
+        .map(|(i, message)| {
+            let mut commit = commit_json(i, message);
+            commit["files"] = json!([{"filename": format!("src/file{}.rs", i), "status": "modified"}]);
+            commit
+        })
+        .collect();
+    (
+        StatusCode::OK,
+        json!({"total_commits": commits.len(), "commits": commits}),
+    )
+}
@@ -0,0 +1,231 @@
+// The fake org served as a GitLab group through the v4 API. Projects are numbered from 1 in repo order.
+
+use hyper::{Body, Method, Response, StatusCode};
+use serde_json::Value;
+
+use super::{param, FakeGithub, FakePull, FakeRepo};
+
+impl FakeGithub {
+    pub(super) fn handle_gitlab(
+        &mut self,
+        method: &Method,
+        segments: &[&str],
+        params: &[(String, String)],
+        body: &str,
+    ) -> Response<Body> {
+        match (method, segments) {
+            (&Method::GET, ["groups", group, "projects"]) if *group == self.org => self.list_projects(params),
+            (&Method::GET, ["projects", id, "repository", "tags"]) => self.list_project_tags(id, params),
+            (_, ["projects", id, rest @ ..]) => {
+                let index = match id.parse::<usize>() {
+                    Ok(id) if id >= 1 && id <= self.repos.len() => id - 1,
+                    _ => return self.respond(StatusCode::NOT_FOUND, json!({"message": "404 Project Not Found"})),
+                };
+                let (status, response) = self.handle_project(method, index, rest, params, body);
+                self.respond(status, response)
+            }
+            _ => self.respond(StatusCode::NOT_FOUND, json!({"message": "404 Not Found"})),
+        }
+    }
+
+    fn list_projects(&mut self, params: &[(String, String)]) -> Response<Body> {
+        if let Some(status) = self.repos_status {
+            let status = StatusCode::from_u16(status).unwrap();
+            return self.respond(status, json!({"message": "500 Internal Server Error"}));
+        }
+        let include_subgroups = param(params, "include_subgroups") == Some("true");
+        // Group listings carry no permissions, only `min_access_level` says which projects we can push to.
+        let pushable_only = param(params, "min_access_level")
+            .and_then(|level| level.parse::<u32>().ok())
+            .map(|level| level >= 30)
+            .unwrap_or(false);
+        let page = param(params, "page")
+            .and_then(|p| p.parse::<usize>().ok())
+            .unwrap_or(1);
+        let listed = self
+            .repos
+            .iter()
+            .enumerate()
+            .filter(|&(_, r)| include_subgroups || !r.name.contains('/'))
+            .filter(|&(_, r)| r.push || !pushable_only)
+            .collect::<Vec<(usize, &FakeRepo)>>();
+        let has_next = page * self.page_size < listed.len();
+        let projects = listed
+            .iter()
+            .skip((page - 1) * self.page_size)
+            .take(self.page_size)
+            .map(|&(i, r)| {
+                json!({
+                    "id": i + 1,
+                    "path_with_namespace": format!("{}/{}", self.org, r.name),
+                })
+            })
+            .collect();
+        let mut response = self.respond(StatusCode::OK, Value::Array(projects));
+        let next_page = if has_next { (page + 1).to_string() } else { String::new() };
+        response.headers_mut().insert("X-Next-Page", next_page.parse().unwrap());
+        response
+    }
+
+    // Tags a page at a time, `per_page` defaulting to GitLab's 20, with the next page in X-Next-Page.
+    fn list_project_tags(&mut self, id: &str, params: &[(String, String)]) -> Response<Body> {
+        let repo = match id.parse::<usize>() {
+            Ok(id) if id >= 1 && id <= self.repos.len() => &self.repos[id - 1],
+            _ => return self.respond(StatusCode::NOT_FOUND, json!({"message": "404 Project Not Found"})),
+        };
+        let per_page = param(params, "per_page").and_then(|p| p.parse::<usize>().ok()).unwrap_or(20);
+        let page = param(params, "page").and_then(|p| p.parse::<usize>().ok()).unwrap_or(1);
+        let tags = repo
+            .tags
+            .iter()
+            .skip((page - 1) * per_page)
+            .take(per_page)
+            .map(|t| json!({"name": t, "commit": {"id": format!("sha-{}", t)}}))
+            .collect();
+        let next_page = if page * per_page < repo.tags.len() { (page + 1).to_string() } else { String::new() };
+        let mut response = self.respond(StatusCode::OK, Value::Array(tags));
+        response.headers_mut().insert("X-Next-Page", next_page.parse().unwrap());
+        response
+    }
+
+    fn handle_project(
+        &mut self,
+        method: &Method,
+        index: usize,
+        rest: &[&str],
+        params: &[(String, String)],
+        body: &str,
+    ) -> (StatusCode, Value) {
+        let org = self.org.clone();
+        let number = if *method == Method::POST && rest == ["merge_requests"] {
+            self.next_pr_number()
+        } else {
+            0
+        };
+        let repo = &mut self.repos[index];
+        match (method, rest) {
+            (&Method::GET, ["repository", "compare"]) => compare(repo, param(params, "from"), param(params, "to")),
+            (&Method::GET, ["repository", "branches", "release"]) if !repo.has_release_branch => {
+                (StatusCode::NOT_FOUND, json!({"message": "404 Branch Not Found"}))
+            }
+            (&Method::GET, ["repository", "branches", branch]) => {
+                (StatusCode::OK, json!({"name": branch, "commit": {"id": "1111111"}}))
+            }
+            (&Method::GET, ["repository", "commits", "master"]) => (
+                StatusCode::OK,
+                json!({"id": format!("master-{}", repo.master_commits.len())}),
+            ),
+            (&Method::GET, ["repository", "commits", "release"]) if repo.has_release_branch => (
+                StatusCode::OK,
+                json!({"id": format!("release-{}", repo.release_commits.len())}),
+            ),
+            (&Method::GET, ["merge_requests"]) => {
+                let source = param(params, "source_branch");
+                let target = param(params, "target_branch");
+                let merge_requests = repo
+                    .pulls
+                    .iter()
+                    .filter(|p| source == Some(p.head.as_str()) && target == Some(p.base.as_str()))
+                    .map(|p| merge_request_json(&org, &repo.name, p))
+                    .collect();
+                (StatusCode::OK, Value::Array(merge_requests))
+            }
+            (&Method::POST, ["merge_requests"]) => {
+                let request: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+                let head = request["source_branch"].as_str().unwrap_or("").to_owned();
+                let base = request["target_branch"].as_str().unwrap_or("").to_owned();
+                if repo.pulls.iter().any(|p| p.head == head && p.base == base) {
+                    return (
+                        StatusCode::CONFLICT,
+                        json!({"message": ["Another open merge request already exists for this source branch"]}),
+                    );
+                }
+                let pull = FakePull {
+                    number,
+                    head,
+                    base,
+                    title: request["title"].as_str().unwrap_or("").to_owned(),
+                    body: None,
+                };
+                let response = merge_request_json(&org, &repo.name, &pull);
+                repo.pulls.push(pull);
+                (StatusCode::CREATED, response)
+            }
+            (&Method::GET, ["merge_requests", iid]) => match repo.pulls.iter().find(|p| p.number.to_string() == *iid) {
+                Some(pull) => (StatusCode::OK, merge_request_json(&org, &repo.name, pull)),
+                None => (StatusCode::NOT_FOUND, json!({"message": "404 Not found"})),
+            },
+            (&Method::PUT, ["merge_requests", iid]) => {
+                let request: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+                match repo.pulls.iter_mut().find(|p| p.number.to_string() == *iid) {
+                    Some(pull) => {
+                        pull.body = request["description"].as_str().map(|b| b.to_owned());
+                        (StatusCode::OK, merge_request_json(&org, &repo.name, pull))
+                    }
+                    None => (StatusCode::NOT_FOUND, json!({"message": "404 Not found"})),
+                }
+            }
+            (&Method::GET, ["merge_requests", _, "commits"]) => (
+                StatusCode::OK,
+                Value::Array(
+                    repo.master_commits
+                        .iter()
+                        .enumerate()
+                        .map(|(i, message)| commit_json(i, message))
+                        .collect(),
+                ),
+            ),
+            _ => (StatusCode::NOT_FOUND, json!({"message": "404 Not Found"})),
+        }
+    }
+}
+
+// Only release and master are asked about: `to`'s commits that `from` doesn't have.
+fn compare(repo: &FakeRepo, from: Option<&str>, to: Option<&str>) -> (StatusCode, Value) {
+    if let Some(status) = repo.compare_status {
+        return (StatusCode::from_u16(status).unwrap(), json!({"message": "500 Internal Server Error"}));
+    }
+    if !repo.has_release_branch {
+        return (StatusCode::NOT_FOUND, json!({"message": "404 Ref Not Found"}));
+    }
+    let commits = match (from, to) {
+        (Some("release"), Some("master")) => &repo.master_commits,
+        _ => &repo.release_commits,
+    };
+    let diffs: Vec<Value> = commits
+        .iter()
+        .enumerate()
+        .map(|(i, _)| {
+            json!({
+                "new_path": format!("src/file{}.rs", i),
+                "new_file": false,
+                "renamed_file": false,
+                "deleted_file": false,
+                "diff": "@@ -1 +1,2 @@\n line\n+added\n"
+            })
+        })
+        .collect();
+    (
+        StatusCode::OK,
+        json!({
+            "commits": commits.iter().enumerate().map(|(i, m)| commit_json(i, m)).collect::<Vec<Value>>(),
+            "diffs": diffs,
+            "compare_same_ref": false
+        }),
+    )
+}
+
+fn commit_json(index: usize, message: &str) -> Value {
+    json!({"id": format!("{:07}", index + 1), "title": message.lines().next(), "message": message})
+}
+
+fn merge_request_json(org: &str, repo: &str, pull: &FakePull) -> Value {
+    json!({
+        "iid": pull.number,
+        "title": pull.title,
+        "description": pull.body,
+        "source_branch": pull.head,
+        "target_branch": pull.base,
+        "web_url": format!("https://gitlab.example.com/{}/{}/-/merge_requests/{}", org, repo, pull.number)
+    })
+}
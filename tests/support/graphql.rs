@@ -0,0 +1,64 @@
+// GitHub's GraphQL API at `/graphql`, only as far as the batched repo lookup goes: each `rN` alias is
+// answered from the repo named by the `$rN` variable.
+
+use hyper::{Body, Response, StatusCode};
+use serde_json::Value;
+
+use super::{compare, FakeGithub, FakeRepo};
+
+impl FakeGithub {
+    pub(super) fn handle_graphql(&mut self, body: &str) -> Response<Body> {
+        let request: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+        let variables = match request["variables"].as_object() {
+            Some(variables) => variables.clone(),
+            None => return self.respond(StatusCode::BAD_REQUEST, json!({"message": "Problems parsing JSON"})),
+        };
+        let mut data = serde_json::Map::new();
+        for (alias, name) in variables.iter().filter(|&(alias, _)| alias.starts_with('r')) {
+            let owner = variables.get(&alias.replacen('r', "o", 1)).and_then(|o| o.as_str());
+            let repo = self
+                .repos
+                .iter()
+                .find(|r| Some(r.name.as_str()) == name.as_str() && owner == Some(self.org.as_str()));
+            let node = match repo {
+                Some(repo) => repo_node(&self.org, repo),
+                None => Value::Null,
+            };
+            data.insert(alias.clone(), node);
+        }
+        self.respond(StatusCode::OK, json!({ "data": data }))
+    }
+}
+
+fn repo_node(org: &str, repo: &FakeRepo) -> Value {
+    let release = if repo.has_release_branch {
+        let (_, diff) = compare(repo, "release...master");
+        json!({"compare": {
+            "status": diff["status"].as_str().unwrap_or("identical").to_uppercase(),
+            "aheadBy": diff["ahead_by"],
+            "behindBy": diff["behind_by"]
+        }})
+    } else {
+        Value::Null
+    };
+    let open_pull = |head: &str, base: &str| -> Value {
+        let nodes: Vec<Value> = repo
+            .pulls
+            .iter()
+            .filter(|p| p.head == head && p.base == base)
+            .take(1)
+            .map(|p| {
+                json!({
+                    "url": format!("https://github.com/{}/{}/pull/{}", org, repo.name, p.number),
+                    "headRepositoryOwner": {"login": org}
+                })
+            })
+            .collect();
+        json!({ "nodes": nodes })
+    };
+    json!({
+        "release": release,
+        "releasePr": open_pull("master", "release"),
+        "backMergePr": open_pull("release", "master")
+    })
+}
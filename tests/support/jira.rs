@@ -0,0 +1,38 @@
+// Jira's REST API under `/jira`: every issue offers the same transitions, comments and transitions are recorded.
+
+use hyper::{Body, Method, Response, StatusCode};
+use serde_json::Value;
+
+use super::FakeGithub;
+
+impl FakeGithub {
+    pub(super) fn handle_jira(&mut self, method: &Method, segments: &[&str], body: &str) -> Response<Body> {
+        let request: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+        match (method, segments) {
+            (&Method::GET, ["rest", "api", "2", "issue", key, "comment"]) => {
+                let comments: Vec<Value> = self
+                    .jira_comments
+                    .iter()
+                    .filter(|c| c.0 == *key)
+                    .map(|c| json!({"id": "1", "body": c.1}))
+                    .collect();
+                self.respond(StatusCode::OK, json!({"comments": comments}))
+            }
+            (&Method::POST, ["rest", "api", "2", "issue", key, "comment"]) => {
+                let comment = request["body"].as_str().unwrap_or("").to_owned();
+                self.jira_comments.push((key.to_string(), comment));
+                self.respond(StatusCode::CREATED, json!({"id": "1"}))
+            }
+            (&Method::GET, ["rest", "api", "2", "issue", _, "transitions"]) => self.respond(
+                StatusCode::OK,
+                json!({"transitions": [{"id": "21", "name": "In Progress"}, {"id": "31", "name": "Ready for Release"}]}),
+            ),
+            (&Method::POST, ["rest", "api", "2", "issue", key, "transitions"]) => {
+                let id = request["transition"]["id"].as_str().unwrap_or("").to_owned();
+                self.jira_transitions.push((key.to_string(), id));
+                self.respond(StatusCode::NO_CONTENT, Value::Null)
+            }
+            _ => self.respond(StatusCode::NOT_FOUND, json!({"errorMessages": ["Issue does not exist"]})),
+        }
+    }
+}
@@ -0,0 +1,893 @@
+// An in-process stand-in for the parts of the GitHub API release-party-br uses.
+// Each test builds a `FakeGithub`, starts it on a random local port and runs the real binary against it.
+// The same repos can be served the way other forges do instead, see `gitlab.rs`, `gitea.rs` and `bitbucket.rs`.
+// `graphql.rs` answers the batched repo lookups made with `--graphql`.
+// `smtp.rs` is a mail sink for the email notifier and `jira.rs` stands in for Jira.
+
+mod bitbucket;
+mod gitea;
+mod gitlab;
+mod graphql;
+mod jira;
+mod smtp;
+
+pub use self::smtp::SmtpSink;
+
+use hyper::rt::{self, Future, Stream};
+use hyper::service::service_fn;
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde_json::Value;
+
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Debug)]
+pub struct FakePull {
+    pub number: u32,
+    pub head: String,
+    pub base: String,
+    pub title: String,
+    pub body: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FakeRepo {
+    pub name: String,
+    pub has_release_branch: bool,
+    // Commit messages on master that release doesn't have, and the other way around.
+    pub master_commits: Vec<String>,
+    pub release_commits: Vec<String>,
+    pub tags: Vec<String>,
+    pub pulls: Vec<FakePull>,
+    // Requests to this repo's compare page fail with this status.
+    pub compare_status: Option<u16>,
+    // Whether the repo listing says the token can push to the repo.
+    pub push: bool,
+    // Comments and labels on issues and PRs, as (number, text).
+    pub comments: Vec<(String, String)>,
+    pub labels: Vec<(String, String)>,
+}
+
+impl FakeRepo {
+    pub fn new(name: &str) -> FakeRepo {
+        FakeRepo {
+            name: name.to_owned(),
+            has_release_branch: true,
+            master_commits: Vec::new(),
+            release_commits: Vec::new(),
+            tags: Vec::new(),
+            pulls: Vec::new(),
+            compare_status: None,
+            push: true,
+            comments: Vec::new(),
+            labels: Vec::new(),
+        }
+    }
+
+    pub fn with_master_commits(mut self, messages: &[&str]) -> FakeRepo {
+        self.master_commits = messages.iter().map(|m| m.to_string()).collect();
+        self
+    }
+
+    pub fn with_release_commits(mut self, messages: &[&str]) -> FakeRepo {
+        self.release_commits = messages.iter().map(|m| m.to_string()).collect();
+        self
+    }
+
+    pub fn with_tags(mut self, tags: &[&str]) -> FakeRepo {
+        self.tags = tags.iter().map(|t| t.to_string()).collect();
+        self
+    }
+
+    pub fn with_release_pr(mut self, number: u32) -> FakeRepo {
+        self.pulls.push(FakePull {
+            number,
+            head: "master".to_owned(),
+            base: "release".to_owned(),
+            title: "automated release partay".to_owned(),
+            body: None,
+        });
+        self
+    }
+
+    pub fn without_release_branch(mut self) -> FakeRepo {
+        self.has_release_branch = false;
+        self
+    }
+
+    pub fn with_compare_failing(mut self, status: u16) -> FakeRepo {
+        self.compare_status = Some(status);
+        self
+    }
+
+    pub fn without_push_permission(mut self) -> FakeRepo {
+        self.push = false;
+        self
+    }
+
+    pub fn with_comment(mut self, number: u32, comment: &str) -> FakeRepo {
+        self.comments.push((number.to_string(), comment.to_owned()));
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Failure {
+    pub request: String,
+    pub status: u16,
+    // Whether the request is carried out before failing, like a response lost on the way back.
+    pub handled: bool,
+}
+
+#[derive(Debug)]
+pub struct FakeGithub {
+    pub org: String,
+    pub repos: Vec<FakeRepo>,
+    pub page_size: usize,
+    pub rate_limit_remaining: u32,
+    // The org's repo listing fails with this status.
+    pub repos_status: Option<u16>,
+    pub requests: Vec<String>,
+    // The Authorization header of each request, in the same order as `requests`.
+    pub authorizations: Vec<String>,
+    // Set when the org has the GitHub App installed: when its installation tokens expire.
+    pub app_token_expires_at: Option<String>,
+    pub installation_tokens_issued: u32,
+    // Sent as X-OAuth-Scopes, like GitHub does for classic personal access tokens.
+    pub token_scopes: Option<String>,
+    // Which API to speak: "github", "gitlab", "gitea" or "bitbucket".
+    pub forge: &'static str,
+    // Bodies POSTed to `/hooks/...`, standing in for a chat webhook.
+    pub webhooks: Vec<String>,
+    // Comments and transitions made through the Jira stand-in, as (issue key, comment or transition id).
+    pub jira_comments: Vec<(String, String)>,
+    pub jira_transitions: Vec<(String, String)>,
+    // Whether GETs get an ETag, and how many were answered 304 Not Modified because of one.
+    pub etags: bool,
+    pub not_modified: u32,
+    // Requests like "GET /repos/org/name/compare/release...master" that fail once, see `failing_once`.
+    pub failures: Vec<Failure>,
+    // Requests that came through us as a proxy, asking for an absolute URL.
+    pub proxied: u32,
+    next_pr_number: u32,
+    addr: Option<SocketAddr>,
+}
+
+impl FakeGithub {
+    pub fn new(org: &str) -> FakeGithub {
+        FakeGithub {
+            org: org.to_owned(),
+            repos: Vec::new(),
+            page_size: 2,
+            rate_limit_remaining: 5000,
+            repos_status: None,
+            requests: Vec::new(),
+            authorizations: Vec::new(),
+            app_token_expires_at: None,
+            installation_tokens_issued: 0,
+            token_scopes: None,
+            forge: "github",
+            webhooks: Vec::new(),
+            jira_comments: Vec::new(),
+            jira_transitions: Vec::new(),
+            etags: false,
+            not_modified: 0,
+            failures: Vec::new(),
+            proxied: 0,
+            next_pr_number: 100,
+            addr: None,
+        }
+    }
+
+    pub fn with_repo(mut self, repo: FakeRepo) -> FakeGithub {
+        self.repos.push(repo);
+        self
+    }
+
+    pub fn with_rate_limit_remaining(mut self, remaining: u32) -> FakeGithub {
+        self.rate_limit_remaining = remaining;
+        self
+    }
+
+    pub fn with_github_app(mut self, tokens_expire_at: &str) -> FakeGithub {
+        self.app_token_expires_at = Some(tokens_expire_at.to_owned());
+        self
+    }
+
+    pub fn with_token_scopes(mut self, scopes: &str) -> FakeGithub {
+        self.token_scopes = Some(scopes.to_owned());
+        self
+    }
+
+    /// Serve the org as a GitLab group instead: repos named `subgroup/name` are in a subgroup.
+    pub fn on_gitlab(mut self) -> FakeGithub {
+        self.forge = "gitlab";
+        self
+    }
+
+    /// Serve the org the way Gitea does: GitHub's routes, but paged with `page`/`limit` and a different compare.
+    pub fn on_gitea(mut self) -> FakeGithub {
+        self.forge = "gitea";
+        self
+    }
+
+    /// Serve the org as a Bitbucket Server project, the org name being the project key.
+    pub fn on_bitbucket(mut self) -> FakeGithub {
+        self.forge = "bitbucket";
+        self
+    }
+
+    /// Tag OK GET responses with an ETag and answer a matching If-None-Match with a bare 304, like GitHub.
+    pub fn with_etags(mut self) -> FakeGithub {
+        self.etags = true;
+        self
+    }
+
+    /// The next `request`, like "POST /repos/org/name/pulls", gets `status` instead of an answer. A 403 or 429
+    /// says to retry after a second, like GitHub's secondary rate limits.
+    pub fn failing_once(mut self, request: &str, status: u16) -> FakeGithub {
+        self.failures.push(Failure {
+            request: request.to_owned(),
+            status,
+            handled: false,
+        });
+        self
+    }
+
+    /// The next `request` is carried out, but answered with a 502 as if the response got lost.
+    pub fn losing_response_once(mut self, request: &str) -> FakeGithub {
+        self.failures.push(Failure {
+            request: request.to_owned(),
+            status: 502,
+            handled: true,
+        });
+        self
+    }
+
+    pub fn with_repos_failing(mut self, status: u16) -> FakeGithub {
+        self.repos_status = Some(status);
+        self
+    }
+
+    pub fn start(self) -> FakeServer {
+        let state = Arc::new(Mutex::new(self));
+        let service_state = state.clone();
+        let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let server = Server::bind(&addr).serve(move || {
+            let state = service_state.clone();
+            service_fn(move |req: Request<Body>| {
+                let state = state.clone();
+                let method = req.method().clone();
+                let authorization = req
+                    .headers()
+                    .get("Authorization")
+                    .and_then(|a| a.to_str().ok())
+                    .unwrap_or("")
+                    .to_owned();
+                let if_none_match = req
+                    .headers()
+                    .get("If-None-Match")
+                    .and_then(|a| a.to_str().ok())
+                    .map(|a| a.to_owned());
+                let proxied = req.uri().scheme_str().is_some();
+                let path = req.uri().path().to_owned();
+                let query = req.uri().query().unwrap_or("").to_owned();
+                req.into_body().concat2().map(move |body| {
+                    let body = String::from_utf8_lossy(&body).to_string();
+                    let mut github = state.lock().unwrap();
+                    github.authorizations.push(authorization.clone());
+                    if proxied {
+                        github.proxied += 1;
+                    }
+                    let request = format!("{} {}", method, path);
+                    let failure = github
+                        .failures
+                        .iter()
+                        .position(|f| f.request == request)
+                        .map(|index| github.failures.remove(index));
+                    match failure {
+                        Some(ref failure) if !failure.handled => {
+                            github.requests.push(request);
+                            github.failure(failure.status)
+                        }
+                        Some(failure) => {
+                            github.handle(&method, &path, &query, &body, &authorization);
+                            github.failure(failure.status)
+                        }
+                        None => {
+                            let response = github.handle(&method, &path, &query, &body, &authorization);
+                            github.not_modified_if_unchanged(response, if_none_match)
+                        }
+                    }
+                })
+            })
+        });
+        let local_addr = server.local_addr();
+        state.lock().unwrap().addr = Some(local_addr);
+        thread::spawn(move || {
+            rt::run(server.map_err(|e| eprintln!("fake github server error: {}", e)));
+        });
+        FakeServer {
+            addr: local_addr,
+            state,
+        }
+    }
+
+    fn base_url(&self) -> String {
+        format!("http://{}", self.addr.expect("server should be started"))
+    }
+
+    fn handle(
+        &mut self,
+        method: &Method,
+        path: &str,
+        query: &str,
+        body: &str,
+        authorization: &str,
+    ) -> Response<Body> {
+        self.requests.push(format!("{} {}", method, path));
+        let segments = path
+            .trim_matches('/')
+            .split('/')
+            .map(|s| s.to_owned())
+            .collect::<Vec<String>>();
+        let segments = segments.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
+        let params = parse_query(query);
+        if *method == Method::POST && segments.first() == Some(&"hooks") {
+            self.webhooks.push(body.to_owned());
+            return self.respond(StatusCode::OK, json!({"ok": true}));
+        }
+        if *method == Method::POST && segments == ["graphql"] {
+            return self.handle_graphql(body);
+        }
+        if segments.first() == Some(&"jira") {
+            return self.handle_jira(method, &segments[1..], body);
+        }
+        if self.forge == "gitlab" {
+            return self.handle_gitlab(method, &segments, &params, body);
+        }
+        if self.forge == "bitbucket" {
+            return self.handle_bitbucket(method, &segments, &params, body);
+        }
+        if self.forge == "gitea" {
+            if let Some(response) = self.handle_gitea(method, &segments, &params) {
+                return response;
+            }
+        }
+
+        match (method, segments.as_slice()) {
+            (&Method::GET, ["orgs", org, "repos"]) if *org == self.org => self.list_repos(&params),
+            (&Method::GET, ["user"]) if authorization.starts_with("token ") => {
+                self.respond(StatusCode::OK, json!({"login": "party-bot"}))
+            }
+            (&Method::GET, ["orgs", org, "installation"])
+                if *org == self.org && self.app_token_expires_at.is_some() && authorization.starts_with("Bearer ") =>
+            {
+                self.respond(StatusCode::OK, json!({"id": 42}))
+            }
+            (&Method::POST, ["app", "installations", "42", "access_tokens"]) if authorization.starts_with("Bearer ") => {
+                self.installation_tokens_issued += 1;
+                let token = json!({
+                    "token": format!("installation-token-{}", self.installation_tokens_issued),
+                    "expires_at": self.app_token_expires_at.clone()
+                });
+                self.respond(StatusCode::CREATED, token)
+            }
+            (_, ["repos", _, name, rest @ ..]) => {
+                let name = name.to_string();
+                let rest = rest.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+                self.handle_repo(method, &name, &rest, &params, body)
+            }
+            _ => self.respond(StatusCode::NOT_FOUND, json!({"message": "Not Found"})),
+        }
+    }
+
+    fn handle_repo(
+        &mut self,
+        method: &Method,
+        name: &str,
+        rest: &[String],
+        params: &[(String, String)],
+        body: &str,
+    ) -> Response<Body> {
+        let base_url = self.base_url();
+        let org = self.org.clone();
+        let repo_index = match self.repos.iter().position(|r| r.name == name) {
+            Some(index) => index,
+            None => return self.respond(StatusCode::NOT_FOUND, json!({"message": "Not Found"})),
+        };
+        let rest = rest.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
+        if *method == Method::GET && rest == ["tags"] {
+            return self.list_tags(repo_index, params);
+        }
+        let (status, response) = {
+            let repo = &mut self.repos[repo_index];
+            match (method, rest.as_slice()) {
+                (&Method::GET, ["compare", range]) => compare(repo, range),
+                (&Method::GET, ["branches", "release"]) if !repo.has_release_branch => {
+                    (StatusCode::NOT_FOUND, json!({"message": "Branch not found"}))
+                }
+                (&Method::GET, ["branches", "release", "protection"]) if !repo.has_release_branch => {
+                    (StatusCode::NOT_FOUND, json!({"message": "Branch not found"}))
+                }
+                (&Method::GET, ["branches", _, "protection"]) => {
+                    (StatusCode::NOT_FOUND, json!({"message": "Branch not protected"}))
+                }
+                (&Method::PUT, ["branches", _, "protection"]) => {
+                    (StatusCode::OK, serde_json::from_str(body).unwrap_or(Value::Null))
+                }
+                (&Method::GET, ["branches", branch]) => {
+                    (StatusCode::OK, json!({"name": branch, "commit": {"sha": "1111111"}}))
+                }
+                (&Method::GET, ["commits", "release"]) if !repo.has_release_branch => {
+                    (StatusCode::NOT_FOUND, json!({"message": "No commit found for SHA: release"}))
+                }
+                // A branch's head changes whenever a commit lands on it.
+                (&Method::GET, ["commits", "master"]) => (
+                    StatusCode::OK,
+                    json!({"sha": format!("master-{}", repo.master_commits.len())}),
+                ),
+                (&Method::GET, ["commits", "release"]) => (
+                    StatusCode::OK,
+                    json!({"sha": format!("release-{}", repo.release_commits.len())}),
+                ),
+                (&Method::GET, ["pulls"]) => {
+                    let head = param(params, "head");
+                    let base = param(params, "base");
+                    let pulls = repo
+                        .pulls
+                        .iter()
+                        .filter(|p| {
+                            (head.is_none() || head == Some(p.head.as_str()))
+                                && (base.is_none() || base == Some(p.base.as_str()))
+                        })
+                        .map(|p| pull_json(&base_url, &org, &repo.name, p))
+                        .collect();
+                    (StatusCode::OK, Value::Array(pulls))
+                }
+                (&Method::POST, ["pulls"]) => {
+                    let request: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+                    let head = request["head"].as_str().unwrap_or("").to_owned();
+                    let base = request["base"].as_str().unwrap_or("").to_owned();
+                    if repo.pulls.iter().any(|p| p.head == head && p.base == base) {
+                        (
+                            StatusCode::UNPROCESSABLE_ENTITY,
+                            json!({"message": "A pull request already exists"}),
+                        )
+                    } else {
+                        let pull = FakePull {
+                            number: self.next_pr_number,
+                            head,
+                            base,
+                            title: request["title"].as_str().unwrap_or("").to_owned(),
+                            body: None,
+                        };
+                        self.next_pr_number += 1;
+                        let response = pull_json(&base_url, &org, &repo.name, &pull);
+                        repo.pulls.push(pull);
+                        (StatusCode::CREATED, response)
+                    }
+                }
+                (&Method::GET, ["pulls", number]) => {
+                    match repo.pulls.iter().find(|p| p.number.to_string() == *number) {
+                        Some(pull) => (StatusCode::OK, pull_json(&base_url, &org, &repo.name, pull)),
+                        None => (StatusCode::NOT_FOUND, json!({"message": "Not Found"})),
+                    }
+                }
+                (&Method::PATCH, ["pulls", number]) => {
+                    let request: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+                    match repo.pulls.iter_mut().find(|p| p.number.to_string() == *number) {
+                        Some(pull) => {
+                            pull.body = request["body"].as_str().map(|b| b.to_owned());
+                            (StatusCode::OK, pull_json(&base_url, &org, &repo.name, pull))
+                        }
+                        None => (StatusCode::NOT_FOUND, json!({"message": "Not Found"})),
+                    }
+                }
+                (&Method::GET, ["pulls", number, "commits"]) if repo.pulls.iter().any(|p| p.number.to_string() == *number) => {
+                    let commits = repo
+                        .master_commits
+                        .iter()
+                        .enumerate()
+                        .map(|(i, message)| commit_json(i, message))
+                        .collect();
+                    (StatusCode::OK, Value::Array(commits))
+                }
+                (&Method::GET, ["issues", number, "comments"]) => (
+                    StatusCode::OK,
+                    Value::Array(
+                        repo.comments
+                            .iter()
+                            .filter(|c| c.0 == *number)
+                            .map(|c| json!({"id": 1, "body": c.1}))
+                            .collect(),
+                    ),
+                ),
+                (&Method::POST, ["issues", number, "comments"]) => {
+                    let request: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+                    let comment = request["body"].as_str().unwrap_or("").to_owned();
+                    repo.comments.push((number.to_string(), comment.clone()));
+                    (StatusCode::CREATED, json!({"id": 1, "body": comment}))
+                }
+                (&Method::GET, ["issues", number, "labels"]) => (
+                    StatusCode::OK,
+                    Value::Array(
+                        repo.labels
+                            .iter()
+                            .filter(|l| l.0 == *number)
+                            .map(|l| json!({"id": 1, "name": l.1}))
+                            .collect(),
+                    ),
+                ),
+                (&Method::POST, ["issues", number, "labels"]) => {
+                    let request: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+                    for label in request["labels"].as_array().cloned().unwrap_or_default() {
+                        repo.labels
+                            .push((number.to_string(), label.as_str().unwrap_or("").to_owned()));
+                    }
+                    (StatusCode::OK, json!([]))
+                }
+                _ => (StatusCode::NOT_FOUND, json!({"message": "Not Found"})),
+            }
+        };
+        self.respond(status, response)
+    }
+
+    fn list_repos(&mut self, params: &[(String, String)]) -> Response<Body> {
+        if let Some(status) = self.repos_status {
+            let status = StatusCode::from_u16(status).unwrap();
+            return self.respond(status, json!({"message": "Server Error"}));
+        }
+        let page = param(params, "page")
+            .and_then(|p| p.parse::<usize>().ok())
+            .unwrap_or(1);
+        let base_url = self.base_url();
+        let repos = self
+            .repos
+            .iter()
+            .skip((page - 1) * self.page_size)
+            .take(self.page_size)
+            .enumerate()
+            .map(|(i, r)| {
+                json!({
+                    "id": i,
+                    "name": r.name,
+                    "url": format!("{}/repos/{}/{}", base_url, self.org, r.name),
+                    "permissions": {"admin": false, "push": r.push, "pull": true}
+                })
+            })
+            .collect();
+        let mut response = self.respond(StatusCode::OK, Value::Array(repos));
+        if page * self.page_size < self.repos.len() {
+            let next = format!(
+                "<{}/orgs/{}/repos?per_page=50&page={}>; rel=\"next\"",
+                base_url,
+                self.org,
+                page + 1
+            );
+            response
+                .headers_mut()
+                .insert("Link", next.parse().unwrap());
+        }
+        response
+    }
+
+    // Tags a page at a time like GitHub, `per_page` (30 by default) to a page.
+    fn list_tags(&mut self, repo_index: usize, params: &[(String, String)]) -> Response<Body> {
+        let page = param(params, "page")
+            .and_then(|p| p.parse::<usize>().ok())
+            .unwrap_or(1);
+        let per_page = param(params, "per_page")
+            .and_then(|p| p.parse::<usize>().ok())
+            .unwrap_or(30);
+        let repo = &self.repos[repo_index];
+        let tags = repo
+            .tags
+            .iter()
+            .skip((page - 1) * per_page)
+            .take(per_page)
+            .map(|t| json!({"name": t, "commit": {"sha": format!("sha-{}", t)}}))
+            .collect();
+        let next = if page * per_page < repo.tags.len() {
+            Some(format!(
+                "<{}/repos/{}/{}/tags?per_page={}&page={}>; rel=\"next\"",
+                self.base_url(),
+                self.org,
+                repo.name,
+                per_page,
+                page + 1
+            ))
+        } else {
+            None
+        };
+        let mut response = self.respond(StatusCode::OK, Value::Array(tags));
+        if let Some(next) = next {
+            response.headers_mut().insert("Link", next.parse().unwrap());
+        }
+        response
+    }
+
+    // A 429 is a secondary rate limit saying when to come back, a 403 is the requests running out.
+    fn failure(&self, status: u16) -> Response<Body> {
+        let mut response = self.respond(StatusCode::from_u16(status).unwrap(), json!({"message": "Try again"}));
+        let headers = response.headers_mut();
+        match status {
+            429 => {
+                headers.insert("Retry-After", "1".parse().unwrap());
+            }
+            403 => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+                headers.insert("X-RateLimit-Remaining", "0".parse().unwrap());
+                headers.insert("X-RateLimit-Reset", now.to_string().parse().unwrap());
+            }
+            _ => (),
+        }
+        response
+    }
+
+    // A response whose ETag the client already has goes back as a 304 without a body or Link header.
+    fn not_modified_if_unchanged(&mut self, response: Response<Body>, if_none_match: Option<String>) -> Response<Body> {
+        let etag = response.headers().get("ETag").and_then(|e| e.to_str().ok()).map(|e| e.to_owned());
+        if etag.is_none() || etag != if_none_match {
+            return response;
+        }
+        self.not_modified += 1;
+        let (mut parts, _) = response.into_parts();
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove("Link");
+        parts.headers.remove("Content-Type");
+        Response::from_parts(parts, Body::empty())
+    }
+
+    fn next_pr_number(&mut self) -> u32 {
+        let number = self.next_pr_number;
+        self.next_pr_number += 1;
+        number
+    }
+
+    fn respond(&self, status: StatusCode, body: Value) -> Response<Body> {
+        let mut response = Response::builder();
+        response
+            .status(status)
+            .header("Content-Type", "application/json")
+            .header("X-RateLimit-Remaining", self.rate_limit_remaining.to_string().as_str());
+        if let Some(ref scopes) = self.token_scopes {
+            response.header("X-OAuth-Scopes", scopes.as_str());
+        }
+        let body = body.to_string();
+        if self.etags && status == StatusCode::OK {
+            response.header("ETag", format!("\"{}\"", etag_for(&body)).as_str());
+        }
+        response.body(Body::from(body)).unwrap()
+    }
+}
+
+fn etag_for(body: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn compare(repo: &FakeRepo, range: &str) -> (StatusCode, Value) {
+    if let Some(status) = repo.compare_status {
+        return (
+            StatusCode::from_u16(status).unwrap(),
+            json!({"message": "Server Error"}),
+        );
+    }
+    if !repo.has_release_branch {
+        return (StatusCode::NOT_FOUND, json!({"message": "Not Found"}));
+    }
+    // Only release...master is asked for: master's extra commits are "ahead", release's are "behind".
+    let (ahead, behind) = if range == "release...master" {
+        (&repo.master_commits, &repo.release_commits)
+    } else {
+        (&repo.release_commits, &repo.master_commits)
+    };
+    let status = match (ahead.is_empty(), behind.is_empty()) {
+        (true, true) => "identical",
+        (false, true) => "ahead",
+        (true, false) => "behind",
+        (false, false) => "diverged",
+    };
+    let commits: Vec<Value> = ahead
+        .iter()
+        .enumerate()
+        .map(|(i, message)| commit_json(i, message))
+        .collect();
+    let files: Vec<Value> = ahead
+        .iter()
+        .enumerate()
+        .map(|(i, _)| {
+            json!({"filename": format!("src/file{}.rs", i), "status": "modified", "additions": 1, "deletions": 0})
+        })
+        .collect();
+    (
+        StatusCode::OK,
+        json!({
+            "status": status,
+            "ahead_by": ahead.len(),
+            "behind_by": behind.len(),
+            "total_commits": ahead.len(),
+            "commits": commits,
+            "files": files
+        }),
+    )
+}
+
+fn commit_json(index: usize, message: &str) -> Value {
+    json!({"sha": format!("{:07}", index + 1), "commit": {"message": message}})
+}
+
+fn pull_json(base_url: &str, org: &str, repo: &str, pull: &FakePull) -> Value {
+    json!({
+        "id": pull.number,
+        "number": pull.number,
+        "title": pull.title,
+        "body": pull.body,
+        "url": format!("{}/repos/{}/{}/pulls/{}", base_url, org, repo, pull.number),
+        "html_url": format!("https://github.com/{}/{}/pull/{}", org, repo, pull.number),
+        "head": {"sha": "2222222", "ref": pull.head},
+        "base": {"sha": "1111111", "ref": pull.base}
+    })
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next().unwrap_or("").to_owned();
+            let value = parts.next().unwrap_or("").to_owned();
+            (key, value)
+        })
+        .collect()
+}
+
+fn param<'a>(params: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    params
+        .iter()
+        .find(|pair| pair.0 == key)
+        .map(|pair| pair.1.as_str())
+}
+
+pub struct FakeServer {
+    pub addr: SocketAddr,
+    pub state: Arc<Mutex<FakeGithub>>,
+}
+
+impl FakeServer {
+    pub fn api_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn pulls_for(&self, repo: &str) -> Vec<FakePull> {
+        let github = self.state.lock().unwrap();
+        match github.repos.iter().find(|r| r.name == repo) {
+            Some(repo) => repo.pulls.clone(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn repo(&self, repo: &str) -> FakeRepo {
+        let github = self.state.lock().unwrap();
+        github
+            .repos
+            .iter()
+            .find(|r| r.name == repo)
+            .cloned()
+            .expect("repo should be on the fake server")
+    }
+
+    pub fn proxied(&self) -> u32 {
+        self.state.lock().unwrap().proxied
+    }
+
+    pub fn not_modified(&self) -> u32 {
+        self.state.lock().unwrap().not_modified
+    }
+
+    pub fn webhooks(&self) -> Vec<String> {
+        self.state.lock().unwrap().webhooks.clone()
+    }
+
+    pub fn authorizations(&self) -> Vec<String> {
+        self.state.lock().unwrap().authorizations.clone()
+    }
+
+    pub fn push_to_master(&self, repo: &str, message: &str) {
+        let mut github = self.state.lock().unwrap();
+        let repo = github
+            .repos
+            .iter_mut()
+            .find(|r| r.name == repo)
+            .expect("repo should be on the fake server");
+        repo.master_commits.push(message.to_owned());
+    }
+
+    /// Where `run_party` runs the binary, so files it writes can be read back.
+    pub fn workdir(&self) -> PathBuf {
+        scratch_dir(&format!("{}", self.addr.port()))
+    }
+
+    /// Runs release-party-br against this server from an empty directory, so no ignoredrepos.toml is picked up.
+    pub fn run_party(&self, args: &[&str]) -> PartyRun {
+        let (org, forge) = {
+            let github = self.state.lock().unwrap();
+            (github.org.clone(), github.forge)
+        };
+        let workdir = self.workdir();
+        let output = Command::new(env!("CARGO_BIN_EXE_release-party-br"))
+            .arg("--org")
+            .arg(&org)
+            .arg("--forge")
+            .arg(forge)
+            .arg("--api-url")
+            .arg(self.api_url())
+            .args(args)
+            .current_dir(&workdir)
+            .env("RP_GITHUBTOKEN", "fake-token")
+            .env("GITLAB_TOKEN", "fake-gitlab-token")
+            .env("GITEA_TOKEN", "fake-gitea-token")
+            .env("BITBUCKET_TOKEN", "fake-bitbucket-token")
+            .env("RP_JIRATOKEN", "fake-jira-token")
+            .env("USER", "test-user")
+            .env("HOME", &workdir)
+            .env("XDG_CACHE_HOME", workdir.join(".cache"))
+            .output()
+            .expect("release-party-br should run");
+        PartyRun::from(output)
+    }
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("release-party-test-{}", name));
+    fs::create_dir_all(&dir).expect("should create scratch dir");
+    dir
+}
+
+pub struct PartyRun {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl From<Output> for PartyRun {
+    fn from(output: Output) -> PartyRun {
+        PartyRun {
+            success: output.status.success(),
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        }
+    }
+}
+
+impl PartyRun {
+    /// What a `--json` run printed, which has to be nothing but the JSON document.
+    pub fn json(&self) -> Value {
+        serde_json::from_str(&self.stdout).unwrap_or_else(|e| panic!("stdout isn't JSON ({}): {}", e, self.stdout))
+    }
+
+    /// The release PR links in a `--json` party run's report.
+    pub fn release_pr_links(&self) -> Vec<String> {
+        self.json()["release_prs"]
+            .as_array()
+            .expect("release_prs should be a list")
+            .iter()
+            .map(|link| link["pr_link"].as_str().unwrap_or("").to_owned())
+            .collect()
+    }
+}
@@ -0,0 +1,64 @@
+// A local SMTP sink: accepts any mail without TLS or auth and keeps each message's DATA.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub struct SmtpSink {
+    pub port: u16,
+    messages: Arc<Mutex<Vec<String>>>,
+}
+
+impl SmtpSink {
+    pub fn start() -> SmtpSink {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("should bind the SMTP sink");
+        let port = listener.local_addr().unwrap().port();
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let received = messages.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let received = received.clone();
+                thread::spawn(move || converse(stream, &received));
+            }
+        });
+        SmtpSink { port, messages }
+    }
+
+    pub fn messages(&self) -> Vec<String> {
+        self.messages.lock().unwrap().clone()
+    }
+}
+
+fn converse(stream: TcpStream, received: &Mutex<Vec<String>>) {
+    let mut writer = stream.try_clone().unwrap();
+    let mut reader = BufReader::new(stream);
+    let _ = writer.write_all(b"220 sink ESMTP\r\n");
+    let mut line = String::new();
+    while reader.read_line(&mut line).map(|n| n > 0).unwrap_or(false) {
+        let command = line.trim_end().to_uppercase();
+        let reply: &[u8] = if command.starts_with("EHLO") || command.starts_with("HELO") {
+            b"250 sink\r\n"
+        } else if command == "DATA" {
+            let _ = writer.write_all(b"354 end with .\r\n");
+            let mut data = String::new();
+            let mut data_line = String::new();
+            while reader.read_line(&mut data_line).map(|n| n > 0).unwrap_or(false) {
+                if data_line == ".\r\n" {
+                    break;
+                }
+                data.push_str(&data_line);
+                data_line.clear();
+            }
+            received.lock().unwrap().push(data);
+            b"250 queued\r\n"
+        } else if command == "QUIT" {
+            let _ = writer.write_all(b"221 bye\r\n");
+            return;
+        } else {
+            b"250 OK\r\n"
+        };
+        let _ = writer.write_all(reply);
+        line.clear();
+    }
+}